@@ -0,0 +1,84 @@
+use crate::JoshResult;
+use std::sync::Arc;
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// A named persistent map of the cache, like the commit map of a filter
+pub trait Tree: Send + Sync {
+    fn get(&self, key: &[u8]) -> JoshResult<Option<Vec<u8>>>;
+    fn insert(&self, key: &[u8], value: &[u8]) -> JoshResult<()>;
+    fn remove(&self, key: &[u8]) -> JoshResult<()>;
+    fn len(&self) -> JoshResult<usize>;
+    /// All entries, ordered by key
+    fn iter(&self) -> Box<dyn Iterator<Item = JoshResult<Entry>> + '_>;
+
+    fn is_empty(&self) -> JoshResult<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+/// Storage of the persistent part of the cache
+pub trait Backend: Send + Sync {
+    fn open_tree(&self, name: &str) -> JoshResult<Arc<dyn Tree>>;
+    fn drop_tree(&self, name: &str) -> JoshResult<()>;
+    fn tree_names(&self) -> JoshResult<Vec<String>>;
+    /// Make sure everything written so far survives a crash
+    fn flush(&self) -> JoshResult<()>;
+    fn size_on_disk(&self) -> JoshResult<u64>;
+}
+
+/// The available implementations of `Backend`
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Sled,
+    Sqlite,
+    Memory,
+}
+
+impl BackendKind {
+    /// Directory below the versioned cache directory the backend keeps its files in
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            BackendKind::Sled => "sled",
+            BackendKind::Sqlite => "sqlite",
+            BackendKind::Memory => "memory",
+        }
+    }
+
+    pub(crate) fn open(&self, path: &std::path::Path) -> JoshResult<Arc<dyn Backend>> {
+        Ok(match self {
+            BackendKind::Sled => Arc::new(
+                ::sled::Config::default()
+                    .path(path)
+                    .flush_every_ms(Some(200))
+                    .open()?,
+            ),
+            BackendKind::Sqlite => Arc::new(super::sqlite::SqliteBackend::open(path)?),
+            BackendKind::Memory => Arc::new(super::memory::MemoryBackend::default()),
+        })
+    }
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(BackendKind::Sled),
+            "sqlite" => Ok(BackendKind::Sqlite),
+            "memory" => Ok(BackendKind::Memory),
+            _ => Err(format!(
+                "unknown cache backend {:?}, expected one of sled, sqlite, memory",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.dir_name())
+    }
+}
@@ -0,0 +1,267 @@
+use super::backend::Backend;
+use super::gc;
+use crate::{JoshResult, filter, from_ns, josh_error, regex_parsed, to_ns};
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+const HEADER: &str = "josh-cache-bundle 1";
+
+regex_parsed!(
+    FilteredRefRegex,
+    r"^refs/josh/filtered/(?P<upstream_repo>[^/]*[.]git)/(?P<filter_spec>[^/]*)/.*",
+    [upstream_repo, filter_spec]
+);
+
+/// Which part of the cache to export. Empty lists select everything.
+#[derive(Clone, Debug, Default)]
+pub struct BundleSelection {
+    pub upstream_repos: Vec<String>,
+    pub filters: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BundledFilter {
+    pub spec: String,
+    pub entries: usize,
+    /// Entries not imported because their commits are missing in the target repo
+    pub skipped: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BundleReport {
+    pub filters: Vec<BundledFilter>,
+    pub refs: usize,
+    pub skipped_refs: usize,
+    /// Objects written to the bundle, only counted on export
+    pub objects: usize,
+}
+
+fn normalize(spec: &str) -> Option<String> {
+    filter::parse(spec).ok().map(filter::spec)
+}
+
+fn oid_from_bytes(bytes: &[u8]) -> JoshResult<git2::Oid> {
+    Ok(git2::Oid::from_bytes(bytes)?)
+}
+
+/// Write the cached commit maps of the selected filters together with their
+/// `refs/josh/filtered` refs and the filtered objects into `out`.
+/// The upstream objects are not included, they have to be fetched separately.
+pub(super) fn export(
+    db: &dyn Backend,
+    repo: &git2::Repository,
+    selection: &BundleSelection,
+    out: &mut dyn Write,
+) -> JoshResult<BundleReport> {
+    let filters = selection
+        .filters
+        .iter()
+        .map(|spec| normalize(spec).ok_or_else(|| josh_error(&format!("invalid filter: {}", spec))))
+        .collect::<JoshResult<BTreeSet<_>>>()?;
+    let filter_selected = |spec: &str| filters.is_empty() || filters.contains(spec);
+
+    let mut refs = vec![];
+    let mut specs = BTreeSet::new();
+    for reference in repo.references_glob("refs/josh/filtered/*")? {
+        let reference = reference?;
+        let name = some_or!(reference.name(), { continue }).to_string();
+        let filtered = some_or!(FilteredRefRegex::from_str(&name), { continue });
+        let spec = some_or!(normalize(&from_ns(&filtered.filter_spec)), { continue });
+
+        let repo_selected = selection.upstream_repos.is_empty()
+            || selection
+                .upstream_repos
+                .iter()
+                .any(|r| to_ns(r) == filtered.upstream_repo);
+        if !repo_selected || !filter_selected(&spec) {
+            continue;
+        }
+        if let Some(target) = reference.target() {
+            refs.push((name, target));
+            specs.insert(spec);
+        }
+    }
+
+    let tree_names = db.tree_names()?;
+    if selection.upstream_repos.is_empty() {
+        specs.extend(
+            tree_names
+                .iter()
+                .filter(|name| !name.starts_with('_') && filter_selected(name))
+                .cloned(),
+        );
+    }
+
+    // Don't create trees for filters that were never cached
+    let specs = specs
+        .into_iter()
+        .filter(|spec| tree_names.contains(spec))
+        .collect::<Vec<_>>();
+
+    let mut walk = repo.revwalk()?;
+    for (_, target) in refs.iter() {
+        walk.push(*target)?;
+    }
+    for spec in specs.iter() {
+        for entry in db.open_tree(spec)?.iter() {
+            let to = oid_from_bytes(&entry?.1)?;
+            if !to.is_zero() && repo.find_commit(to).is_ok() {
+                walk.push(to)?;
+            }
+        }
+    }
+
+    let mut report = BundleReport::default();
+    let mut builder = repo.packbuilder()?;
+    builder.insert_walk(&mut walk)?;
+    report.objects = builder.object_count();
+
+    // The pack comes first, so importing can check the entries while reading them.
+    // It is written in chunks prefixed with their length, ending with an empty one.
+    writeln!(out, "{}", HEADER)?;
+    writeln!(out, "pack")?;
+    if report.objects > 0 {
+        let mut result = Ok(());
+        builder.foreach(|chunk| {
+            result = writeln!(out, "{}", chunk.len()).and_then(|_| out.write_all(chunk));
+            result.is_ok()
+        })?;
+        result?;
+    }
+    writeln!(out, "0")?;
+
+    for (name, target) in refs.iter() {
+        writeln!(out, "ref {} {}", target, name)?;
+        report.refs += 1;
+    }
+
+    for spec in specs.iter() {
+        writeln!(out, "filter {}", spec)?;
+        let mut entries = 0;
+        for entry in db.open_tree(spec)?.iter() {
+            let (from, to) = entry?;
+            writeln!(out, "{} {}", oid_from_bytes(&from)?, oid_from_bytes(&to)?)?;
+            entries += 1;
+        }
+        report.filters.push(BundledFilter {
+            spec: spec.clone(),
+            entries,
+            skipped: 0,
+        });
+    }
+    writeln!(out, "end")?;
+    out.flush()?;
+
+    Ok(report)
+}
+
+/// Read a line without its newline into `line`, returns false at the end of `input`
+fn read_line(input: &mut dyn BufRead, line: &mut String) -> JoshResult<bool> {
+    line.clear();
+    if input.read_line(line)? == 0 {
+        return Ok(false);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(true)
+}
+
+/// Read a bundle written by `export`. Entries and refs are only imported when
+/// all commits they refer to exist in `repo` after adding the bundled objects.
+pub(super) fn import(
+    db: &dyn Backend,
+    repo: &git2::Repository,
+    input: &mut dyn BufRead,
+) -> JoshResult<BundleReport> {
+    let invalid = |line: &str| josh_error(&format!("invalid bundle line: {:?}", line));
+    let truncated = || josh_error("truncated bundle");
+
+    let mut line = String::new();
+    if !read_line(input, &mut line)? || line != HEADER {
+        return Err(josh_error("not a josh cache bundle"));
+    }
+    if !read_line(input, &mut line)? {
+        return Err(truncated());
+    }
+    if line != "pack" {
+        return Err(invalid(&line));
+    }
+
+    let odb = repo.odb()?;
+    let mut report = BundleReport::default();
+    let mut writer = odb.packwriter()?;
+    let mut written = 0;
+    loop {
+        if !read_line(input, &mut line)? {
+            return Err(truncated());
+        }
+        let len: u64 = line.parse().map_err(|_| invalid(&line))?;
+        if len == 0 {
+            break;
+        }
+        let mut chunk = <&mut dyn BufRead as std::io::Read>::take(&mut *input, len);
+        if std::io::copy(&mut chunk, &mut writer)? < len {
+            return Err(truncated());
+        }
+        written += len;
+    }
+    if written > 0 {
+        writer.commit()?;
+        odb.refresh()?;
+    }
+
+    let exists = |oid: git2::Oid| odb.exists(oid);
+    let mut tree = None;
+
+    loop {
+        if !read_line(input, &mut line)? {
+            return Err(truncated());
+        }
+        let l = line.as_str();
+        if l == "end" {
+            break;
+        }
+        if let Some(r) = l.strip_prefix("ref ") {
+            let (target, name) = r.split_once(' ').ok_or_else(|| invalid(l))?;
+            let target = git2::Oid::from_str(target)?;
+            // The bundle must not overwrite anything but the refs of filtered repos
+            if FilteredRefRegex::from_str(name).is_none() {
+                return Err(josh_error(&format!("invalid ref in bundle: {:?}", name)));
+            }
+            if exists(target) {
+                repo.reference(name, target, true, "josh cache import")?;
+                report.refs += 1;
+            } else {
+                report.skipped_refs += 1;
+            }
+        } else if let Some(spec) = l.strip_prefix("filter ") {
+            let spec = normalize(spec).ok_or_else(|| invalid(l))?;
+            gc::touch(db, &spec)?;
+            tree = Some(db.open_tree(&spec)?);
+            report.filters.push(BundledFilter {
+                spec,
+                ..Default::default()
+            });
+        } else {
+            let (from, to) = l.split_once(' ').ok_or_else(|| invalid(l))?;
+            let (from, to) = (git2::Oid::from_str(from)?, git2::Oid::from_str(to)?);
+            let (tree, filter) = match (&tree, report.filters.last_mut()) {
+                (Some(tree), Some(filter)) => (tree, filter),
+                _ => return Err(invalid(l)),
+            };
+            if exists(from) && (to.is_zero() || exists(to)) {
+                tree.insert(from.as_bytes(), to.as_bytes())?;
+                filter.entries += 1;
+            } else {
+                filter.skipped += 1;
+            }
+        }
+    }
+    for filter in report.filters.iter() {
+        gc::add_entries(db, &filter.spec, filter.entries as u64)?;
+    }
+    db.flush()?;
+
+    Ok(report)
+}
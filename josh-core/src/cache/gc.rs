@@ -0,0 +1,238 @@
+use super::backend::{Backend, Tree};
+use crate::{JoshResult, josh_error};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// Last access of each filter tree, as seconds since the epoch
+pub(super) const ACCESS_TREE: &str = "_access";
+
+// Number of entries of each filter tree, counting them is too slow with sled
+pub(super) const ENTRIES_TREE: &str = "_entries";
+
+// Commit maps store a 20 byte oid for a 20 byte oid
+const ENTRY_SIZE: u64 = 40;
+
+/// Which filter trees to drop. Trees not used for `max_age` are dropped, then the least
+/// recently used ones until the remaining trees fit into `max_size` bytes.
+#[derive(Clone, Debug, Default)]
+pub struct GcOptions {
+    pub max_age: Option<Duration>,
+    pub max_size: Option<u64>,
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct DroppedTree {
+    /// Spec of the filter the tree belongs to
+    pub name: String,
+    pub entries: usize,
+    pub last_access: SystemTime,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GcReport {
+    pub dropped: Vec<DroppedTree>,
+    pub kept: usize,
+}
+
+impl GcReport {
+    /// Estimated size of the dropped trees
+    pub fn reclaimed(&self) -> u64 {
+        self.dropped.iter().map(|t| t.entries as u64).sum::<u64>() * ENTRY_SIZE
+    }
+}
+
+lazy_static! {
+    // Filter trees with open `FilterTree` handles, they are not dropped by the GC
+    static ref IN_USE: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+}
+
+/// Handle of a filter tree used by a transaction. The GC doesn't drop the tree while
+/// the handle exists, and the entries inserted through it are added to the stored count
+/// of the tree when it is dropped.
+pub(super) struct FilterTree {
+    db: Arc<dyn Backend>,
+    name: String,
+    tree: Arc<dyn Tree>,
+    inserted: u64,
+}
+
+impl FilterTree {
+    pub(super) fn open(db: Arc<dyn Backend>, name: String) -> JoshResult<FilterTree> {
+        let mut in_use = IN_USE.lock()?;
+        let tree = db.open_tree(&name)?;
+        *in_use.entry(name.clone()).or_default() += 1;
+        Ok(FilterTree {
+            db,
+            name,
+            tree,
+            inserted: 0,
+        })
+    }
+
+    pub(super) fn get(&self, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        self.tree.get(key)
+    }
+
+    pub(super) fn insert(&mut self, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        self.tree.insert(key, value)?;
+        self.inserted += 1;
+        Ok(())
+    }
+
+    pub(super) fn len(&self) -> JoshResult<usize> {
+        self.tree.len()
+    }
+}
+
+impl Drop for FilterTree {
+    fn drop(&mut self) {
+        if self.inserted > 0 {
+            if let Err(e) = add_entries(&*self.db, &self.name, self.inserted) {
+                tracing::warn!("failed to count the entries of {}: {}", self.name, e.0);
+            }
+        }
+        if let Ok(mut in_use) = IN_USE.lock() {
+            if let Some(count) = in_use.get_mut(&self.name) {
+                *count -= 1;
+                if *count == 0 {
+                    in_use.remove(&self.name);
+                }
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub(super) fn touch(db: &dyn Backend, name: &str) -> JoshResult<()> {
+    db.open_tree(ACCESS_TREE)?
+        .insert(name.as_bytes(), &now().to_be_bytes())
+}
+
+fn read_u64(db: &dyn Backend, tree: &str, name: &str) -> JoshResult<Option<u64>> {
+    Ok(db
+        .open_tree(tree)?
+        .get(name.as_bytes())?
+        .and_then(|v| Some(u64::from_be_bytes(v.try_into().ok()?))))
+}
+
+/// Add `n` to the stored number of entries of the tree `name`
+pub(super) fn add_entries(db: &dyn Backend, name: &str, n: u64) -> JoshResult<()> {
+    // Serializes the updates of the count
+    let _in_use = IN_USE.lock()?;
+    let entries = read_u64(db, ENTRIES_TREE, name)?.unwrap_or(0);
+    db.open_tree(ENTRIES_TREE)?
+        .insert(name.as_bytes(), &(entries + n).to_be_bytes())
+}
+
+/// The stored number of entries of the tree `name`. Trees created before the
+/// entries were counted are counted once.
+fn entries(db: &dyn Backend, name: &str, dry_run: bool) -> JoshResult<u64> {
+    let _in_use = IN_USE.lock()?;
+    if let Some(entries) = read_u64(db, ENTRIES_TREE, name)? {
+        return Ok(entries);
+    }
+    let entries = db.open_tree(name)?.len()? as u64;
+    if !dry_run {
+        db.open_tree(ENTRIES_TREE)?
+            .insert(name.as_bytes(), &entries.to_be_bytes())?;
+    }
+    Ok(entries)
+}
+
+pub(super) fn gc(db: &dyn Backend, options: &GcOptions) -> JoshResult<GcReport> {
+    let now = now();
+    let mut trees = vec![];
+
+    // Trees starting with "_" are shared by all filters
+    for name in db.tree_names()? {
+        if name.starts_with('_') {
+            continue;
+        }
+        let entries = entries(db, &name, options.dry_run)?;
+        let last = match read_u64(db, ACCESS_TREE, &name)? {
+            Some(last) => last,
+            None => {
+                // Created before access was tracked, start counting now
+                if !options.dry_run {
+                    touch(db, &name)?;
+                }
+                now
+            }
+        };
+        trees.push((last, name, entries));
+    }
+
+    // Oldest first
+    trees.sort();
+
+    let mut size = trees.iter().map(|t| t.2).sum::<u64>() * ENTRY_SIZE;
+    let mut report = GcReport::default();
+
+    for (last, name, entries) in trees {
+        let expired = options
+            .max_age
+            .is_some_and(|max_age| now.saturating_sub(last) >= max_age.as_secs());
+        let over_budget = options.max_size.is_some_and(|max_size| size > max_size);
+
+        // Transactions using the tree would lose their writes, it is collected
+        // in a later pass
+        let in_use = IN_USE.lock()?;
+        if (!expired && !over_budget) || in_use.contains_key(&name) {
+            report.kept += 1;
+            continue;
+        }
+
+        if !options.dry_run {
+            db.drop_tree(&name)?;
+            db.open_tree(ACCESS_TREE)?.remove(name.as_bytes())?;
+            db.open_tree(ENTRIES_TREE)?.remove(name.as_bytes())?;
+        }
+        size -= entries * ENTRY_SIZE;
+        report.dropped.push(DroppedTree {
+            name,
+            entries: entries as usize,
+            last_access: SystemTime::UNIX_EPOCH + Duration::from_secs(last),
+        });
+    }
+
+    if !options.dry_run {
+        db.flush()?;
+    }
+
+    Ok(report)
+}
+
+fn parse_number(s: &str, units: &[(char, u64)]) -> JoshResult<u64> {
+    let invalid = || josh_error(&format!("invalid value: {:?}", s));
+    let (number, factor) = match units.iter().find(|(unit, _)| s.ends_with(*unit)) {
+        Some((_, factor)) => (&s[..s.len() - 1], *factor),
+        None => (s, 1),
+    };
+    let number: u64 = number.trim().parse().map_err(|_| invalid())?;
+    number.checked_mul(factor).ok_or_else(invalid)
+}
+
+/// Parse a duration like "90d", "12h", "30m" or "10s"; plain numbers are seconds
+pub fn parse_age(s: &str) -> JoshResult<Duration> {
+    let units = [('s', 1), ('m', 60), ('h', 60 * 60), ('d', 24 * 60 * 60)];
+    Ok(Duration::from_secs(parse_number(s, &units)?))
+}
+
+/// Parse a size like "500M" or "2T"; plain numbers are bytes
+pub fn parse_size(s: &str) -> JoshResult<u64> {
+    let units = [
+        ('K', 1 << 10),
+        ('M', 1 << 20),
+        ('G', 1 << 30),
+        ('T', 1 << 40),
+    ];
+    parse_number(s, &units)
+}
@@ -0,0 +1,77 @@
+use super::backend::{Backend, Entry, Tree};
+use crate::JoshResult;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+/// Keeps the cache in memory only, for one-off runs and tests
+#[derive(Default)]
+pub struct MemoryBackend {
+    trees: RwLock<HashMap<String, Arc<MemoryTree>>>,
+}
+
+#[derive(Default)]
+pub struct MemoryTree {
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Tree for MemoryTree {
+    fn get(&self, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        Ok(self.entries.read()?.get(key).cloned())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        self.entries.write()?.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> JoshResult<()> {
+        self.entries.write()?.remove(key);
+        Ok(())
+    }
+
+    fn len(&self) -> JoshResult<usize> {
+        Ok(self.entries.read()?.len())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = JoshResult<Entry>> + '_> {
+        // Copy the entries, so the tree can be modified while iterating
+        match self.entries.read() {
+            Ok(entries) => Box::new(
+                entries
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), v.clone())))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            Err(e) => Box::new(std::iter::once(Err(e.into()))),
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn open_tree(&self, name: &str) -> JoshResult<Arc<dyn Tree>> {
+        Ok(self
+            .trees
+            .write()?
+            .entry(name.to_string())
+            .or_default()
+            .clone())
+    }
+
+    fn drop_tree(&self, name: &str) -> JoshResult<()> {
+        self.trees.write()?.remove(name);
+        Ok(())
+    }
+
+    fn tree_names(&self) -> JoshResult<Vec<String>> {
+        Ok(self.trees.read()?.keys().cloned().collect())
+    }
+
+    fn flush(&self) -> JoshResult<()> {
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> JoshResult<u64> {
+        Ok(0)
+    }
+}
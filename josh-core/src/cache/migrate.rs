@@ -0,0 +1,240 @@
+use super::CACHE_VERSION;
+use super::backend::{Backend, BackendKind, Entry};
+use crate::JoshResult;
+use std::path::{Path, PathBuf};
+
+/// Upgrade of the cache from version `from` to `from + 1`
+pub(super) struct Migration {
+    pub from: u64,
+    /// Trees that can't be kept, like those of filters whose `Op` semantics changed
+    pub invalidate: fn(&str) -> bool,
+    /// Rewrite an entry of a kept tree, `None` drops the entry
+    pub upgrade: fn(&str, Entry) -> Option<Entry>,
+}
+
+/// Add an entry here when bumping `CACHE_VERSION`. The cache of an older version is only
+/// used if there is a migration for every version in between, otherwise the new version
+/// starts with an empty cache.
+pub(super) const MIGRATIONS: &[Migration] = &[];
+
+/// `upgrade` of migrations that keep all entries as they are
+#[allow(dead_code)]
+pub(super) fn keep(_tree: &str, entry: Entry) -> Option<Entry> {
+    Some(entry)
+}
+
+/// Whether the tree of the filter `spec` uses one of `ops`, for `invalidate`
+#[allow(dead_code)]
+pub(super) fn uses_op(spec: &str, ops: &[&str]) -> bool {
+    spec.split(':').skip(1).any(|s| {
+        ops.iter().any(|op| {
+            s.strip_prefix(op)
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+        })
+    })
+}
+
+/// Cache versions found below `path`
+fn versions(path: &Path) -> JoshResult<Vec<(u64, PathBuf)>> {
+    let dir = path.join("josh");
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut versions = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some(version) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+            versions.push((version, entry.path()));
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+/// The migrations leading from `from` to `to`, if there is one for every version
+fn chain(migrations: &[Migration], from: u64, to: u64) -> Option<Vec<&Migration>> {
+    (from..to)
+        .map(|version| migrations.iter().find(|m| m.from == version))
+        .collect()
+}
+
+/// Copy the trees of `old` into `new`, applying `chain` in order.
+/// Returns the number of kept and dropped trees.
+pub(super) fn copy(
+    old: &dyn Backend,
+    new: &dyn Backend,
+    chain: &[&Migration],
+) -> JoshResult<(usize, usize)> {
+    let (mut kept, mut dropped) = (0, 0);
+
+    let names = old.tree_names()?;
+    tracing::info!("migrating {} trees of the cache", names.len());
+
+    for (i, name) in names.into_iter().enumerate() {
+        if i > 0 && i % 100 == 0 {
+            tracing::info!("migrated {} trees of the cache", i);
+        }
+        if chain.iter().any(|m| (m.invalidate)(&name)) {
+            dropped += 1;
+            continue;
+        }
+
+        let tree = new.open_tree(&name)?;
+        for entry in old.open_tree(&name)?.iter() {
+            let entry = chain
+                .iter()
+                .try_fold(entry?, |entry, m| (m.upgrade)(&name, entry));
+            if let Some((key, value)) = entry {
+                tree.insert(&key, &value)?;
+            }
+        }
+        kept += 1;
+    }
+    new.flush()?;
+
+    Ok((kept, dropped))
+}
+
+/// Marks a cache of the current version that is being migrated, or whose migration
+/// was interrupted
+pub(super) fn marker(dir: &Path) -> PathBuf {
+    dir.with_extension("migrating")
+}
+
+/// The newest older version below `path` that the cache of `kind` can be migrated from,
+/// with the migrations leading to the current version
+pub(super) fn source(
+    path: &Path,
+    kind: BackendKind,
+) -> JoshResult<Option<(u64, PathBuf, Vec<&'static Migration>)>> {
+    let old = versions(path)?
+        .into_iter()
+        .filter(|(version, dir)| *version < CACHE_VERSION && dir.join(kind.dir_name()).exists())
+        .next_back();
+    let (version, dir) = some_or!(old, { return Ok(None) });
+
+    let chain = some_or!(chain(MIGRATIONS, version, CACHE_VERSION), {
+        tracing::info!(
+            "no migration of the cache from version {} to {}, starting empty",
+            version,
+            CACHE_VERSION
+        );
+        return Ok(None);
+    });
+    Ok(Some((version, dir.join(kind.dir_name()), chain)))
+}
+
+/// Fill the freshly created cache `new` of the current version from `source`
+pub(super) fn migrate(
+    source: (u64, PathBuf, Vec<&Migration>),
+    kind: BackendKind,
+    new: &dyn Backend,
+) -> JoshResult<()> {
+    let (version, dir, chain) = source;
+    tracing::info!(
+        "migrating the cache from version {} to {}, this can take a while",
+        version,
+        CACHE_VERSION
+    );
+
+    let old = kind.open(&dir)?;
+    let (kept, dropped) = copy(&*old, new, &chain)?;
+    tracing::info!(
+        "migrated the cache from version {} to {}: kept {} trees, dropped {}",
+        version,
+        CACHE_VERSION,
+        kept,
+        dropped
+    );
+
+    Ok(())
+}
+
+/// Remove the cache directories of versions older than the current one below `path`.
+/// Nothing is removed while a migration is running or after one was interrupted.
+pub fn remove_stale_versions(path: &Path) -> JoshResult<Vec<PathBuf>> {
+    let current = path.join(format!("josh/{}", CACHE_VERSION));
+    if current.exists() {
+        for entry in std::fs::read_dir(current)? {
+            if entry?.path().extension().is_some_and(|e| e == "migrating") {
+                return Ok(vec![]);
+            }
+        }
+    }
+
+    let mut removed = vec![];
+    for (version, dir) in versions(path)? {
+        if version < CACHE_VERSION {
+            std::fs::remove_dir_all(&dir)?;
+            removed.push(dir);
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory::MemoryBackend;
+    use super::*;
+
+    fn drop_follow(tree: &str) -> bool {
+        uses_op(tree, &["follow"])
+    }
+
+    fn drop_zero(_tree: &str, entry: Entry) -> Option<Entry> {
+        (entry.1 != [0; 20]).then_some(entry)
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: 1,
+            invalidate: drop_follow,
+            upgrade: keep,
+        },
+        Migration {
+            from: 2,
+            invalidate: |_| false,
+            upgrade: drop_zero,
+        },
+    ];
+
+    #[test]
+    fn test_uses_op() {
+        assert!(uses_op(":follow=a", &["follow"]));
+        assert!(uses_op(":[:/a,:follow=b]", &["follow"]));
+        assert!(!uses_op(":followers=a", &["follow"]));
+        assert!(!uses_op(":/follow", &["follow"]));
+    }
+
+    #[test]
+    fn test_chain() {
+        assert_eq!(chain(TEST_MIGRATIONS, 1, 3).map(|c| c.len()), Some(2));
+        assert_eq!(chain(TEST_MIGRATIONS, 2, 3).map(|c| c.len()), Some(1));
+        assert!(chain(TEST_MIGRATIONS, 0, 3).is_none());
+        assert!(chain(TEST_MIGRATIONS, 1, 4).is_none());
+    }
+
+    #[test]
+    fn test_copy() {
+        let old = MemoryBackend::default();
+        let new = MemoryBackend::default();
+        old.open_tree(":/a")
+            .unwrap()
+            .insert(b"1", &[1; 20])
+            .unwrap();
+        old.open_tree(":/a")
+            .unwrap()
+            .insert(b"2", &[0; 20])
+            .unwrap();
+        old.open_tree(":follow=a")
+            .unwrap()
+            .insert(b"1", &[1; 20])
+            .unwrap();
+
+        let chain = chain(TEST_MIGRATIONS, 1, 3).unwrap();
+        assert_eq!(copy(&old, &new, &chain).unwrap(), (1, 1));
+        assert_eq!(new.tree_names().unwrap(), vec![":/a".to_string()]);
+        assert_eq!(new.open_tree(":/a").unwrap().len().unwrap(), 1);
+    }
+}
@@ -1,10 +1,27 @@
 use super::*;
 use std::collections::HashMap;
-
-const CACHE_VERSION: u64 = 22;
+use std::sync::Arc;
+
+mod backend;
+mod bundle;
+mod gc;
+mod memory;
+mod migrate;
+mod sled;
+mod sqlite;
+mod verify;
+
+pub use backend::{Backend, BackendKind, Entry, Tree};
+pub use bundle::{BundleReport, BundleSelection, BundledFilter};
+pub use gc::{DroppedTree, GcOptions, GcReport, parse_age, parse_size};
+pub use migrate::remove_stale_versions;
+pub use verify::{Problem, VerifyIssue, VerifyOptions, VerifyReport, verify};
+
+const CACHE_VERSION: u64 = 23;
+const FILTERS_TREE: &str = "_filters";
 
 lazy_static! {
-    static ref DB: std::sync::Mutex<Option<sled::Db>> = std::sync::Mutex::new(None);
+    static ref DB: std::sync::Mutex<Option<Arc<dyn Backend>>> = std::sync::Mutex::new(None);
     static ref REF_CACHE: std::sync::Mutex<HashMap<git2::Oid, HashMap<git2::Oid, git2::Oid>>> =
         std::sync::Mutex::new(HashMap::new());
     static ref POPULATE_MAP: std::sync::Mutex<HashMap<(git2::Oid, git2::Oid), git2::Oid>> =
@@ -14,31 +31,145 @@ lazy_static! {
 }
 
 pub fn load(path: &std::path::Path) -> JoshResult<()> {
-    *DB.lock()? = Some(
-        sled::Config::default()
-            .path(path.join(format!("josh/{}/sled/", CACHE_VERSION)))
-            .flush_every_ms(Some(200))
-            .open()?,
-    );
+    load_backend(path, BackendKind::default())
+}
+
+/// Open the cache of the repo at `path`, stored with the given backend
+pub fn load_backend(path: &std::path::Path, kind: BackendKind) -> JoshResult<()> {
+    let dir = path.join(format!("josh/{}/{}", CACHE_VERSION, kind.dir_name()));
+
+    // The cache is only used once its migration has finished
+    let marker = migrate::marker(&dir);
+    if marker.exists() {
+        tracing::warn!("migration of the cache was interrupted, starting again");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::remove_file(&marker)?;
+    }
+
+    let source = if dir.exists() {
+        None
+    } else {
+        migrate::source(path, kind)?
+    };
+    if source.is_some() {
+        std::fs::create_dir_all(path.join(format!("josh/{}", CACHE_VERSION)))?;
+        std::fs::write(&marker, "")?;
+    }
+    let db = kind.open(&dir)?;
+    if let Some(source) = source {
+        migrate::migrate(source, kind, &*db)?;
+        std::fs::remove_file(&marker)?;
+    }
+    *DB.lock()? = Some(db);
+    filter::store_filters();
     Ok(())
 }
 
-pub fn print_stats() {
+/// The backend of the loaded cache
+pub fn backend() -> JoshResult<Arc<dyn Backend>> {
+    DB.lock()?
+        .clone()
+        .ok_or_else(|| josh_error("cache not loaded"))
+}
+
+/// Persist the spec of a filter so it can be resolved by id in later processes,
+/// or after it has been evicted from memory. Returns whether it was stored.
+pub(crate) fn store_filter(filter: filter::Filter, spec: &str) -> bool {
     let d = DB.lock().unwrap();
-    let db = d.as_ref().unwrap();
-    db.flush().unwrap();
+    let db = some_or!(d.as_ref(), { return false });
+    match db
+        .open_tree(FILTERS_TREE)
+        .and_then(|t| t.insert(filter.id().as_bytes(), spec.as_bytes()))
+    {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("failed to store filter {}: {}", filter.id(), e.0);
+            false
+        }
+    }
+}
+
+pub(crate) fn load_filter(filter: filter::Filter) -> Option<String> {
+    let d = DB.lock().unwrap();
+    let spec = d
+        .as_ref()?
+        .open_tree(FILTERS_TREE)
+        .ok()?
+        .get(filter.id().as_bytes())
+        .ok()??;
+    String::from_utf8(spec).ok()
+}
+
+fn open_filter_tree(filter: filter::Filter, track_access: bool) -> gc::FilterTree {
+    // The spec has to be computed before taking the lock, as creating filters
+    // stores them in the database.
+    let name = filter::spec(filter);
+    let db = backend().unwrap();
+    if track_access {
+        if let Err(e) = gc::touch(&*db, &name) {
+            tracing::warn!("failed to record access of {}: {}", name, e.0);
+        }
+    }
+    gc::FilterTree::open(db, name).unwrap()
+}
+
+/// Drop the trees of filters that were not used recently, see `GcOptions`
+pub fn gc(options: &GcOptions) -> JoshResult<GcReport> {
+    gc::gc(&*backend()?, options)
+}
+
+/// Make sure everything written to the loaded cache so far survives a crash
+pub fn flush() -> JoshResult<()> {
+    backend()?.flush()
+}
+
+/// Export the cache of the selected filters as a bundle, see `import_bundle`
+pub fn export_bundle(
+    repo: &git2::Repository,
+    selection: &BundleSelection,
+    out: &mut dyn std::io::Write,
+) -> JoshResult<BundleReport> {
+    bundle::export(&*backend()?, repo, selection, out)
+}
+
+/// Import a bundle written by `export_bundle` into the cache of `repo`
+pub fn import_bundle(
+    repo: &git2::Repository,
+    input: &mut dyn std::io::BufRead,
+) -> JoshResult<BundleReport> {
+    bundle::import(&*backend()?, repo, input)
+}
+
+pub fn size_on_disk() -> JoshResult<u64> {
+    backend()?.size_on_disk()
+}
+
+pub fn print_stats() {
+    let trees = {
+        let db = backend().unwrap();
+        db.flush().unwrap();
+        db.tree_names()
+            .unwrap()
+            .into_iter()
+            .filter(|name| {
+                name != FILTERS_TREE && name != gc::ACCESS_TREE && name != gc::ENTRIES_TREE
+            })
+            .map(|name| (db.open_tree(&name).unwrap(), name))
+            .collect::<Vec<_>>()
+    };
     log::debug!("Trees:");
     let mut v = vec![];
-    for name in db.tree_names() {
-        let name = String::from_utf8(name.to_vec()).unwrap();
-        let t = db.open_tree(&name).unwrap();
-        if !t.is_empty() {
+    for (t, name) in trees {
+        let len = t.len().unwrap();
+        if len != 0 {
             let name = if let Ok(filter) = filter::parse(&name) {
                 filter::pretty(filter, 4)
             } else {
                 name.clone()
             };
-            v.push((t.len(), name));
+            v.push((len, name));
         }
     }
 
@@ -56,13 +187,15 @@ struct Transaction2 {
     subtract_map: HashMap<(git2::Oid, git2::Oid), git2::Oid>,
     overlay_map: HashMap<(git2::Oid, git2::Oid), git2::Oid>,
     unapply_map: HashMap<git2::Oid, HashMap<git2::Oid, git2::Oid>>,
-    sled_trees: HashMap<git2::Oid, sled::Tree>,
-    path_tree: sled::Tree,
-    invert_tree: sled::Tree,
-    trigram_index_tree: sled::Tree,
+    trees: HashMap<git2::Oid, gc::FilterTree>,
+    path_tree: Arc<dyn Tree>,
+    invert_tree: Arc<dyn Tree>,
+    trigram_index_tree: Arc<dyn Tree>,
     missing: Vec<(filter::Filter, git2::Oid)>,
     misses: usize,
     walks: usize,
+    track_access: bool,
+    persistent: bool,
 }
 
 pub struct Transaction {
@@ -86,11 +219,11 @@ impl Transaction {
         ))
     }
 
-    pub fn open_from_env(load_cache: bool) -> JoshResult<Transaction> {
+    pub fn open_from_env(load_cache: Option<BackendKind>) -> JoshResult<Transaction> {
         let repo = git2::Repository::open_from_env()?;
         let path = repo.path().to_owned();
-        if load_cache {
-            load(&path)?
+        if let Some(kind) = load_cache {
+            load_backend(&path, kind)?
         };
 
         Ok(Transaction::new(repo, None))
@@ -104,27 +237,10 @@ impl Transaction {
 
     fn new(repo: git2::Repository, ref_prefix: Option<&str>) -> Transaction {
         log::debug!("new transaction");
-        let path_tree = DB
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .open_tree("_paths")
-            .unwrap();
-        let invert_tree = DB
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .open_tree("_invert")
-            .unwrap();
-        let trigram_index_tree = DB
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .open_tree("_trigram_index")
-            .unwrap();
+        let db = backend().unwrap();
+        let path_tree = db.open_tree("_paths").unwrap();
+        let invert_tree = db.open_tree("_invert").unwrap();
+        let trigram_index_tree = db.open_tree("_trigram_index").unwrap();
         Transaction {
             t2: std::cell::RefCell::new(Transaction2 {
                 commit_map: HashMap::new(),
@@ -132,13 +248,15 @@ impl Transaction {
                 subtract_map: HashMap::new(),
                 overlay_map: HashMap::new(),
                 unapply_map: HashMap::new(),
-                sled_trees: HashMap::new(),
+                trees: HashMap::new(),
                 path_tree,
                 invert_tree,
                 trigram_index_tree,
                 missing: vec![],
                 misses: 0,
                 walks: 0,
+                track_access: true,
+                persistent: true,
             }),
             repo,
             ref_prefix: ref_prefix.unwrap_or("").to_string(),
@@ -157,6 +275,28 @@ impl Transaction {
         format!("{}{}", self.ref_prefix, r)
     }
 
+    /// A transaction on the same repo that neither reads nor writes the persistent
+    /// cache, to compute filtered commits from scratch
+    pub fn uncached(&self) -> JoshResult<Transaction> {
+        let transaction = self.try_clone()?;
+        {
+            let memory = memory::MemoryBackend::default();
+            let mut t2 = transaction.t2.borrow_mut();
+            t2.path_tree = memory.open_tree("_paths")?;
+            t2.invert_tree = memory.open_tree("_invert")?;
+            t2.trigram_index_tree = memory.open_tree("_trigram_index")?;
+            t2.track_access = false;
+            t2.persistent = false;
+        }
+        Ok(transaction)
+    }
+
+    /// Stop recording the use of filters, for background work that shouldn't keep
+    /// their cache from being collected
+    pub fn disable_access_tracking(&self) {
+        self.t2.borrow_mut().track_access = false;
+    }
+
     pub fn misses(&self) -> usize {
         self.t2.borrow().misses
     }
@@ -325,15 +465,12 @@ impl Transaction {
         // In addition to commits that are explicitly requested to be stored, also store
         // random extra commits (probability 1/256) to avoid long searches for filters that reduce
         // the history length by a very large factor.
-        if store || from.as_bytes()[0] == 0 {
-            let t = t2.sled_trees.entry(filter.id()).or_insert_with(|| {
-                DB.lock()
-                    .unwrap()
-                    .as_ref()
-                    .unwrap()
-                    .open_tree(filter::spec(filter))
-                    .unwrap()
-            });
+        if t2.persistent && (store || from.as_bytes()[0] == 0) {
+            let track_access = t2.track_access;
+            let t = t2
+                .trees
+                .entry(filter.id())
+                .or_insert_with(|| open_filter_tree(filter, track_access));
 
             t.insert(from.as_bytes(), to.as_bytes()).unwrap();
         }
@@ -342,16 +479,13 @@ impl Transaction {
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self, filter: filter::Filter) -> usize {
         let mut t2 = self.t2.borrow_mut();
-        let t = t2.sled_trees.entry(filter.id()).or_insert_with(|| {
-            DB.lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .open_tree(filter::spec(filter))
-                .unwrap()
-        });
+        let track_access = t2.track_access;
+        let t = t2
+            .trees
+            .entry(filter.id())
+            .or_insert_with(|| open_filter_tree(filter, track_access));
 
-        t.len()
+        t.len().unwrap()
     }
 
     pub fn get_missing(&self) -> Vec<(filter::Filter, git2::Oid)> {
@@ -388,14 +522,14 @@ impl Transaction {
                 return Some(oid);
             }
         }
-        let t = t2.sled_trees.entry(filter.id()).or_insert_with(|| {
-            DB.lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .open_tree(filter::spec(filter))
-                .unwrap()
-        });
+        if !t2.persistent {
+            return None;
+        }
+        let track_access = t2.track_access;
+        let t = t2
+            .trees
+            .entry(filter.id())
+            .or_insert_with(|| open_filter_tree(filter, track_access));
         if let Some(oid) = t.get(from.as_bytes()).unwrap() {
             let oid = git2::Oid::from_bytes(&oid).unwrap();
             if oid == git2::Oid::zero() {
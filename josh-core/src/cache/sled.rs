@@ -0,0 +1,57 @@
+use super::backend::{Backend, Entry, Tree};
+use crate::JoshResult;
+use std::sync::Arc;
+
+impl Tree for ::sled::Tree {
+    fn get(&self, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        Ok(::sled::Tree::get(self, key)?.map(|v| v.to_vec()))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        ::sled::Tree::insert(self, key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> JoshResult<()> {
+        ::sled::Tree::remove(self, key)?;
+        Ok(())
+    }
+
+    fn len(&self) -> JoshResult<usize> {
+        Ok(::sled::Tree::len(self))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = JoshResult<Entry>> + '_> {
+        Box::new(
+            ::sled::Tree::iter(self).map(|entry| Ok(entry.map(|(k, v)| (k.to_vec(), v.to_vec()))?)),
+        )
+    }
+}
+
+impl Backend for ::sled::Db {
+    fn open_tree(&self, name: &str) -> JoshResult<Arc<dyn Tree>> {
+        Ok(Arc::new(::sled::Db::open_tree(self, name)?))
+    }
+
+    fn drop_tree(&self, name: &str) -> JoshResult<()> {
+        ::sled::Db::drop_tree(self, name)?;
+        Ok(())
+    }
+
+    fn tree_names(&self) -> JoshResult<Vec<String>> {
+        Ok(::sled::Db::tree_names(self)
+            .into_iter()
+            .filter(|name| name.as_ref() != b"__sled__default")
+            .map(|name| String::from_utf8_lossy(&name).to_string())
+            .collect())
+    }
+
+    fn flush(&self) -> JoshResult<()> {
+        ::sled::Tree::flush(self)?;
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> JoshResult<u64> {
+        Ok(::sled::Db::size_on_disk(self)?)
+    }
+}
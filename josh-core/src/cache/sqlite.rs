@@ -0,0 +1,385 @@
+use super::backend::{Backend, Entry, Tree};
+use crate::JoshResult;
+use rusqlite::{OptionalExtension, params};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Number of entries read at once when iterating, so the connection isn't
+// locked for the whole iteration
+const ITER_BATCH: usize = 1000;
+
+// Writes are collected in a transaction that is committed after this many writes,
+// on `flush`, or by a background thread after `COMMIT_INTERVAL`
+const COMMIT_BATCH: usize = 1000;
+const COMMIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Keeps the cache in a single SQLite database. Committed writes go to the WAL and survive
+/// a crash of the process, the database can be compacted with `VACUUM` while not in use.
+pub struct SqliteBackend {
+    connections: Arc<Connections>,
+    path: std::path::PathBuf,
+}
+
+/// Writes to a tree that was dropped are ignored, the handle doesn't see the tree
+/// created when opening the same name again.
+pub struct SqliteTree {
+    connections: Arc<Connections>,
+    id: i64,
+}
+
+struct Writer {
+    connection: rusqlite::Connection,
+    writes: usize,
+}
+
+/// Reads use their own connection so they don't wait for writes, unless there are
+/// uncommitted writes which only the writing connection sees
+struct Connections {
+    writer: Mutex<Writer>,
+    reader: Mutex<rusqlite::Connection>,
+    pending: AtomicBool,
+}
+
+fn connect(path: &std::path::Path) -> JoshResult<rusqlite::Connection> {
+    let connection = rusqlite::Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "NORMAL")?;
+    connection.busy_timeout(std::time::Duration::from_secs(30))?;
+    Ok(connection)
+}
+
+impl Connections {
+    fn read<T>(
+        &self,
+        f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+    ) -> JoshResult<T> {
+        if self.pending.load(Ordering::Acquire) {
+            Ok(f(&self.writer.lock()?.connection)?)
+        } else {
+            Ok(f(&*self.reader.lock()?)?)
+        }
+    }
+
+    fn write<T>(
+        &self,
+        f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+    ) -> JoshResult<T> {
+        let mut writer = self.writer.lock()?;
+        if writer.writes == 0 {
+            writer.connection.execute_batch("BEGIN")?;
+            self.pending.store(true, Ordering::Release);
+        }
+        writer.writes += 1;
+        let result = f(&writer.connection)?;
+        if writer.writes >= COMMIT_BATCH {
+            self.commit(&mut writer)?;
+        }
+        Ok(result)
+    }
+
+    fn commit(&self, writer: &mut Writer) -> JoshResult<()> {
+        if writer.writes > 0 {
+            writer.connection.execute_batch("COMMIT")?;
+            writer.writes = 0;
+            self.pending.store(false, Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+impl SqliteBackend {
+    pub fn open(path: &std::path::Path) -> JoshResult<SqliteBackend> {
+        std::fs::create_dir_all(path)?;
+        let path = path.join("cache.db");
+        let writer = connect(&path)?;
+
+        // AUTOINCREMENT, so the id of a dropped tree is never used again
+        writer.execute_batch(
+            "CREATE TABLE IF NOT EXISTS trees (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 name TEXT NOT NULL UNIQUE
+             );
+             CREATE TABLE IF NOT EXISTS entries (
+                 tree INTEGER NOT NULL,
+                 key BLOB NOT NULL,
+                 value BLOB NOT NULL,
+                 PRIMARY KEY (tree, key)
+             ) WITHOUT ROWID;",
+        )?;
+
+        let connections = Arc::new(Connections {
+            writer: Mutex::new(Writer {
+                connection: writer,
+                writes: 0,
+            }),
+            reader: Mutex::new(connect(&path)?),
+            pending: AtomicBool::new(false),
+        });
+
+        let weak = Arc::downgrade(&connections);
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(COMMIT_INTERVAL);
+                let connections = some_or!(weak.upgrade(), { break });
+                let result = connections
+                    .writer
+                    .lock()
+                    .map_err(Into::into)
+                    .and_then(|mut writer| connections.commit(&mut writer));
+                if let Err(e) = result {
+                    tracing::warn!("failed to commit the cache: {}", e.0);
+                }
+            }
+        });
+
+        Ok(SqliteBackend { connections, path })
+    }
+}
+
+impl Tree for SqliteTree {
+    fn get(&self, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        self.connections.read(|connection| {
+            connection
+                .prepare_cached("SELECT value FROM entries WHERE tree = ?1 AND key = ?2")?
+                .query_row(params![self.id, key], |row| row.get(0))
+                .optional()
+        })
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        self.connections.write(|connection| {
+            connection
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO entries (tree, key, value)
+                     SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM trees WHERE id = ?1)",
+                )?
+                .execute(params![self.id, key, value])
+        })?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> JoshResult<()> {
+        self.connections.write(|connection| {
+            connection
+                .prepare_cached("DELETE FROM entries WHERE tree = ?1 AND key = ?2")?
+                .execute(params![self.id, key])
+        })?;
+        Ok(())
+    }
+
+    fn len(&self) -> JoshResult<usize> {
+        let len = self.connections.read(|connection| {
+            connection
+                .prepare_cached("SELECT COUNT(*) FROM entries WHERE tree = ?1")?
+                .query_row(params![self.id], |row| row.get::<_, i64>(0))
+        })?;
+        Ok(len as usize)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = JoshResult<Entry>> + '_> {
+        let mut batch = std::collections::VecDeque::new();
+        let mut last: Option<Vec<u8>> = None;
+        let mut done = false;
+
+        Box::new(std::iter::from_fn(move || {
+            if batch.is_empty() && !done {
+                let result = self.read_batch(last.as_deref()).map(|entries| {
+                    done = entries.len() < ITER_BATCH;
+                    last = entries.last().map(|(k, _)| k.clone());
+                    batch.extend(entries);
+                });
+                if let Err(e) = result {
+                    done = true;
+                    return Some(Err(e));
+                }
+            }
+            batch.pop_front().map(Ok)
+        }))
+    }
+}
+
+impl SqliteTree {
+    fn read_batch(&self, after: Option<&[u8]>) -> JoshResult<Vec<Entry>> {
+        self.connections.read(|connection| {
+            connection
+                .prepare_cached(
+                    "SELECT key, value FROM entries WHERE tree = ?1 AND (?2 IS NULL OR key > ?2)
+                     ORDER BY key LIMIT ?3",
+                )?
+                .query_map(params![self.id, after, ITER_BATCH as i64], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect()
+        })
+    }
+}
+
+impl Backend for SqliteBackend {
+    fn open_tree(&self, name: &str) -> JoshResult<Arc<dyn Tree>> {
+        let existing = self.connections.read(|connection| {
+            connection
+                .prepare_cached("SELECT id FROM trees WHERE name = ?1")?
+                .query_row(params![name], |row| row.get(0))
+                .optional()
+        })?;
+        let id = match existing {
+            Some(id) => id,
+            None => self.connections.write(|connection| {
+                connection
+                    .prepare_cached("INSERT OR IGNORE INTO trees (name) VALUES (?1)")?
+                    .execute(params![name])?;
+                connection
+                    .prepare_cached("SELECT id FROM trees WHERE name = ?1")?
+                    .query_row(params![name], |row| row.get(0))
+            })?,
+        };
+
+        Ok(Arc::new(SqliteTree {
+            connections: self.connections.clone(),
+            id,
+        }))
+    }
+
+    fn drop_tree(&self, name: &str) -> JoshResult<()> {
+        self.connections.write(|connection| {
+            connection.execute(
+                "DELETE FROM entries WHERE tree = (SELECT id FROM trees WHERE name = ?1)",
+                params![name],
+            )?;
+            connection.execute("DELETE FROM trees WHERE name = ?1", params![name])
+        })?;
+        Ok(())
+    }
+
+    fn tree_names(&self) -> JoshResult<Vec<String>> {
+        self.connections.read(|connection| {
+            connection
+                .prepare_cached("SELECT name FROM trees ORDER BY name")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+    }
+
+    fn flush(&self) -> JoshResult<()> {
+        let mut writer = self.connections.writer.lock()?;
+        self.connections.commit(&mut writer)?;
+        writer
+            .connection
+            .query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> JoshResult<u64> {
+        let mut wal = self.path.clone().into_os_string();
+        wal.push("-wal");
+
+        Ok([self.path.as_os_str(), wal.as_os_str()]
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestDir(std::path::PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> TestDir {
+            let path =
+                std::env::temp_dir().join(format!("josh-sqlite-{}-{}", name, std::process::id()));
+            std::fs::remove_dir_all(&path).ok();
+            TestDir(path)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn key(i: usize) -> Vec<u8> {
+        (i as u32).to_be_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_iter_batches() {
+        let dir = TestDir::new("iter");
+        let db = SqliteBackend::open(&dir.0).unwrap();
+        let tree = db.open_tree("a").unwrap();
+        db.open_tree("b").unwrap().insert(b"b", b"b").unwrap();
+
+        let n = 2 * ITER_BATCH + 1;
+        for i in (0..n).rev() {
+            tree.insert(&key(i), &key(i + 1)).unwrap();
+        }
+        db.flush().unwrap();
+
+        let entries = tree.iter().collect::<JoshResult<Vec<_>>>().unwrap();
+        assert_eq!(entries.len(), n);
+        for (i, (k, v)) in entries.into_iter().enumerate() {
+            assert_eq!((k, v), (key(i), key(i + 1)));
+        }
+
+        // Exactly a multiple of the batch size
+        tree.remove(&key(0)).unwrap();
+        assert_eq!(tree.iter().count(), 2 * ITER_BATCH);
+    }
+
+    #[test]
+    fn test_uncommitted_writes_are_visible() {
+        let dir = TestDir::new("pending");
+        let db = SqliteBackend::open(&dir.0).unwrap();
+        let tree = db.open_tree("a").unwrap();
+
+        tree.insert(b"1", b"x").unwrap();
+        assert_eq!(tree.get(b"1").unwrap(), Some(b"x".to_vec()));
+        assert_eq!(tree.len().unwrap(), 1);
+        db.flush().unwrap();
+        assert!(!db.connections.pending.load(Ordering::Acquire));
+        assert_eq!(tree.get(b"1").unwrap(), Some(b"x".to_vec()));
+
+        // Another process sees the committed entries
+        let other = SqliteBackend::open(&dir.0).unwrap();
+        assert_eq!(
+            other.open_tree("a").unwrap().get(b"1").unwrap(),
+            Some(b"x".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_drop_tree() {
+        let dir = TestDir::new("drop");
+        let db = SqliteBackend::open(&dir.0).unwrap();
+        let old = db.open_tree("a").unwrap();
+        old.insert(b"1", b"x").unwrap();
+        db.open_tree("b").unwrap().insert(b"1", b"y").unwrap();
+
+        db.drop_tree("a").unwrap();
+        assert_eq!(db.tree_names().unwrap(), vec!["b".to_string()]);
+        assert_eq!(old.get(b"1").unwrap(), None);
+
+        // The old handle neither writes orphaned entries nor into the new tree
+        let new = db.open_tree("a").unwrap();
+        old.insert(b"2", b"x").unwrap();
+        assert_eq!(old.len().unwrap(), 0);
+        assert_eq!(new.len().unwrap(), 0);
+        new.insert(b"3", b"z").unwrap();
+        assert_eq!(new.len().unwrap(), 1);
+
+        db.flush().unwrap();
+        let count = db
+            .connections
+            .read(|c| {
+                c.query_row("SELECT COUNT(*) FROM entries", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+            })
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
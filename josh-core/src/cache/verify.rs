@@ -0,0 +1,117 @@
+use super::{Transaction, backend};
+use crate::{JoshResult, filter};
+
+#[derive(Clone, Debug, Default)]
+pub struct VerifyOptions {
+    /// Only check this many entries of each filter instead of all of them
+    pub sample: Option<usize>,
+    /// Store the recomputed commit for mismatches and remove the entries of
+    /// missing commits
+    pub repair: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// Filtering the commit again gives a different result
+    Mismatch(git2::Oid),
+    /// The unfiltered commit doesn't exist
+    MissingCommit,
+    /// The cached filtered commit didn't exist, it was written again when recomputing it
+    MissingResult,
+}
+
+#[derive(Clone, Debug)]
+pub struct VerifyIssue {
+    pub spec: String,
+    pub from: git2::Oid,
+    pub to: git2::Oid,
+    pub problem: Problem,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    pub filters: usize,
+    pub checked: usize,
+    pub issues: Vec<VerifyIssue>,
+}
+
+/// Compare the cached `(filter, from) -> to` entries with the result of filtering
+/// `from` again without using the cache
+pub fn verify(transaction: &Transaction, options: &VerifyOptions) -> JoshResult<VerifyReport> {
+    let db = backend()?;
+    let repo = transaction.repo();
+    let odb = repo.odb()?;
+    let mut report = VerifyReport::default();
+
+    for spec in db.tree_names()? {
+        if spec.starts_with('_') {
+            continue;
+        }
+        let filter = match filter::parse(&spec) {
+            Ok(filter) => filter,
+            Err(e) => {
+                tracing::warn!("skipping cache of invalid filter {}: {}", spec, e.0);
+                continue;
+            }
+        };
+        let tree = db.open_tree(&spec)?;
+
+        // Spread the sample over all keys. Entries stored at random are those of commit
+        // ids starting with 0x00, they would make up all of the first ones.
+        let sample = options.sample.unwrap_or(usize::MAX);
+        let step = match options.sample {
+            Some(sample) if sample > 0 => (tree.len()? / sample).max(1),
+            _ => 1,
+        };
+        let entries = tree
+            .iter()
+            .step_by(step)
+            .take(sample)
+            .collect::<JoshResult<Vec<_>>>()?;
+
+        // A new transaction for every filter, to bound the memory used
+        let uncached = transaction.uncached()?;
+        report.filters += 1;
+
+        for (key, value) in entries {
+            let from = git2::Oid::from_bytes(&key)?;
+            let to = git2::Oid::from_bytes(&value)?;
+            report.checked += 1;
+
+            let problem = if let Ok(commit) = repo.find_commit(from) {
+                let missing = !to.is_zero() && !odb.exists(to);
+                let expected = filter::apply_to_commit(filter, &commit, &uncached)?;
+                if expected != to {
+                    Some(Problem::Mismatch(expected))
+                } else if missing {
+                    Some(Problem::MissingResult)
+                } else {
+                    None
+                }
+            } else {
+                Some(Problem::MissingCommit)
+            };
+            let problem = some_or!(problem, { continue });
+
+            if options.repair {
+                match problem {
+                    Problem::Mismatch(expected) => tree.insert(&key, expected.as_bytes())?,
+                    Problem::MissingCommit => tree.remove(&key)?,
+                    Problem::MissingResult => {}
+                }
+            }
+            report.issues.push(VerifyIssue {
+                spec: spec.clone(),
+                from,
+                to,
+                problem,
+            });
+        }
+    }
+
+    if options.repair {
+        db.flush()?;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,131 @@
+/*
+ * Support for identity mapping tables in the format of git's `.mailmap` files.
+ * See `git help check-mailmap` for a description of the format.
+ */
+
+use super::*;
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+impl Entry {
+    pub fn parse(line: &str) -> JoshResult<Option<Entry>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (name1, email1, rest) = parse_name_and_email(line)
+            .ok_or_else(|| josh_error(&format!("invalid mailmap entry: {:?}", line)))?;
+
+        if rest.trim().is_empty() || rest.trim().starts_with('#') {
+            return Ok(Some(Entry {
+                proper_name: name1,
+                proper_email: None,
+                commit_name: None,
+                commit_email: email1,
+            }));
+        }
+
+        let (name2, email2, _) = parse_name_and_email(rest)
+            .ok_or_else(|| josh_error(&format!("invalid mailmap entry: {:?}", line)))?;
+
+        Ok(Some(Entry {
+            proper_name: name1,
+            proper_email: Some(email1),
+            commit_name: name2,
+            commit_email: email2,
+        }))
+    }
+
+    fn matches(&self, name: &str, email: &str) -> bool {
+        self.commit_email.eq_ignore_ascii_case(email)
+            && self
+                .commit_name
+                .as_ref()
+                .map(|n| n.eq_ignore_ascii_case(name))
+                .unwrap_or(true)
+    }
+
+    fn matches_proper(&self, name: &str, email: &str) -> bool {
+        self.proper_email
+            .as_ref()
+            .unwrap_or(&self.commit_email)
+            .eq_ignore_ascii_case(email)
+            && self.proper_name.as_ref().map(|n| n == name).unwrap_or(true)
+    }
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pair = |name: &Option<String>, email: &str| match name {
+            Some(name) => format!("{} <{}>", name, email),
+            None => format!("<{}>", email),
+        };
+        match &self.proper_email {
+            Some(proper_email) => write!(
+                f,
+                "{} {}",
+                pair(&self.proper_name, proper_email),
+                pair(&self.commit_name, &self.commit_email)
+            ),
+            None => write!(f, "{}", pair(&self.proper_name, &self.commit_email)),
+        }
+    }
+}
+
+fn parse_name_and_email(s: &str) -> Option<(Option<String>, String, &str)> {
+    let (name, rest) = s.split_once('<')?;
+    let (email, rest) = rest.split_once('>')?;
+    let name = name.trim();
+    let name = if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    };
+    Some((name, email.trim().to_string(), rest))
+}
+
+/// Read a table from a file. Like git, lines that can't be parsed are ignored.
+pub fn read(text: &str) -> Vec<Entry> {
+    text.lines()
+        .filter_map(|line| Entry::parse(line).ok().flatten())
+        .collect()
+}
+
+/// Map an identity to the one specified in the table. Entries that also match
+/// the name take precedence, among equally specific entries the last one wins.
+pub fn resolve(entries: &[Entry], name: &str, email: &str) -> (String, String) {
+    let entry = entries
+        .iter()
+        .filter(|e| e.matches(name, email))
+        .max_by_key(|e| e.commit_name.is_some());
+
+    if let Some(e) = entry {
+        (
+            e.proper_name.clone().unwrap_or_else(|| name.to_string()),
+            e.proper_email.clone().unwrap_or_else(|| email.to_string()),
+        )
+    } else {
+        (name.to_string(), email.to_string())
+    }
+}
+
+/// Map an identity produced by `resolve` back to the one it was mapped from.
+/// When several identities got mapped to the same one, the first entry wins.
+/// Parts of the identity that are not recorded in the table are kept as is.
+pub fn unresolve(entries: &[Entry], name: &str, email: &str) -> (String, String) {
+    if let Some(e) = entries.iter().find(|e| e.matches_proper(name, email)) {
+        (
+            e.commit_name.clone().unwrap_or_else(|| name.to_string()),
+            e.commit_email.clone(),
+        )
+    } else {
+        (name.to_string(), email.to_string())
+    }
+}
@@ -2,21 +2,42 @@ use super::*;
 use history::RewriteData;
 use pest::Parser;
 use std::path::Path;
+mod mailmap;
 mod opt;
 mod parse;
+mod patterns;
+mod submodules;
 pub mod tree;
 
 pub use opt::invert;
 pub use parse::get_comments;
 pub use parse::parse;
 
+/// Number of filters kept in memory. Filters evicted from memory are
+/// loaded again from the on-disk registry when needed.
+#[cfg(not(test))]
+const FILTER_CACHE_SIZE: usize = 100_000;
+#[cfg(test)]
+const FILTER_CACHE_SIZE: usize = 100;
+
+/// Number of tips for which the set of ancestors is kept in memory
+const ANCESTORS_CACHE_SIZE: usize = 64;
+
+fn cache_size() -> std::num::NonZeroUsize {
+    std::num::NonZeroUsize::new(FILTER_CACHE_SIZE).unwrap()
+}
+
 lazy_static! {
-    static ref FILTERS: std::sync::Mutex<std::collections::HashMap<Filter, Op>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-    static ref WORKSPACES: std::sync::Mutex<std::collections::HashMap<git2::Oid, Filter>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-    static ref ANCESTORS: std::sync::Mutex<std::collections::HashMap<git2::Oid, std::collections::HashSet<git2::Oid>>> =
+    static ref FILTERS: std::sync::Mutex<lru::LruCache<Filter, Op>> =
+        std::sync::Mutex::new(lru::LruCache::new(cache_size()));
+    // Filters created while no cache is loaded can't be evicted, as there is no
+    // registry to load them from again
+    static ref PINNED: std::sync::Mutex<std::collections::HashMap<Filter, Op>> =
         std::sync::Mutex::new(std::collections::HashMap::new());
+    static ref WORKSPACES: std::sync::Mutex<lru::LruCache<git2::Oid, Filter>> =
+        std::sync::Mutex::new(lru::LruCache::new(cache_size()));
+    static ref ANCESTORS: std::sync::Mutex<lru::LruCache<git2::Oid, std::collections::HashSet<git2::Oid>>> =
+        std::sync::Mutex::new(lru::LruCache::new(std::num::NonZeroUsize::new(ANCESTORS_CACHE_SIZE).unwrap()));
 }
 
 /// Filters are represented as `git2::Oid`, however they are not ever stored
@@ -87,17 +108,72 @@ fn to_filter(op: Op) -> Filter {
     let f = Filter(
         git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object filter"),
     );
-    FILTERS.lock().unwrap().insert(f, op);
+    {
+        let mut filters = FILTERS.lock().unwrap();
+        if filters.get(&f).is_some() {
+            return f;
+        }
+        filters.put(f, op.clone());
+    }
+    if !cache::store_filter(f, &spec2(&op)) {
+        PINNED.lock().unwrap().insert(f, op);
+    }
     f
 }
 
-fn to_op(filter: Filter) -> Op {
-    FILTERS
+/// Store all filters currently in memory in the on-disk registry.
+/// Used when the cache gets loaded after filters have already been created.
+pub(crate) fn store_filters() {
+    let filters: Vec<_> = FILTERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(f, op)| (*f, op.clone()))
+        .collect();
+    for (f, op) in filters {
+        cache::store_filter(f, &spec2(&op));
+    }
+
+    // Unpin only after storing, as the specs refer to other pinned filters
+    let pinned: Vec<_> = PINNED
         .lock()
         .unwrap()
-        .get(&filter)
-        .expect("unknown filter")
-        .clone()
+        .iter()
+        .map(|(f, op)| (*f, op.clone()))
+        .collect();
+    let stored: Vec<_> = pinned
+        .into_iter()
+        .filter(|(f, op)| cache::store_filter(*f, &spec2(op)))
+        .collect();
+    let mut pinned = PINNED.lock().unwrap();
+    for (f, _) in stored {
+        pinned.remove(&f);
+    }
+}
+
+/// Look up the definition of a filter, fails if it is neither in memory nor
+/// in the registry of the loaded cache
+fn try_to_op(filter: Filter) -> JoshResult<Op> {
+    if let Some(op) = FILTERS.lock().unwrap().get(&filter) {
+        return Ok(op.clone());
+    }
+    if let Some(op) = PINNED.lock().unwrap().get(&filter) {
+        return Ok(op.clone());
+    }
+
+    let spec = cache::load_filter(filter)
+        .ok_or_else(|| josh_error(&format!("unknown filter: {}", filter.id())))?;
+    let op = try_to_op(parse(&spec)?)?;
+
+    FILTERS.lock().unwrap().put(filter, op.clone());
+    Ok(op)
+}
+
+// Filters only exist after being created by `to_filter`, which keeps them either in
+// memory or in the registry, so this only fails if the registry got lost.
+// Entry points use `try_to_op` to report that as an error.
+fn to_op(filter: Filter) -> Op {
+    try_to_op(filter).unwrap_or_else(|e| panic!("{}", e.0))
 }
 
 #[derive(Hash, Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
@@ -138,6 +214,8 @@ enum Op {
     Squash(Option<std::collections::BTreeMap<LazyRef, Filter>>),
     Author(String, String),
     Committer(String, String),
+    Mailmap(Vec<mailmap::Entry>),
+    MailmapFile(std::path::PathBuf),
 
     // We use BTreeMap rather than HashMap to guarantee deterministic results when
     // converting to Filter
@@ -147,6 +225,7 @@ enum Op {
     Unsign,
 
     RegexReplace(Vec<(regex::Regex, String)>),
+    MessageReplace(Vec<(regex::Regex, String)>),
 
     Index,
     Invert,
@@ -155,12 +234,20 @@ enum Op {
     Prefix(std::path::PathBuf),
     Subdir(std::path::PathBuf),
     Workspace(std::path::PathBuf),
+    Sparse(std::path::PathBuf),
+    Follow(std::path::PathBuf),
 
     Glob(String),
+    Globs(Vec<String>),
+    MaxSize(usize),
+    ExcludeBinary,
+    InlineSubmodules,
+    Submodule(std::path::PathBuf, String),
     Message(String),
 
     Compose(Vec<Filter>),
     Chain(Filter, Filter),
+    Named(String, Filter),
     Subtract(Filter, Filter),
     Exclude(Filter),
 }
@@ -168,28 +255,91 @@ enum Op {
 /// Pretty print the filter on multiple lines with initial indentation level.
 /// Nested filters will be indented with additional 4 spaces per nesting level.
 pub fn pretty(filter: Filter, indent: usize) -> String {
+    pretty_with_shared(filter, indent, &[])
+}
+
+/// Like `pretty`, but named filters that are in `shared` are only referenced and not defined.
+/// At indentation level 0 all other named filters are defined with `let` before their first use.
+fn pretty_with_shared(filter: Filter, indent: usize, shared: &[(String, Filter)]) -> String {
     let filter = opt::simplify(filter);
+    let shared: Vec<_> = shared
+        .iter()
+        .map(|(name, f)| (name.clone(), opt::simplify(*f)))
+        .collect();
+
+    let mut named = vec![];
+    if indent == 0 {
+        named_filters(filter, &mut named);
+    }
+    // Names that are used for different filters can't be defined once
+    let named: Vec<_> = named
+        .iter()
+        .filter(|(name, _)| named.iter().filter(|(n, _)| n == name).count() == 1)
+        .cloned()
+        .collect();
+    let names: Vec<_> = named.iter().map(|(name, _)| name.clone()).collect();
+    let names = &names;
+
+    let mut lines: Vec<_> = named
+        .iter()
+        .filter(|x| !shared.contains(x))
+        .filter_map(|(name, f)| match to_op(*f) {
+            Op::Named(_, f) => Some(format!(
+                "let {} = {}",
+                name,
+                pretty2(&to_op(opt::simplify(opt::flatten(f))), 4, false, names)
+            )),
+            _ => None,
+        })
+        .collect();
 
     if let Op::Compose(filters) = to_op(filter) {
         if indent == 0 {
-            let i = format!("\n{}", " ".repeat(indent));
-            return filters
-                .iter()
-                .map(|x| pretty2(&to_op(*x), indent + 4, true))
-                .collect::<Vec<_>>()
-                .join(&i);
+            lines.extend(
+                filters
+                    .iter()
+                    .map(|x| pretty2(&to_op(*x), indent + 4, true, names)),
+            );
+            return lines.join("\n");
+        }
+    }
+    if let Op::Globs(patterns) = to_op(filter) {
+        if indent == 0 {
+            lines.extend(patterns.iter().map(|p| glob_spec(p)));
+            return lines.join("\n");
+        }
+    }
+    lines.push(pretty2(&to_op(filter), indent, true, names));
+    lines.join("\n")
+}
+
+/// Collect all named filters, in an order where every filter comes after
+/// the named filters it references
+fn named_filters(filter: Filter, out: &mut Vec<(String, Filter)>) {
+    match to_op(filter) {
+        Op::Named(name, f) => {
+            named_filters(f, out);
+            if !out.contains(&(name.clone(), filter)) {
+                out.push((name, filter));
+            }
         }
+        Op::Compose(filters) => filters.into_iter().for_each(|f| named_filters(f, out)),
+        Op::Chain(a, b) | Op::Subtract(a, b) => {
+            named_filters(a, out);
+            named_filters(b, out);
+        }
+        Op::Exclude(f) => named_filters(f, out),
+        _ => {}
     }
-    pretty2(&to_op(filter), indent, true)
 }
 
-fn pretty2(op: &Op, indent: usize, compose: bool) -> String {
+fn pretty2(op: &Op, indent: usize, compose: bool, names: &[String]) -> String {
     let ff = |filters: &Vec<_>, n, ind| {
         let ind2 = std::cmp::max(ind, 4);
         let i = format!("\n{}", " ".repeat(ind2));
         let joined = filters
             .iter()
-            .map(|x| pretty2(&to_op(*x), ind + 4, true))
+            .map(|x| pretty2(&to_op(*x), ind + 4, true, names))
             .collect::<Vec<_>>()
             .join(&i);
 
@@ -203,10 +353,25 @@ fn pretty2(op: &Op, indent: usize, compose: bool) -> String {
     };
     match op {
         Op::Compose(filters) => ff(filters, "", indent),
+        Op::Named(name, _) if names.contains(name) => format!(":~{}", name),
+        Op::Named(name, f) => match to_op(opt::simplify(opt::flatten(*f))) {
+            Op::Compose(filters) => ff(&filters, &format!("~{}", name), indent),
+            f => format!(":~{}[{}]", name, pretty2(&f, indent, false, names)),
+        },
+        Op::Globs(patterns) => {
+            let ind2 = std::cmp::max(indent, 4);
+            let i = format!("\n{}", " ".repeat(ind2));
+            let joined = patterns
+                .iter()
+                .map(|p| glob_spec(p))
+                .collect::<Vec<_>>()
+                .join(&i);
+            format!(":[{}{}\n{}]", &i, joined, " ".repeat(ind2 - 4))
+        }
         Op::Subtract(af, bf) => ff(&vec![*af, *bf], "subtract", indent + 4),
         Op::Exclude(bf) => match to_op(*bf) {
             Op::Compose(filters) => ff(&filters, "exclude", indent),
-            b => format!(":exclude[{}]", pretty2(&b, indent, false)),
+            b => format!(":exclude[{}]", pretty2(&b, indent, false, names)),
         },
         Op::Chain(a, b) => match (to_op(*a), to_op(*b)) {
             (Op::Subdir(p1), Op::Prefix(p2)) if p1 == p2 => {
@@ -216,13 +381,13 @@ fn pretty2(op: &Op, indent: usize, compose: bool) -> String {
                 format!(
                     "{} = {}",
                     parse::quote_if(&p.to_string_lossy()),
-                    pretty2(&a, indent, false)
+                    pretty2(&a, indent, false, names)
                 )
             }
             (a, b) => format!(
                 "{}{}",
-                pretty2(&a, indent, false),
-                pretty2(&b, indent, false)
+                pretty2(&a, indent, false, names),
+                pretty2(&b, indent, false, names)
             ),
         },
         Op::RegexReplace(replacements) => {
@@ -239,6 +404,27 @@ fn pretty2(op: &Op, indent: usize, compose: bool) -> String {
                 .collect::<Vec<_>>();
             format!(":replace(\n{}\n)", v.join("\n"))
         }
+        Op::MessageReplace(replacements) => {
+            let v = replacements
+                .iter()
+                .map(|(regex, r)| {
+                    format!(
+                        "{}{}:{}",
+                        " ".repeat(indent),
+                        parse::quote(&regex.to_string()),
+                        parse::quote(r)
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!(":message_replace(\n{}\n)", v.join("\n"))
+        }
+        Op::Mailmap(entries) => {
+            let v = entries
+                .iter()
+                .map(|e| format!("{}{}", " ".repeat(indent), parse::quote(&e.to_string())))
+                .collect::<Vec<_>>();
+            format!(":mailmap(\n{}\n)", v.join("\n"))
+        }
         Op::Squash(Some(ids)) => {
             let mut v = ids
                 .iter()
@@ -259,6 +445,7 @@ fn nesting2(op: &Op) -> usize {
     match op {
         Op::Compose(filters) => 1 + filters.iter().map(|f| nesting(*f)).fold(0, |a, b| a.max(b)),
         Op::Exclude(filter) => 1 + nesting(*filter),
+        Op::Named(_, filter) => 1 + nesting(*filter),
         Op::Workspace(_) => usize::MAX / 2, // divide by 2 to make sure there is enough headroom to avoid overflows
         Op::Chain(a, b) => 1 + nesting(*a).max(nesting(*b)),
         Op::Subtract(a, b) => 1 + nesting(*a).max(nesting(*b)),
@@ -276,10 +463,24 @@ fn nesting2(op: &Op) -> usize {
                 .max()
                 .unwrap_or(0)
         }
+        // The subdirectory filter has to be applied first, see `apply_to_commit2`
+        Op::Submodule(_, _) => 1,
         _ => 0,
     }
 }
 
+/// Whether `filter` contains a `:submodule` outside of `:rev` or `:join`. Those filters
+/// need whole commits and can't be applied to trees.
+pub(crate) fn uses_submodule(filter: Filter) -> bool {
+    match to_op(filter) {
+        Op::Submodule(_, _) => true,
+        Op::Chain(a, b) | Op::Subtract(a, b) => uses_submodule(a) || uses_submodule(b),
+        Op::Compose(filters) => filters.into_iter().any(uses_submodule),
+        Op::Exclude(filter) | Op::Named(_, filter) => uses_submodule(filter),
+        _ => false,
+    }
+}
+
 pub fn lazy_refs(filter: Filter) -> Vec<String> {
     lazy_refs2(&to_op(filter))
 }
@@ -296,6 +497,7 @@ fn lazy_refs2(op: &Op) -> Vec<String> {
                 })
         }
         Op::Exclude(filter) => lazy_refs(*filter),
+        Op::Named(_, filter) => lazy_refs(*filter),
         Op::Chain(a, b) => {
             let mut av = lazy_refs(*a);
             av.append(&mut lazy_refs(*b));
@@ -349,6 +551,7 @@ fn resolve_refs2(refs: &std::collections::HashMap<String, git2::Oid>, op: &Op) -
                 .collect(),
         ),
         Op::Exclude(filter) => Op::Exclude(resolve_refs(refs, *filter)),
+        Op::Named(name, filter) => Op::Named(name.clone(), resolve_refs(refs, *filter)),
         Op::Chain(a, b) => Op::Chain(resolve_refs(refs, *a), resolve_refs(refs, *b)),
         Op::Subtract(a, b) => Op::Subtract(resolve_refs(refs, *a), resolve_refs(refs, *b)),
         Op::Rev(filters) => {
@@ -415,6 +618,23 @@ pub fn spec(filter: Filter) -> String {
     spec2(&to_op(filter))
 }
 
+// Spec of a single pattern inside a pattern list, keeping the negation unquoted
+fn glob_spec(pattern: &str) -> String {
+    match pattern.strip_prefix('!') {
+        Some(pattern) => format!("::!{}", parse::quote_if(pattern)),
+        None => positive_glob_spec(pattern),
+    }
+}
+
+// Without a "*" the pattern would be parsed as a file
+fn positive_glob_spec(pattern: &str) -> String {
+    if pattern.contains('*') {
+        format!("::{}", parse::quote_if(pattern))
+    } else {
+        format!(":glob={}", parse::quote_if(pattern))
+    }
+}
+
 fn spec2(op: &Op) -> String {
     match op {
         Op::Compose(filters) => {
@@ -433,6 +653,18 @@ fn spec2(op: &Op) -> String {
         Op::Exclude(b) => {
             format!(":exclude[{}]", spec(*b))
         }
+        Op::Named(name, f) => match to_op(*f) {
+            Op::Compose(filters) => format!(
+                ":~{}[{}]",
+                name,
+                filters
+                    .iter()
+                    .map(|x| spec(*x))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            _ => format!(":~{}[{}]", name, spec(*f)),
+        },
         Op::Rev(filters) => {
             let mut v = filters
                 .iter()
@@ -452,6 +684,9 @@ fn spec2(op: &Op) -> String {
         Op::Workspace(path) => {
             format!(":workspace={}", parse::quote_if(&path.to_string_lossy()))
         }
+        Op::Sparse(path) => {
+            format!(":sparse={}", parse::quote_if(&path.to_string_lossy()))
+        }
         Op::RegexReplace(replacements) => {
             let v = replacements
                 .iter()
@@ -461,6 +696,25 @@ fn spec2(op: &Op) -> String {
                 .collect::<Vec<_>>();
             format!(":replace({})", v.join(","))
         }
+        Op::MessageReplace(replacements) => {
+            let v = replacements
+                .iter()
+                .map(|(regex, r)| {
+                    format!("{}:{}", parse::quote(&regex.to_string()), parse::quote(r))
+                })
+                .collect::<Vec<_>>();
+            format!(":message_replace({})", v.join(","))
+        }
+        Op::Mailmap(entries) => {
+            let v = entries
+                .iter()
+                .map(|e| parse::quote(&e.to_string()))
+                .collect::<Vec<_>>();
+            format!(":mailmap({})", v.join(","))
+        }
+        Op::MailmapFile(path) => {
+            format!(":mailmap={}", parse::quote_if(&path.to_string_lossy()))
+        }
 
         Op::Chain(a, b) => match (to_op(*a), to_op(*b)) {
             (Op::Subdir(p1), Op::Prefix(p2)) if p1 == p2 => {
@@ -489,7 +743,24 @@ fn spec2(op: &Op) -> String {
         Op::Subdir(path) => format!(":/{}", parse::quote_if(&path.to_string_lossy())),
         Op::File(path) => format!("::{}", parse::quote_if(&path.to_string_lossy())),
         Op::Prefix(path) => format!(":prefix={}", parse::quote_if(&path.to_string_lossy())),
-        Op::Glob(pattern) => format!("::{}", parse::quote_if(pattern)),
+        Op::Follow(path) => format!(":follow={}", parse::quote_if(&path.to_string_lossy())),
+        Op::Glob(pattern) => positive_glob_spec(pattern),
+        Op::Globs(patterns) => format!(
+            ":[{}]",
+            patterns
+                .iter()
+                .map(|p| glob_spec(p))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Op::MaxSize(size) => format!(":maxsize={}", parse::format_size(*size)),
+        Op::ExcludeBinary => ":exclude_binary".to_string(),
+        Op::InlineSubmodules => ":inline_submodules".to_string(),
+        Op::Submodule(path, url) => format!(
+            ":submodule={};{}",
+            parse::quote_if(&path.to_string_lossy()),
+            parse::quote(url)
+        ),
         Op::Author(author, email) => {
             format!(":author={};{}", parse::quote(author), parse::quote(email))
         }
@@ -513,8 +784,10 @@ pub fn src_path(filter: Filter) -> std::path::PathBuf {
 fn src_path2(op: &Op) -> std::path::PathBuf {
     normalize_path(&match op {
         Op::Subdir(path) => path.to_owned(),
+        Op::Follow(path) => path.to_owned(),
         Op::File(path) => path.to_owned(),
         Op::Chain(a, b) => src_path(*a).join(src_path(*b)),
+        Op::Named(_, filter) => src_path(*filter),
         _ => std::path::PathBuf::new(),
     })
 }
@@ -528,6 +801,7 @@ fn dst_path2(op: &Op) -> std::path::PathBuf {
         Op::Prefix(path) => path.to_owned(),
         Op::File(path) => path.to_owned(),
         Op::Chain(a, b) => dst_path(*b).join(dst_path(*a)),
+        Op::Named(_, filter) => dst_path(*filter),
         _ => std::path::PathBuf::new(),
     })
 }
@@ -539,9 +813,9 @@ pub fn apply_to_commit(
     commit: &git2::Commit,
     transaction: &cache::Transaction,
 ) -> JoshResult<git2::Oid> {
-    let filter = opt::optimize(filter);
+    let filter = opt::optimize(try_to_op(filter).map(|_| filter)?);
     loop {
-        let filtered = apply_to_commit2(&to_op(filter), commit, transaction)?;
+        let filtered = apply_to_commit2(&try_to_op(filter)?, commit, transaction)?;
 
         if let Some(id) = filtered {
             return Ok(id);
@@ -585,37 +859,95 @@ fn resolve_workspace_redirect<'a>(
     }
 }
 
+/// Directory in the root of a tree that contains shared filter definitions.
+/// The file `.josh/name.josh` can be referenced as `:~name` from workspaces.
+const DEFINITIONS_DIR: &str = ".josh";
+
+fn get_definitions(repo: &git2::Repository, tree: &git2::Tree) -> parse::Definitions {
+    let dir = ok_or!(
+        tree.get_path(Path::new(DEFINITIONS_DIR))
+            .and_then(|entry| repo.find_tree(entry.id())),
+        {
+            return parse::Definitions::default();
+        }
+    );
+
+    parse::Definitions::new(
+        dir.iter()
+            .filter_map(|entry| {
+                let name = entry.name()?.strip_suffix(".josh")?.to_string();
+                let blob = tree::get_blob(repo, &dir, Path::new(entry.name()?));
+                Some((name, blob))
+            })
+            .collect(),
+    )
+}
+
+fn read_workspace(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    path: &Path,
+    definitions: &parse::Definitions,
+) -> Filter {
+    let ws_blob = tree::get_blob(repo, tree, &normalize_path(&path.join("workspace.josh")));
+    let f = parse::parse_with_definitions(&ws_blob, definitions)
+        .unwrap_or_else(|_| to_filter(Op::Empty));
+
+    if invert(f).is_ok() {
+        f
+    } else {
+        to_filter(Op::Empty)
+    }
+}
+
 fn get_workspace<'a>(repo: &'a git2::Repository, tree: &'a git2::Tree<'a>, path: &Path) -> Filter {
     let ws_path = normalize_path(&path.join("workspace.josh"));
     let ws_id = ok_or!(tree.get_path(&ws_path), {
         return to_filter(Op::Empty);
     })
     .id();
-    let ws_blob = tree::get_blob(repo, tree, &ws_path);
+
+    // The result also depends on the shared definitions
+    let key = match tree.get_path(Path::new(DEFINITIONS_DIR)) {
+        Ok(entry) => ok_or!(
+            git2::Oid::hash_object(
+                git2::ObjectType::Blob,
+                format!("{}:{}", ws_id, entry.id()).as_bytes()
+            ),
+            {
+                return to_filter(Op::Empty);
+            }
+        ),
+        Err(_) => ws_id,
+    };
 
     let mut workspaces = WORKSPACES.lock().unwrap();
 
-    if let Some(f) = workspaces.get(&ws_id) {
+    if let Some(f) = workspaces.get(&key) {
         *f
     } else {
-        let f = parse::parse(&ws_blob).unwrap_or_else(|_| to_filter(Op::Empty));
-
-        let f = if invert(f).is_ok() {
-            f
-        } else {
-            to_filter(Op::Empty)
-        };
-        workspaces.insert(ws_id, f);
+        let f = read_workspace(repo, tree, path, &get_definitions(repo, tree));
+        workspaces.put(key, f);
         f
     }
 }
 
+// Build a filter from the patterns in a sparse-checkout file
+fn get_sparse(repo: &git2::Repository, tree: &git2::Tree, path: &Path) -> Filter {
+    let patterns = patterns::from_sparse_checkout(&tree::get_blob(repo, tree, path));
+    if patterns.is_empty() {
+        to_filter(Op::Empty)
+    } else {
+        to_filter(Op::Globs(patterns))
+    }
+}
+
 pub fn apply_to_commit3(
     filter: Filter,
     commit: &git2::Commit,
     transaction: &cache::Transaction,
 ) -> JoshResult<bool> {
-    Ok(apply_to_commit2(&to_op(filter), commit, transaction)?.is_some())
+    Ok(apply_to_commit2(&try_to_op(filter)?, commit, transaction)?.is_some())
 }
 
 fn apply_to_commit2(
@@ -640,6 +972,7 @@ fn apply_to_commit2(
                 Ok(Some(git2::Oid::zero()))
             };
         }
+        Op::Named(_, f) => return apply_to_commit2(&to_op(*f), commit, transaction),
         Op::Squash(None) => {
             return Some(history::rewrite_commit(
                 repo,
@@ -904,6 +1237,42 @@ fn apply_to_commit2(
             ))
             .transpose();
         }
+        Op::Follow(path) => {
+            let tree = commit.tree()?;
+            let filtered_parent_ids = commit
+                .parents()
+                .map(|parent| {
+                    let parent_tree = parent.tree()?;
+                    // When `path` first appears in this commit, select the directory it was
+                    // renamed from for the history before it.
+                    let parent_filter =
+                        if tree.get_path(path).is_ok() && parent_tree.get_path(path).is_err() {
+                            tree::rename_source(repo, &parent_tree, &tree, path)?
+                                .map(|source| to_filter(Op::Follow(source)))
+                                .unwrap_or(filter)
+                        } else {
+                            filter
+                        };
+                    Ok(transaction.get(parent_filter, parent.id()))
+                })
+                .collect::<JoshResult<Option<Vec<_>>>>()?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            return Some(history::create_filtered_commit(
+                commit,
+                filtered_parent_ids,
+                RewriteData {
+                    tree: apply(transaction, filter, tree)?,
+                    author: None,
+                    committer: None,
+                    message: None,
+                },
+                transaction,
+                filter,
+            ))
+            .transpose();
+        }
         Op::Fold => {
             let filtered_parent_ids = commit
                 .parents()
@@ -953,12 +1322,53 @@ fn apply_to_commit2(
                 &std::collections::HashMap::<String, &dyn strfmt::DisplayStr>::new(),
             )?),
         },
-        _ => RewriteData {
-            tree: apply(transaction, filter, commit.tree()?)?,
-            message: None,
+        Op::Mailmap(_) | Op::MailmapFile(_) => {
+            let tree = commit.tree()?;
+            let entries = mailmap_entries(repo, op, &tree);
+            RewriteData {
+                author: map_signature(&entries, &commit.author(), mailmap::resolve),
+                committer: map_signature(&entries, &commit.committer(), mailmap::resolve),
+                tree,
+                message: None,
+            }
+        }
+        Op::Submodule(path, url) => {
+            // Filtering the subdirectory is tracked as a missing dependency by the
+            // transaction, so its history gets walked first
+            let sub = some_or!(
+                transaction.get(to_filter(Op::Subdir(path.clone())), commit.id()),
+                {
+                    return Ok(None);
+                }
+            );
+            RewriteData {
+                tree: submodules::replace_with_gitlink(repo, commit.tree()?, path, url, sub)?,
+                author: None,
+                committer: None,
+                message: None,
+            }
+        }
+        Op::MessageReplace(replacements) => RewriteData {
+            tree: commit.tree()?,
             author: None,
             committer: None,
+            message: commit.message_raw().map(|m| {
+                replacements
+                    .iter()
+                    .fold(m.to_owned(), |m, (regex, replacement)| {
+                        regex.replacen(&m, 0, replacement).into_owned()
+                    })
+            }),
         },
+        _ => {
+            let (author, committer) = apply_mailmap(transaction, filter, commit)?;
+            RewriteData {
+                tree: apply(transaction, filter, commit.tree()?)?,
+                message: None,
+                author,
+                committer,
+            }
+        }
     };
 
     let filtered_parent_ids = {
@@ -987,7 +1397,27 @@ pub fn apply<'a>(
     filter: Filter,
     tree: git2::Tree<'a>,
 ) -> JoshResult<git2::Tree<'a>> {
-    apply2(transaction, &to_op(filter), tree)
+    apply2(transaction, &try_to_op(filter)?, tree)
+}
+
+fn apply_patterns<'a>(
+    transaction: &'a cache::Transaction,
+    tree: git2::Tree<'a>,
+    patterns: &patterns::PatternList,
+    filter: Filter,
+) -> JoshResult<git2::Tree<'a>> {
+    tree::remove_pred(
+        transaction,
+        "",
+        tree.id(),
+        &|path, entry| match entry.kind() {
+            Some(git2::ObjectType::Blob) => patterns.matches_file(path),
+            Some(git2::ObjectType::Tree) => patterns.matches_dir(path),
+            _ => false,
+        },
+        true,
+        filter.id(),
+    )
 }
 
 fn apply2<'a>(
@@ -1002,6 +1432,9 @@ fn apply2<'a>(
         Op::Fold => Ok(tree),
         Op::Squash(None) => Ok(tree),
         Op::Message(_) => Ok(tree),
+        Op::MessageReplace(_) => Ok(tree),
+        Op::Mailmap(_) => Ok(tree),
+        Op::MailmapFile(_) => Ok(tree),
         Op::Author(_, _) => Ok(tree),
         Op::Committer(_, _) => Ok(tree),
         Op::Squash(Some(_)) => Err(josh_error("not applicable to tree")),
@@ -1009,6 +1442,9 @@ fn apply2<'a>(
         Op::Unsign => Ok(tree),
         Op::Rev(_) => Err(josh_error("not applicable to tree")),
         Op::Join(_) => Err(josh_error("not applicable to tree")),
+        Op::Submodule(_, _) => Err(josh_error(
+            ":submodule can only be applied to commits, not to trees",
+        )),
         Op::RegexReplace(replacements) => {
             let mut t = tree;
             for (regex, replacement) in replacements {
@@ -1018,20 +1454,44 @@ fn apply2<'a>(
         }
 
         Op::Glob(pattern) => {
-            let pattern = glob::Pattern::new(pattern)?;
-            let options = glob::MatchOptions {
-                case_sensitive: true,
-                require_literal_separator: true,
-                require_literal_leading_dot: true,
-            };
+            let patterns = patterns::PatternList::new(&[pattern.clone()])?;
+            apply_patterns(transaction, tree, &patterns, to_filter(op.clone()))
+        }
+        Op::Globs(patterns) => {
+            let patterns = patterns::PatternList::new(patterns)?;
+            apply_patterns(transaction, tree, &patterns, to_filter(op.clone()))
+        }
+        Op::MaxSize(size) => {
+            let odb = repo.odb()?;
             tree::remove_pred(
                 transaction,
                 "",
                 tree.id(),
-                &|path, isblob| isblob && (pattern.matches_path_with(path, options)),
+                &|_, entry| {
+                    entry.kind() == Some(git2::ObjectType::Blob)
+                        && odb
+                            .read_header(entry.id())
+                            .map(|(len, _)| len <= *size)
+                            .unwrap_or(false)
+                },
+                false,
                 to_filter(op.clone()).id(),
             )
         }
+        Op::ExcludeBinary => tree::remove_pred(
+            transaction,
+            "",
+            tree.id(),
+            &|_, entry| {
+                entry.kind() == Some(git2::ObjectType::Blob)
+                    && repo
+                        .find_blob(entry.id())
+                        .map(|blob| !blob.is_binary())
+                        .unwrap_or(false)
+            },
+            false,
+            to_filter(op.clone()).id(),
+        ),
         Op::File(path) => {
             let (file, mode) = tree
                 .get_path(path)
@@ -1040,7 +1500,7 @@ fn apply2<'a>(
             tree::insert(repo, &tree::empty(repo), path, file, mode)
         }
 
-        Op::Subdir(path) => {
+        Op::Subdir(path) | Op::Follow(path) => {
             return Ok(tree
                 .get_path(path)
                 .and_then(|x| repo.find_tree(x.id()))
@@ -1081,6 +1541,9 @@ fn apply2<'a>(
             )
         }
 
+        Op::Sparse(path) => apply(transaction, get_sparse(repo, &tree, path), tree),
+        Op::InlineSubmodules => submodules::inline(transaction, tree),
+
         Op::Compose(filters) => {
             let filtered: Vec<_> = filters
                 .iter()
@@ -1093,6 +1556,7 @@ fn apply2<'a>(
         Op::Chain(a, b) => {
             return apply(transaction, *b, apply(transaction, *a, tree)?);
         }
+        Op::Named(_, f) => apply(transaction, *f, tree),
     }
 }
 
@@ -1103,6 +1567,28 @@ pub fn unapply<'a>(
     filter: Filter,
     tree: git2::Tree<'a>,
     parent_tree: git2::Tree<'a>,
+) -> JoshResult<git2::Tree<'a>> {
+    unapply2(transaction, filter, tree, parent_tree, None)
+}
+
+/// Like `unapply` for the tree of `commit`. Commits that need to be created
+/// in submodules take their author, committer and message from `commit`.
+pub fn unapply_commit<'a>(
+    transaction: &'a cache::Transaction,
+    filter: Filter,
+    commit: &git2::Commit,
+    parent_tree: git2::Tree<'a>,
+) -> JoshResult<git2::Tree<'a>> {
+    let tree = transaction.repo().find_tree(commit.tree_id())?;
+    unapply2(transaction, filter, tree, parent_tree, Some(commit))
+}
+
+fn unapply2<'a>(
+    transaction: &'a cache::Transaction,
+    filter: Filter,
+    tree: git2::Tree<'a>,
+    parent_tree: git2::Tree<'a>,
+    commit: Option<&git2::Commit>,
 ) -> JoshResult<git2::Tree<'a>> {
     if let Ok(inverted) = invert(filter) {
         let filtered = apply(transaction, invert(inverted)?, parent_tree.clone())?;
@@ -1117,6 +1603,21 @@ pub fn unapply<'a>(
         )?)?);
     }
 
+    if let Op::Sparse(path) = to_op(filter) {
+        // The patterns are taken from the original tree, as the filtered one
+        // might not contain the file
+        let sparse = get_sparse(transaction.repo(), &parent_tree, &path);
+        return unapply2(transaction, sparse, tree, parent_tree, commit);
+    }
+
+    if let Op::InlineSubmodules = to_op(filter) {
+        return submodules::unapply(transaction, tree, parent_tree, commit);
+    }
+
+    if let Op::Submodule(path, url) = to_op(filter) {
+        return submodules::unapply_gitlink(transaction.repo(), tree, &path, &url);
+    }
+
     if let Some(ws) = unapply_workspace(
         transaction,
         &to_op(filter),
@@ -1135,11 +1636,12 @@ pub fn unapply<'a>(
         };
         let filtered_parent_tree = apply(transaction, a_normalized, parent_tree.clone())?;
 
-        return unapply(
+        return unapply2(
             transaction,
             a,
-            unapply(transaction, b, tree, filtered_parent_tree)?,
+            unapply2(transaction, b, tree, filtered_parent_tree, commit)?,
             parent_tree,
+            commit,
         );
     }
 
@@ -1154,8 +1656,11 @@ fn unapply_workspace<'a>(
 ) -> JoshResult<Option<git2::Tree<'a>>> {
     return match op {
         Op::Workspace(path) => {
-            let tree = pre_process_tree(transaction.repo(), tree)?;
-            let workspace = get_workspace(transaction.repo(), &tree, Path::new(""));
+            // References to shared definitions are resolved using the original tree,
+            // as the filtered one does not necessarily contain them
+            let definitions = get_definitions(transaction.repo(), &parent_tree);
+            let tree = pre_process_tree(transaction.repo(), tree, &definitions)?;
+            let workspace = read_workspace(transaction.repo(), &tree, Path::new(""), &definitions);
             let original_workspace = get_workspace(transaction.repo(), &parent_tree, path);
 
             let root = to_filter(Op::Subdir(path.to_owned()));
@@ -1183,10 +1688,11 @@ fn unapply_workspace<'a>(
 fn pre_process_tree<'a>(
     repo: &'a git2::Repository,
     tree: git2::Tree<'a>,
+    definitions: &parse::Definitions,
 ) -> JoshResult<git2::Tree<'a>> {
     let path = Path::new("workspace.josh");
     let ws_file = tree::get_blob(repo, &tree, path);
-    let parsed = filter::parse(&ws_file)?;
+    let parsed = parse::parse_with_definitions(&ws_file, definitions)?;
 
     if invert(parsed).is_err() {
         return Err(josh_error("Invalid workspace: not reversible"));
@@ -1198,7 +1704,11 @@ fn pre_process_tree<'a>(
             blob = c;
         }
     }
-    let blob = &format!("{}{}\n", &blob, pretty(parsed, 0));
+    let blob = &format!(
+        "{}{}\n",
+        &blob,
+        pretty_with_shared(parsed, 0, &definitions.shared())
+    );
 
     let tree = tree::insert(
         repo,
@@ -1211,6 +1721,119 @@ fn pre_process_tree<'a>(
     Ok(tree)
 }
 
+/// Name and email of an author or committer
+type Identity = (String, String);
+
+fn mailmap_entries(repo: &git2::Repository, op: &Op, tree: &git2::Tree) -> Vec<mailmap::Entry> {
+    match op {
+        Op::Mailmap(entries) => entries.clone(),
+        Op::MailmapFile(path) => mailmap::read(&tree::get_blob(repo, tree, path)),
+        _ => vec![],
+    }
+}
+
+fn map_signature(
+    entries: &[mailmap::Entry],
+    signature: &git2::Signature,
+    f: fn(&[mailmap::Entry], &str, &str) -> Identity,
+) -> Option<Identity> {
+    Some(f(entries, signature.name()?, signature.email()?))
+}
+
+fn uses_mailmap(filter: Filter) -> bool {
+    match to_op(filter) {
+        Op::Mailmap(_) | Op::MailmapFile(_) => true,
+        Op::Chain(a, b) | Op::Subtract(a, b) => uses_mailmap(a) || uses_mailmap(b),
+        Op::Compose(filters) => filters.into_iter().any(uses_mailmap),
+        Op::Exclude(filter) | Op::Named(_, filter) => uses_mailmap(filter),
+        _ => false,
+    }
+}
+
+/// Collect the mailmap tables used by `filter` in the order they get applied.
+fn mailmap_tables(
+    transaction: &cache::Transaction,
+    filter: Filter,
+    tree: git2::Tree,
+) -> JoshResult<Vec<Vec<mailmap::Entry>>> {
+    let op = to_op(filter);
+    match op {
+        Op::Mailmap(_) | Op::MailmapFile(_) => {
+            Ok(vec![mailmap_entries(transaction.repo(), &op, &tree)])
+        }
+        Op::Chain(a, b) => {
+            let mut tables = mailmap_tables(transaction, a, tree.clone())?;
+            if uses_mailmap(b) {
+                tables.append(&mut mailmap_tables(
+                    transaction,
+                    b,
+                    apply(transaction, a, tree)?,
+                )?);
+            }
+            Ok(tables)
+        }
+        // All parts of a composition see the same input tree
+        Op::Compose(filters) => Ok(filters
+            .into_iter()
+            .map(|f| mailmap_tables(transaction, f, tree.clone()))
+            .collect::<JoshResult<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect()),
+        Op::Subtract(a, b) => {
+            let mut tables = mailmap_tables(transaction, a, tree.clone())?;
+            tables.append(&mut mailmap_tables(transaction, b, tree)?);
+            Ok(tables)
+        }
+        Op::Exclude(filter) | Op::Named(_, filter) => mailmap_tables(transaction, filter, tree),
+        _ => Ok(vec![]),
+    }
+}
+
+/// Calculate the author and committer of `commit` after applying the mailmap tables
+/// used inside of `filter`, like in the parts of a composition.
+fn apply_mailmap(
+    transaction: &cache::Transaction,
+    filter: Filter,
+    commit: &git2::Commit,
+) -> JoshResult<(Option<Identity>, Option<Identity>)> {
+    if !uses_mailmap(filter) {
+        return Ok((None, None));
+    }
+    let tables = mailmap_tables(transaction, filter, commit.tree()?)?;
+    let map = |signature: &git2::Signature| {
+        let mut identity = (signature.name()?.to_owned(), signature.email()?.to_owned());
+        for entries in tables.iter() {
+            identity = mailmap::resolve(entries, &identity.0, &identity.1);
+        }
+        Some(identity)
+    };
+    Ok((map(&commit.author()), map(&commit.committer())))
+}
+
+/// Calculate the author and committer of the unfiltered version of `commit`,
+/// using the inverse of the mailmap tables used by `filter`.
+/// `tree` is the unfiltered tree the tables are read from.
+pub fn unapply_mailmap(
+    transaction: &cache::Transaction,
+    filter: Filter,
+    commit: &git2::Commit,
+    tree: &git2::Tree,
+) -> JoshResult<(Option<Identity>, Option<Identity>)> {
+    if !uses_mailmap(filter) {
+        return Ok((None, None));
+    }
+    let tables = mailmap_tables(transaction, filter, tree.clone())?;
+    let unmap = |signature: &git2::Signature| {
+        let mut identity = (signature.name()?.to_owned(), signature.email()?.to_owned());
+        for entries in tables.iter().rev() {
+            identity = mailmap::unresolve(entries, &identity.0, &identity.1);
+        }
+        Some(identity)
+    };
+    Ok((unmap(&commit.author()), unmap(&commit.committer())))
+}
+
 /// Create a filter that is the result of feeding the output of `first` into `second`
 pub fn chain(first: Filter, second: Filter) -> Filter {
     opt::optimize(to_filter(Op::Chain(first, second)))
@@ -1236,7 +1859,10 @@ pub fn compute_warnings<'a>(
             &tree,
             &path.join(Path::new("workspace.josh")),
         );
-        if let Ok(res) = parse(workspace_filter) {
+        if let Ok(res) = parse::parse_with_definitions(
+            workspace_filter,
+            &get_definitions(transaction.repo(), &tree),
+        ) {
             filter = res;
         } else {
             warnings.push("couldn't parse workspace\n".to_string());
@@ -1295,25 +1921,22 @@ fn is_ancestor_of(
     tip: git2::Oid,
 ) -> Result<bool, git2::Error> {
     let mut ancestor_cache = ANCESTORS.lock().unwrap();
-    let ancestors = match ancestor_cache.entry(tip) {
-        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
-        std::collections::hash_map::Entry::Vacant(entry) => {
-            tracing::trace!("is_ancestor_of tip={tip}");
-            // Recursively compute all ancestors of `tip`.
-            // Invariant: Everything in `todo` is also in `ancestors`.
-            let mut todo = vec![tip];
-            let mut ancestors = std::collections::HashSet::from_iter(todo.iter().copied());
-            while let Some(commit) = todo.pop() {
-                for parent in repo.find_commit(commit)?.parent_ids() {
-                    if ancestors.insert(parent) {
-                        // Newly inserted! Also handle its parents.
-                        todo.push(parent);
-                    }
+    let ancestors = ancestor_cache.try_get_or_insert(tip, || {
+        tracing::trace!("is_ancestor_of tip={tip}");
+        // Recursively compute all ancestors of `tip`.
+        // Invariant: Everything in `todo` is also in `ancestors`.
+        let mut todo = vec![tip];
+        let mut ancestors = std::collections::HashSet::from_iter(todo.iter().copied());
+        while let Some(commit) = todo.pop() {
+            for parent in repo.find_commit(commit)?.parent_ids() {
+                if ancestors.insert(parent) {
+                    // Newly inserted! Also handle its parents.
+                    todo.push(parent);
                 }
             }
-            entry.insert(ancestors)
         }
-    };
+        Ok::<_, git2::Error>(ancestors)
+    })?;
     Ok(ancestors.contains(&commit))
 }
 
@@ -1344,6 +1967,32 @@ mod tests {
             dst_path(parse(":[a=:/x::y/,a/b=:/i]:prefix=c").unwrap())
         );
     }
+
+    #[test]
+    fn evicted_filter_test() {
+        let fill = |name: &str| {
+            for i in 0..2 * FILTER_CACHE_SIZE {
+                parse(&format!(":/{}{}", name, i)).unwrap();
+            }
+        };
+
+        // Without a cache there is no registry, so filters are kept
+        let pinned = parse(":/pinned").unwrap();
+        fill("a");
+        assert_eq!(spec(pinned), ":/pinned");
+
+        // Evicted filters are loaded from the registry again
+        cache::load_backend(Path::new("/nonexistent"), cache::BackendKind::Memory).unwrap();
+        assert!(PINNED.lock().unwrap().is_empty());
+        let evicted = parse(":/evicted").unwrap();
+        fill("b");
+        assert!(!FILTERS.lock().unwrap().contains(&evicted));
+        assert_eq!(spec(pinned), ":/pinned");
+        assert_eq!(spec(evicted), ":/evicted");
+
+        let unknown = Filter(git2::Oid::hash_object(git2::ObjectType::Blob, b"x").unwrap());
+        assert!(try_to_op(unknown).is_err());
+    }
 }
 
 pub fn is_linear(filter: Filter) -> bool {
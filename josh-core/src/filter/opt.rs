@@ -6,12 +6,12 @@
 use super::*;
 
 lazy_static! {
-    static ref OPTIMIZED: std::sync::Mutex<std::collections::HashMap<Filter, Filter>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-    static ref INVERTED: std::sync::Mutex<std::collections::HashMap<Filter, Filter>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-    static ref SIMPLIFIED: std::sync::Mutex<std::collections::HashMap<Filter, Filter>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
+    static ref OPTIMIZED: std::sync::Mutex<lru::LruCache<Filter, Filter>> =
+        std::sync::Mutex::new(lru::LruCache::new(cache_size()));
+    static ref INVERTED: std::sync::Mutex<lru::LruCache<Filter, Filter>> =
+        std::sync::Mutex::new(lru::LruCache::new(cache_size()));
+    static ref SIMPLIFIED: std::sync::Mutex<lru::LruCache<Filter, Filter>> =
+        std::sync::Mutex::new(lru::LruCache::new(cache_size()));
 }
 
 /*
@@ -35,7 +35,7 @@ pub fn optimize(filter: Filter) -> Filter {
         }
     };
 
-    OPTIMIZED.lock().unwrap().insert(original, result);
+    OPTIMIZED.lock().unwrap().put(original, result);
     result
 }
 
@@ -79,6 +79,7 @@ pub fn simplify(filter: Filter) -> Filter {
             Op::Subtract(simplify(to_filter(a)), simplify(to_filter(b)))
         }
         Op::Exclude(b) => Op::Exclude(simplify(b)),
+        Op::Named(name, f) => Op::Named(name, simplify(f)),
         _ => to_op(filter),
     });
 
@@ -88,7 +89,7 @@ pub fn simplify(filter: Filter) -> Filter {
         simplify(result)
     };
 
-    SIMPLIFIED.lock().unwrap().insert(original, r);
+    SIMPLIFIED.lock().unwrap().put(original, r);
     r
 }
 
@@ -112,6 +113,8 @@ pub fn flatten(filter: Filter) -> Filter {
             }
             Op::Compose(out.drain(..).map(flatten).collect())
         }
+        // Moving `:submodule` into a composition would apply it to trees
+        Op::Chain(af, bf) if uses_submodule(af) || uses_submodule(bf) => Op::Chain(af, bf),
         Op::Chain(af, bf) => match (to_op(af), to_op(bf)) {
             (_, Op::Compose(filters)) => {
                 let mut out = vec![];
@@ -134,6 +137,7 @@ pub fn flatten(filter: Filter) -> Filter {
             Op::Subtract(flatten(to_filter(a)), flatten(to_filter(b)))
         }
         Op::Exclude(b) => Op::Exclude(flatten(b)),
+        Op::Named(name, f) => Op::Named(name, flatten(f)),
         _ => to_op(filter),
     });
 
@@ -262,6 +266,45 @@ fn common_post(filters: &Vec<Filter>) -> Option<(Filter, Vec<Filter>)> {
     }
 }
 
+fn patterns_of(filter: Filter) -> Option<Vec<String>> {
+    match to_op(filter) {
+        Op::Glob(pattern) => Some(vec![pattern]),
+        Op::Globs(patterns) => Some(patterns),
+        _ => None,
+    }
+}
+
+/*
+ * Merge adjacent pattern filters of a composition into a single list, so the tree
+ * only has to be traversed once. Because the last matching pattern of a list decides,
+ * a list can only be appended to another if it contains no negated patterns.
+ */
+fn merge_globs(filters: &[Filter]) -> Option<Vec<Filter>> {
+    let mut out: Vec<Filter> = vec![];
+    for f in filters {
+        if let (Some(mut a), Some(b)) = (out.last().and_then(|x| patterns_of(*x)), patterns_of(*f))
+        {
+            if !b.iter().any(|p| p.starts_with('!')) {
+                let negated = a.iter().any(|p| p.starts_with('!'));
+                for p in b {
+                    if negated || !a.contains(&p) {
+                        a.push(p);
+                    }
+                }
+                *out.last_mut().unwrap() = to_filter(Op::Globs(a));
+                continue;
+            }
+        }
+        out.push(*f);
+    }
+
+    if out.len() != filters.len() {
+        Some(out)
+    } else {
+        None
+    }
+}
+
 /*
  * Apply optimization steps to a filter until it converges (no rules apply anymore)
  */
@@ -353,6 +396,8 @@ fn step(filter: Filter) -> Filter {
                         .map(|x| to_filter(Op::Compose(x)))
                         .collect(),
                 )
+            } else if let Some(merged) = merge_globs(&filters) {
+                Op::Compose(merged)
             } else {
                 let mut filters = prefix_sort(&filters);
                 Op::Compose(filters.drain(..).map(step).collect())
@@ -378,6 +423,7 @@ fn step(filter: Filter) -> Filter {
         Op::Exclude(b) if b == to_filter(Op::Nop) => Op::Empty,
         Op::Exclude(b) if b == to_filter(Op::Empty) => Op::Nop,
         Op::Exclude(b) => Op::Exclude(step(b)),
+        Op::Named(name, f) => Op::Named(name, step(f)),
         Op::Subtract(a, b) if a == b => Op::Empty,
         Op::Subtract(af, bf) => match (to_op(af), to_op(bf)) {
             (Op::Empty, _) => Op::Empty,
@@ -421,7 +467,7 @@ fn step(filter: Filter) -> Filter {
         _ => to_op(filter),
     });
 
-    OPTIMIZED.lock().unwrap().insert(original, result);
+    OPTIMIZED.lock().unwrap().put(original, result);
     result
 }
 
@@ -429,12 +475,19 @@ pub fn invert(filter: Filter) -> JoshResult<Filter> {
     let result = match to_op(filter) {
         Op::Nop => Some(Op::Nop),
         Op::Linear => Some(Op::Nop),
+        Op::MessageReplace(_) => Some(Op::Nop),
+        Op::Mailmap(_) => Some(Op::Nop),
+        Op::MailmapFile(_) => Some(Op::Nop),
         Op::Unsign => Some(Op::Unsign),
         Op::Empty => Some(Op::Empty),
         Op::Subdir(path) => Some(Op::Prefix(path)),
+        Op::Follow(path) => Some(Op::Prefix(path)),
         Op::File(path) => Some(Op::File(path)),
         Op::Prefix(path) => Some(Op::Subdir(path)),
         Op::Glob(pattern) => Some(Op::Glob(pattern)),
+        Op::Globs(patterns) => Some(Op::Globs(patterns)),
+        Op::MaxSize(size) => Some(Op::MaxSize(size)),
+        Op::ExcludeBinary => Some(Op::ExcludeBinary),
         Op::Rev(_) => Some(Op::Nop),
         _ => None,
     };
@@ -458,11 +511,12 @@ pub fn invert(filter: Filter) -> JoshResult<Filter> {
                 .collect::<JoshResult<Vec<_>>>()?,
         ),
         Op::Exclude(filter) => Op::Exclude(invert(filter)?),
+        Op::Named(_, filter) => to_op(invert(filter)?),
         _ => return Err(josh_error("no invert")),
     });
 
     let result = optimize(result);
 
-    INVERTED.lock().unwrap().insert(original, result);
+    INVERTED.lock().unwrap().put(original, result);
     Ok(result)
 }
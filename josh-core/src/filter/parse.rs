@@ -10,6 +10,14 @@ fn make_op(args: &[&str]) -> JoshResult<Op> {
         ["author", author, email] => Ok(Op::Author(author.to_string(), email.to_string())),
         ["committer", author, email] => Ok(Op::Committer(author.to_string(), email.to_string())),
         ["workspace", arg] => Ok(Op::Workspace(Path::new(arg).to_owned())),
+        ["sparse", arg] => Ok(Op::Sparse(Path::new(arg).to_owned())),
+        ["submodule", path, url] => Ok(Op::Submodule(Path::new(path).to_owned(), url.to_string())),
+        ["mailmap", arg] => Ok(Op::MailmapFile(Path::new(arg).to_owned())),
+        ["maxsize", arg] => Ok(Op::MaxSize(parse_size(arg)?)),
+        ["glob", arg] => make_presub(arg, true),
+        ["follow", arg] => Ok(Op::Follow(
+            Path::new(arg.trim_start_matches('/')).to_owned(),
+        )),
         ["prefix"] => Err(josh_error(indoc!(
             r#"
             Filter ":prefix" requires an argument.
@@ -21,6 +29,17 @@ fn make_op(args: &[&str]) -> JoshResult<Op> {
             Where `path` is path to be used as a prefix
             "#
         ))),
+        ["follow"] => Err(josh_error(indoc!(
+            r#"
+            Filter ":follow" requires an argument.
+
+            Note: use "=" to provide the argument value:
+
+              :follow=path
+
+            Where `path` is the current location of the directory to follow
+            "#
+        ))),
         ["workspace"] => Err(josh_error(indoc!(
             r#"
             Filter ":workspace" requires an argument.
@@ -32,6 +51,29 @@ fn make_op(args: &[&str]) -> JoshResult<Op> {
             Where `path` is path to the directory where workspace.josh file is located
             "#
         ))),
+        ["sparse"] => Err(josh_error(indoc!(
+            r#"
+            Filter ":sparse" requires an argument.
+
+            Note: use "=" to provide the argument value:
+
+              :sparse=path
+
+            Where `path` is path to a file in the sparse-checkout format
+            "#
+        ))),
+        ["submodule", ..] => Err(josh_error(indoc!(
+            r#"
+            Filter ":submodule" requires two arguments.
+
+            Note: use "=" to provide the argument values:
+
+              :submodule=path;"url"
+
+            Where `path` is the directory to replace and `url` is the url of the repository
+            on the proxy
+            "#
+        ))),
         ["SQUASH"] => Ok(Op::Squash(None)),
         ["SQUASH", _ids @ ..] => Err(josh_error("SQUASH with ids can't be parsed")),
         ["linear"] => Ok(Op::Linear),
@@ -40,6 +82,8 @@ fn make_op(args: &[&str]) -> JoshResult<Op> {
         ["INDEX"] => Ok(Op::Index),
         ["INVERT"] => Ok(Op::Invert),
         ["FOLD"] => Ok(Op::Fold),
+        ["exclude_binary"] => Ok(Op::ExcludeBinary),
+        ["inline_submodules"] => Ok(Op::InlineSubmodules),
         _ => Err(josh_error(
             formatdoc!(
                 r#"
@@ -57,7 +101,146 @@ fn make_op(args: &[&str]) -> JoshResult<Op> {
     }
 }
 
-fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
+const SIZE_UNITS: [(char, usize); 3] = [('G', 1 << 30), ('M', 1 << 20), ('K', 1 << 10)];
+
+/// Parse a size in bytes with an optional `K`, `M` or `G` suffix
+fn parse_size(arg: &str) -> JoshResult<usize> {
+    let invalid = || josh_error(&format!("invalid size: {:?}", arg));
+    let (number, factor) = match SIZE_UNITS
+        .iter()
+        .find(|(unit, _)| arg.to_ascii_uppercase().ends_with(*unit))
+    {
+        Some((_, factor)) => (&arg[..arg.len() - 1], *factor),
+        None => (arg, 1),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .ok_or_else(invalid)
+}
+
+/// Format a size using the largest unit that represents it exactly
+pub fn format_size(size: usize) -> String {
+    SIZE_UNITS
+        .iter()
+        .find(|(_, factor)| size != 0 && size % factor == 0)
+        .map(|(unit, factor)| format!("{}{}", size / factor, unit))
+        .unwrap_or_else(|| size.to_string())
+}
+
+fn make_presub(arg: &str, is_glob: bool) -> JoshResult<Op> {
+    if is_glob {
+        let pattern = patterns::normalize(arg);
+        glob::Pattern::new(pattern.trim_end_matches('/'))?;
+        Ok(Op::Glob(pattern))
+    } else if arg.ends_with('/') {
+        let arg = arg.trim_end_matches('/');
+        Ok(Op::Chain(
+            to_filter(Op::Subdir(std::path::PathBuf::from(arg))),
+            to_filter(make_op(&["prefix", arg])?),
+        ))
+    } else {
+        Ok(Op::File(Path::new(arg).to_owned()))
+    }
+}
+
+/// Get the argument of a filter spec of the form `::!pattern`
+fn negated_pattern(pair: &pest::iterators::Pair<Rule>) -> Option<String> {
+    let mut inner = pair.clone().into_inner();
+    let presub = match (inner.next(), inner.next()) {
+        (Some(presub), None) if presub.as_rule() == Rule::filter_presub => presub,
+        _ => return None,
+    };
+    let mut inner = presub.into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(negate), Some(arg)) if negate.as_rule() == Rule::negate => {
+            Some(unquote(arg.as_str()))
+        }
+        _ => None,
+    }
+}
+
+/// Remove the paths matched by `pattern` from all preceding entries of a composition.
+/// Entries that are patterns themselves get the negation appended, so they can still be
+/// merged into a single list by the optimizer.
+fn negate(filters: &mut [Filter], pattern: &str) -> JoshResult<()> {
+    let op = make_presub(pattern, patterns::is_pattern(pattern))?;
+    let negated = match &op {
+        Op::Glob(pattern) => pattern.clone(),
+        _ => {
+            glob::Pattern::escape(pattern.trim_end_matches('/'))
+                + if pattern.ends_with('/') { "/" } else { "" }
+        }
+    };
+    let excluded = to_filter(Op::Exclude(to_filter(op)));
+
+    for filter in filters.iter_mut() {
+        *filter = match to_op(*filter) {
+            Op::Glob(p) => to_filter(Op::Globs(vec![p, format!("!{}", negated)])),
+            Op::Globs(mut v) => {
+                v.push(format!("!{}", negated));
+                to_filter(Op::Globs(v))
+            }
+            _ => chain(*filter, excluded),
+        };
+    }
+    Ok(())
+}
+
+/// Named filters that can be referenced with `:~name`. Besides the ones introduced with
+/// `let name = ...`, there can be shared definitions which are only parsed once referenced.
+#[derive(Clone, Default)]
+pub struct Definitions {
+    filters: std::collections::HashMap<String, Filter>,
+    sources: std::sync::Arc<std::collections::BTreeMap<String, String>>,
+    resolving: Vec<String>,
+}
+
+impl Definitions {
+    /// Create definitions from the specs of shared named filters
+    pub fn new(sources: std::collections::BTreeMap<String, String>) -> Definitions {
+        Definitions {
+            sources: std::sync::Arc::new(sources),
+            ..Default::default()
+        }
+    }
+
+    fn get(&self, name: &str) -> JoshResult<Filter> {
+        if let Some(filter) = self.filters.get(name) {
+            return Ok(*filter);
+        }
+        let source = self
+            .sources
+            .get(name)
+            .ok_or_else(|| josh_error(&format!("unknown filter definition: {:?}", name)))?;
+        if self.resolving.iter().any(|n| n == name) {
+            return Err(josh_error(&format!(
+                "recursive filter definition: {:?}",
+                name
+            )));
+        }
+
+        let mut definitions = Definitions {
+            sources: self.sources.clone(),
+            resolving: self.resolving.clone(),
+            ..Default::default()
+        };
+        definitions.resolving.push(name.to_string());
+        let filter = parse_with_definitions(source, &definitions)?;
+        Ok(to_filter(Op::Named(name.to_string(), filter)))
+    }
+
+    /// All shared definitions that can be parsed
+    pub fn shared(&self) -> Vec<(String, Filter)> {
+        self.sources
+            .keys()
+            .filter_map(|name| Some((name.clone(), self.get(name).ok()?)))
+            .collect()
+    }
+}
+
+fn parse_item(pair: pest::iterators::Pair<Rule>, definitions: &Definitions) -> JoshResult<Op> {
     match pair.as_rule() {
         Rule::filter => {
             let v: Vec<_> = pair.into_inner().map(|x| unquote(x.as_str())).collect();
@@ -69,18 +252,20 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
         )),
         Rule::filter_presub => {
             let mut inner = pair.into_inner();
-            let arg = &unquote(inner.next().unwrap().as_str());
-            if arg.ends_with('/') {
-                let arg = arg.trim_end_matches('/');
-                Ok(Op::Chain(
-                    to_filter(Op::Subdir(std::path::PathBuf::from(arg))),
-                    to_filter(make_op(&["prefix", arg])?),
-                ))
-            } else if arg.contains('*') {
-                Ok(Op::Glob(arg.to_string()))
-            } else {
-                Ok(Op::File(Path::new(arg).to_owned()))
+            let arg = inner.next().unwrap();
+            if arg.as_rule() == Rule::negate {
+                return Err(josh_error(indoc!(
+                    r#"
+                    Negated patterns can only be used inside of a composition:
+
+                      :[::pattern,::!pattern]
+                    "#
+                )));
             }
+            // Paths containing "?" or "[" were always files, they are only matched as
+            // patterns with ":glob="
+            let arg = unquote(arg.as_str());
+            make_presub(&arg, arg.contains('*'))
         }
         Rule::filter_noarg => {
             let mut inner = pair.into_inner();
@@ -94,9 +279,9 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
             let v: Vec<_> = pair.into_inner().map(|x| unquote(x.as_str())).collect();
 
             match v.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
-                [args] => Ok(Op::Compose(parse_group(args)?)),
+                [args] => Ok(Op::Compose(parse_group(args, definitions)?)),
                 [cmd, args] => {
-                    let g = parse_group(args)?;
+                    let g = parse_group(args, definitions)?;
                     match *cmd {
                         "exclude" => Ok(Op::Exclude(to_filter(Op::Compose(g)))),
                         "subtract" if g.len() == 2 => Ok(Op::Subtract(g[0], g[1])),
@@ -112,7 +297,12 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
             let hm = v
                 .iter()
                 .tuples()
-                .map(|(oid, filter)| Ok((LazyRef::parse(oid)?, parse(filter)?)))
+                .map(|(oid, filter)| {
+                    Ok((
+                        LazyRef::parse(oid)?,
+                        parse_with_definitions(filter, definitions)?,
+                    ))
+                })
                 .collect::<JoshResult<_>>()?;
 
             Ok(Op::Join(hm))
@@ -123,7 +313,12 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
             let hm = v
                 .iter()
                 .tuples()
-                .map(|(oid, filter)| Ok((LazyRef::parse(oid)?, parse(filter)?)))
+                .map(|(oid, filter)| {
+                    Ok((
+                        LazyRef::parse(oid)?,
+                        parse_with_definitions(filter, definitions)?,
+                    ))
+                })
                 .collect::<JoshResult<_>>()?;
 
             Ok(Op::Rev(hm))
@@ -138,11 +333,45 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
 
             Ok(Op::RegexReplace(replacements))
         }
+        Rule::filter_message_replace => {
+            let replacements = pair
+                .into_inner()
+                .map(|x| unquote(x.as_str()))
+                .tuples()
+                .map(|(regex, replacement)| Ok((regex::Regex::new(&regex)?, replacement)))
+                .collect::<JoshResult<_>>()?;
+
+            Ok(Op::MessageReplace(replacements))
+        }
+        Rule::filter_mailmap => {
+            let entries = pair
+                .into_inner()
+                .map(|x| mailmap::Entry::parse(&unquote(x.as_str())))
+                .collect::<JoshResult<Vec<_>>>()?;
+
+            Ok(Op::Mailmap(entries.into_iter().flatten().collect()))
+        }
+        Rule::filter_named => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().unwrap().as_str();
+            match inner.next() {
+                Some(compose) => Ok(Op::Named(
+                    name.to_string(),
+                    to_filter(Op::Compose(parse_group(compose.as_str(), definitions)?)),
+                )),
+                None => Ok(to_op(definitions.get(name)?)),
+            }
+        }
         Rule::filter_squash => {
             let ids = pair
                 .into_inner()
                 .tuples()
-                .map(|(oid, filter)| Ok((LazyRef::parse(oid.as_str())?, parse(filter.as_str())?)))
+                .map(|(oid, filter)| {
+                    Ok((
+                        LazyRef::parse(oid.as_str())?,
+                        parse_with_definitions(filter.as_str(), definitions)?,
+                    ))
+                })
                 .collect::<JoshResult<_>>()?;
 
             Ok(Op::Squash(Some(ids)))
@@ -155,6 +384,7 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
 fn parse_file_entry(
     pair: pest::iterators::Pair<Rule>,
     filters: &mut Vec<Filter>,
+    definitions: &mut Definitions,
 ) -> JoshResult<()> {
     match pair.as_rule() {
         Rule::file_entry => {
@@ -164,14 +394,27 @@ fn parse_file_entry(
                 .next()
                 .map(|x| x.as_str().to_owned())
                 .unwrap_or(format!(":/{}", path));
-            let filter = parse(&filter)?;
+            let filter = parse_with_definitions(&filter, definitions)?;
             let filter = chain(filter, to_filter(Op::Prefix(Path::new(path).to_owned())));
             filters.push(filter);
             Ok(())
         }
         Rule::filter_spec => {
+            if let Some(pattern) = negated_pattern(&pair) {
+                return negate(filters, &pattern);
+            }
             let filter = pair.as_str();
-            filters.push(parse(filter)?);
+            filters.push(parse_with_definitions(filter, definitions)?);
+            Ok(())
+        }
+        Rule::definition => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let name = inner.next().unwrap().as_str().to_string();
+            let filter = parse_with_definitions(inner.next().unwrap().as_str(), definitions)?;
+            definitions
+                .filters
+                .insert(name.clone(), to_filter(Op::Named(name, filter)));
             Ok(())
         }
         Rule::EOI => Ok(()),
@@ -179,15 +422,30 @@ fn parse_file_entry(
     }
 }
 
-fn parse_group(filter_spec: &str) -> JoshResult<Vec<Filter>> {
+fn parse_group(filter_spec: &str, definitions: &Definitions) -> JoshResult<Vec<Filter>> {
     rs_tracing::trace_scoped!("parse_group");
     let mut filters = vec![];
+    let mut definitions = definitions.clone();
 
     match Grammar::parse(Rule::compose, filter_spec) {
         Ok(mut r) => {
             let r = r.next().unwrap();
             for pair in r.into_inner() {
-                parse_file_entry(pair, &mut filters)?;
+                parse_file_entry(pair, &mut filters, &mut definitions)?;
+            }
+
+            // Compositions and workspaces apply their filters to trees, but replacing a
+            // directory with a submodule needs the history of the directory
+            if filters.iter().any(|f| uses_submodule(*f)) {
+                return Err(josh_error(indoc!(
+                    r#"
+                    Filter ":submodule" can't be used inside of a composition or workspace.
+
+                    Note: chain it after the composition instead:
+
+                      :[...]:submodule=path;"url"
+                    "#
+                )));
             }
 
             Ok(filters)
@@ -202,7 +460,7 @@ fn parse_group(filter_spec: &str) -> JoshResult<Vec<Filter>> {
     }
 }
 
-fn parse_workspace(filter_spec: &str) -> JoshResult<Vec<Filter>> {
+fn parse_workspace(filter_spec: &str, definitions: &Definitions) -> JoshResult<Vec<Filter>> {
     rs_tracing::trace_scoped!("parse_workspace");
 
     match Grammar::parse(Rule::workspace_file, filter_spec) {
@@ -211,7 +469,7 @@ fn parse_workspace(filter_spec: &str) -> JoshResult<Vec<Filter>> {
             for pair in r.into_inner() {
                 match pair.as_rule() {
                     Rule::compose => {
-                        let filters = parse_group(pair.as_str())?;
+                        let filters = parse_group(pair.as_str(), definitions)?;
                         return Ok(filters);
                     }
                     Rule::workspace_comments => {
@@ -259,6 +517,11 @@ pub fn quote(s: &str) -> String {
 
 /// Create a `Filter` from a string representation
 pub fn parse(filter_spec: &str) -> JoshResult<Filter> {
+    parse_with_definitions(filter_spec, &Definitions::default())
+}
+
+/// Like `parse`, but `:~name` can also refer to the passed definitions
+pub fn parse_with_definitions(filter_spec: &str, definitions: &Definitions) -> JoshResult<Filter> {
     if filter_spec.is_empty() {
         return Ok(to_filter(Op::Empty));
     }
@@ -267,7 +530,7 @@ pub fn parse(filter_spec: &str) -> JoshResult<Filter> {
         let mut r = r;
         let r = r.next().unwrap();
         for pair in r.into_inner() {
-            let v = parse_item(pair)?;
+            let v = parse_item(pair, definitions)?;
             chain = Some(if let Some(c) = chain {
                 Op::Chain(to_filter(c), to_filter(v))
             } else {
@@ -279,6 +542,7 @@ pub fn parse(filter_spec: &str) -> JoshResult<Filter> {
 
     Ok(opt::optimize(to_filter(Op::Compose(parse_workspace(
         filter_spec,
+        definitions,
     )?))))
 }
 
@@ -0,0 +1,154 @@
+/*
+ * Matching of paths against lists of gitignore-style glob patterns.
+ * Patterns are anchored at the root of the tree, a trailing `/` makes a pattern
+ * match only directories and a leading `!` negates it. When several patterns
+ * match a path the last one decides whether the path is selected.
+ */
+
+use super::*;
+
+const OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: true,
+};
+
+struct Pattern {
+    pattern: glob::Pattern,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn matches_dir(&self, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| self.pattern.matches_path_with(p, OPTIONS))
+    }
+
+    fn matches_file(&self, path: &Path) -> bool {
+        if self.dir_only {
+            path.parent().map(|p| self.matches_dir(p)).unwrap_or(false)
+        } else {
+            self.pattern.matches_path_with(path, OPTIONS)
+        }
+    }
+}
+
+pub struct PatternList {
+    patterns: Vec<Pattern>,
+}
+
+impl PatternList {
+    pub fn new(patterns: &[String]) -> JoshResult<PatternList> {
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                let (negated, p) = match p.strip_prefix('!') {
+                    Some(p) => (true, p),
+                    None => (false, p.as_str()),
+                };
+                let dir_only = p.ends_with('/');
+                Ok(Pattern {
+                    pattern: glob::Pattern::new(p.trim_end_matches('/'))?,
+                    negated,
+                    dir_only,
+                })
+            })
+            .collect::<JoshResult<_>>()?;
+
+        Ok(PatternList { patterns })
+    }
+
+    /// Should the file at `path` be selected
+    pub fn matches_file(&self, path: &Path) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| p.matches_file(path))
+            .map(|p| !p.negated)
+            .unwrap_or(false)
+    }
+
+    /// Should the directory at `path` be selected as a whole. This is only the
+    /// case if no negated pattern follows the one that selected it, because
+    /// that could still match some of the contents.
+    pub fn matches_dir(&self, path: &Path) -> bool {
+        let selected = self
+            .patterns
+            .iter()
+            .rposition(|p| !p.negated && p.dir_only && p.matches_dir(path));
+        let negated = self.patterns.iter().rposition(|p| p.negated);
+
+        match (selected, negated) {
+            (Some(s), Some(n)) => s > n,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Like in `.gitignore` files, `**` only has a special meaning when it forms
+/// a whole path component, anywhere else it is the same as `*`.
+pub fn normalize(pattern: &str) -> String {
+    pattern
+        .split('/')
+        .map(|component| {
+            if component.contains("**") && component.chars().any(|c| c != '*') {
+                let mut result = String::new();
+                for c in component.chars() {
+                    if !(c == '*' && result.ends_with('*')) {
+                        result.push(c);
+                    }
+                }
+                result
+            } else if component.contains("**") {
+                "**".to_string()
+            } else {
+                component.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Translate a file in the format used by `git sparse-checkout` (which is the
+/// same as `.gitignore`) into a pattern list. Patterns that don't contain a `/`
+/// other than a trailing one match at any depth, and patterns that don't end in
+/// `/` also match directories. Like git, invalid lines are ignored.
+pub fn from_sparse_checkout(text: &str) -> Vec<String> {
+    let mut patterns = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => ("!", line),
+            None => ("", line.strip_prefix("\\#").map_or(line, |_| &line[1..])),
+        };
+        let dir_only = line.ends_with('/');
+        let pattern = line.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let pattern = normalize(pattern.trim_start_matches('/'));
+
+        if pattern.is_empty() || glob::Pattern::new(&pattern).is_err() {
+            continue;
+        }
+        let pattern = if anchored || pattern.starts_with("**") {
+            pattern
+        } else {
+            format!("**/{}", pattern)
+        };
+
+        if !dir_only {
+            patterns.push(format!("{}{}", negated, pattern));
+        }
+        patterns.push(format!("{}{}/", negated, pattern));
+    }
+    patterns
+}
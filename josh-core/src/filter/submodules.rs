@@ -0,0 +1,225 @@
+/*
+ * Replace submodules with the trees of the commits they point to and split
+ * changes to those trees back into submodule commits.
+ */
+
+use super::*;
+
+const GITLINK_MODE: i32 = 0o160000;
+
+fn parse_gitmodules(content: &str) -> JoshResult<gix_config::File<'static>> {
+    content
+        .parse()
+        .map_err(|e| josh_error(&format!("invalid .gitmodules: {}", e)))
+}
+
+/// Paths of all submodules listed in the `.gitmodules` file of `tree`
+fn submodule_paths(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+) -> JoshResult<Vec<std::path::PathBuf>> {
+    let gitmodules = parse_gitmodules(&tree::get_blob(repo, tree, Path::new(".gitmodules")))?;
+    Ok(gitmodules
+        .sections_by_name("submodule")
+        .into_iter()
+        .flatten()
+        .filter_map(|section| section.value("path"))
+        .map(|path| Path::new(&path.to_string()).to_owned())
+        .collect())
+}
+
+/// Find the commit a submodule at `path` points to, if it is available
+fn submodule_commit<'a>(
+    repo: &'a git2::Repository,
+    tree: &git2::Tree,
+    path: &Path,
+) -> Option<git2::Commit<'a>> {
+    let entry = tree.get_path(path).ok()?;
+    if entry.filemode() != GITLINK_MODE {
+        return None;
+    }
+    repo.find_commit(entry.id()).ok()
+}
+
+pub fn inline<'a>(
+    transaction: &'a cache::Transaction,
+    tree: git2::Tree<'a>,
+) -> JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    let key = to_filter(Op::InlineSubmodules).id();
+    if let Some(cached) = transaction.get_glob((tree.id(), key)) {
+        return Ok(repo.find_tree(cached)?);
+    }
+    let mut result = tree.clone();
+
+    for path in submodule_paths(repo, &tree)? {
+        if let Some(commit) = submodule_commit(repo, &tree, &path) {
+            let inlined = inline(transaction, commit.tree()?)?;
+            result = tree::insert(repo, &result, &path, inlined.id(), 0o0040000)?;
+        }
+    }
+
+    transaction.insert_glob((tree.id(), key), result.id());
+    Ok(result)
+}
+
+/// Replace the inlined trees in `tree` with submodule entries again. For every submodule of
+/// `parent_tree` whose contents got changed a new commit is created in the submodule, based on
+/// the commit the submodule pointed to in `parent_tree`.
+pub fn unapply<'a>(
+    transaction: &'a cache::Transaction,
+    tree: git2::Tree<'a>,
+    parent_tree: git2::Tree<'a>,
+    commit: Option<&git2::Commit>,
+) -> JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    let mut result = tree.clone();
+
+    for path in submodule_paths(repo, &parent_tree)? {
+        let original = some_or!(submodule_commit(repo, &parent_tree, &path), {
+            continue;
+        });
+        let inlined = match tree.get_path(&path) {
+            Ok(entry) if entry.kind() == Some(git2::ObjectType::Tree) => {
+                repo.find_tree(entry.id())?
+            }
+            _ => continue,
+        };
+
+        let id = if inlined.id() == inline(transaction, original.tree()?)?.id() {
+            original.id()
+        } else {
+            let commit = commit.ok_or_else(|| {
+                josh_error("changes to submodules can only be pushed as part of a commit")
+            })?;
+            let new_tree = unapply(transaction, inlined, original.tree()?, Some(commit))?;
+            repo.commit(
+                None,
+                &commit.author(),
+                &commit.committer(),
+                &String::from_utf8_lossy(commit.message_raw_bytes()),
+                &new_tree,
+                &[&original],
+            )?
+        };
+
+        result = tree::insert(repo, &result, &path, id, GITLINK_MODE)?;
+    }
+
+    Ok(result)
+}
+
+/// Url of the repository serving `path` of the repository at `url` as its own history
+fn gitlink_url(path: &Path, url: &str) -> String {
+    format!(
+        "{}{}.git",
+        url,
+        spec(to_filter(Op::Subdir(path.to_owned())))
+    )
+}
+
+fn gitmodules_section(path: &Path, url: &str) -> String {
+    let path = path.to_string_lossy();
+    format!(
+        "[submodule \"{}\"]\n\tpath = {}\n\turl = {}\n",
+        path,
+        path,
+        gitlink_url(Path::new(path.as_ref()), url)
+    )
+}
+
+/// `content` of a `.gitmodules` file without the sections of the submodule at `path`
+fn remove_gitmodules_sections(content: &str, path: &Path) -> JoshResult<String> {
+    let mut gitmodules = parse_gitmodules(content)?;
+    let ids = gitmodules
+        .sections_and_ids_by_name("submodule")
+        .into_iter()
+        .flatten()
+        .filter(|(section, _)| {
+            section
+                .value("path")
+                .is_some_and(|p| Path::new(&p.to_string()) == path)
+        })
+        .map(|(_, id)| id)
+        .collect::<Vec<_>>();
+    for id in ids {
+        gitmodules.remove_section_by_id(id);
+    }
+    Ok(gitmodules.to_bstring().to_string())
+}
+
+fn write_gitmodules<'a>(
+    repo: &'a git2::Repository,
+    tree: &git2::Tree,
+    content: &str,
+) -> JoshResult<git2::Tree<'a>> {
+    let blob = if content.is_empty() {
+        git2::Oid::zero()
+    } else {
+        repo.blob(content.as_bytes())?
+    };
+    tree::insert(repo, tree, Path::new(".gitmodules"), blob, 0o0100644)
+}
+
+/// Replace the directory at `path` with a submodule pointing to `commit` and
+/// add it to `.gitmodules`, replacing an existing section for `path`.
+/// A zero `commit` removes the directory.
+pub fn replace_with_gitlink<'a>(
+    repo: &'a git2::Repository,
+    tree: git2::Tree<'a>,
+    path: &Path,
+    url: &str,
+    commit: git2::Oid,
+) -> JoshResult<git2::Tree<'a>> {
+    if tree.get_path(path).is_err() {
+        return Ok(tree);
+    }
+    let result = tree::insert(repo, &tree, path, commit, GITLINK_MODE)?;
+    if commit == git2::Oid::zero() {
+        return Ok(result);
+    }
+
+    let gitmodules =
+        remove_gitmodules_sections(&tree::get_blob(repo, &tree, Path::new(".gitmodules")), path)?;
+    write_gitmodules(
+        repo,
+        &result,
+        &format!("{}{}", gitmodules, gitmodules_section(path, url)),
+    )
+}
+
+/// Replace the submodule at `path` with the tree of the commit it points to again
+/// and remove it from `.gitmodules`.
+pub fn unapply_gitlink<'a>(
+    repo: &'a git2::Repository,
+    tree: git2::Tree<'a>,
+    path: &Path,
+    url: &str,
+) -> JoshResult<git2::Tree<'a>> {
+    let result = match tree.get_path(path) {
+        Ok(entry) if entry.filemode() == GITLINK_MODE => {
+            let commit = repo.find_commit(entry.id()).map_err(|_| {
+                josh_error(&format!(
+                    "submodule commit {} not found, it needs to be pushed to {} first",
+                    entry.id(),
+                    gitlink_url(path, url)
+                ))
+            })?;
+            tree::insert(repo, &tree, path, commit.tree_id(), 0o0040000)?
+        }
+        Ok(_) => {
+            return Err(josh_error(&format!(
+                "{} has to be a submodule",
+                path.display()
+            )));
+        }
+        Err(_) => tree.clone(),
+    };
+
+    let gitmodules = tree::get_blob(repo, &tree, Path::new(".gitmodules"));
+    write_gitmodules(
+        repo,
+        &result,
+        &remove_gitmodules_sections(&gitmodules, path)?,
+    )
+}
@@ -96,11 +96,22 @@ pub fn remove_pred<'a>(
     transaction: &'a cache::Transaction,
     root: &str,
     input: git2::Oid,
-    pred: &dyn Fn(&Path, bool) -> bool,
+    pred: &dyn Fn(&Path, &git2::TreeEntry) -> bool,
+    path_dependent: bool,
     key: git2::Oid,
 ) -> JoshResult<git2::Tree<'a>> {
     let repo = transaction.repo();
-    if let Some(cached) = transaction.get_glob((input, key)) {
+    // If the predicate depends on the path, the same tree at a different
+    // location might give a different result
+    let cache_key = if !path_dependent || root.is_empty() {
+        key
+    } else {
+        git2::Oid::hash_object(
+            git2::ObjectType::Blob,
+            format!("{}:{}", key, root).as_bytes(),
+        )?
+    };
+    if let Some(cached) = transaction.get_glob((input, cache_key)) {
         return Ok(repo.find_tree(cached)?);
     }
     rs_tracing::trace_scoped!("remove_pred X", "root": root);
@@ -112,7 +123,7 @@ pub fn remove_pred<'a>(
         let name = entry.name().ok_or_else(|| josh_error("INVALID_FILENAME"))?;
         let path = std::path::PathBuf::from(root).join(name);
 
-        if entry.kind() == Some(git2::ObjectType::Blob) && pred(&path, true) {
+        if entry.kind() == Some(git2::ObjectType::Blob) && pred(&path, &entry) {
             result = replace_child(
                 repo,
                 Path::new(entry.name().ok_or_else(|| josh_error("no name"))?),
@@ -123,7 +134,7 @@ pub fn remove_pred<'a>(
         }
 
         if entry.kind() == Some(git2::ObjectType::Tree) {
-            let s = if !root.is_empty() && pred(&path, false) {
+            let s = if pred(&path, &entry) {
                 entry.id()
             } else {
                 remove_pred(
@@ -136,6 +147,7 @@ pub fn remove_pred<'a>(
                     ),
                     entry.id(),
                     &pred,
+                    path_dependent,
                     key,
                 )?
                 .id()
@@ -153,7 +165,7 @@ pub fn remove_pred<'a>(
         }
     }
 
-    transaction.insert_glob((input, key), result.id());
+    transaction.insert_glob((input, cache_key), result.id());
     Ok(result)
 }
 
@@ -323,7 +335,7 @@ pub fn diff_paths(
         return Ok(r);
     }
 
-    if let Ok(tree1) = repo.find_tree(input2) {
+    if let Ok(tree1) = repo.find_tree(input1) {
         for entry in tree1.iter() {
             let name = entry.name().ok_or_else(|| josh_error("no name"))?;
             r.append(&mut diff_paths(
@@ -339,6 +351,49 @@ pub fn diff_paths(
     Ok(r)
 }
 
+/// Find the directory in `old_tree` that was renamed to `path` in `new_tree`.
+/// Files added below `path` are matched against removed files with the same
+/// blob id and the same path relative to the directory. The candidate with the
+/// most matching files wins.
+pub fn rename_source(
+    repo: &git2::Repository,
+    old_tree: &git2::Tree,
+    new_tree: &git2::Tree,
+    path: &Path,
+) -> JoshResult<Option<std::path::PathBuf>> {
+    rs_tracing::trace_scoped!("rename_source", "path": path.to_string_lossy());
+    let prefix = format!("{}/", path.to_string_lossy());
+    let diff = diff_paths(repo, old_tree.id(), new_tree.id(), "")?;
+
+    let mut removed = std::collections::HashMap::<git2::Oid, Vec<&str>>::new();
+    for (p, _) in diff
+        .iter()
+        .filter(|(p, n)| *n == -1 && !p.starts_with(&prefix))
+    {
+        let id = old_tree.get_path(Path::new(p))?.id();
+        removed.entry(id).or_default().push(p);
+    }
+
+    let mut candidates = std::collections::BTreeMap::<&str, usize>::new();
+    for (p, _) in diff
+        .iter()
+        .filter(|(p, n)| *n == 1 && p.starts_with(&prefix))
+    {
+        let id = new_tree.get_path(Path::new(p))?.id();
+        let rel = &p[prefix.len()..];
+        for r in removed.get(&id).into_iter().flatten() {
+            if let Some(src) = r.strip_suffix(rel).and_then(|s| s.strip_suffix('/')) {
+                *candidates.entry(src).or_default() += 1;
+            }
+        }
+    }
+
+    Ok(candidates
+        .into_iter()
+        .max_by_key(|(_, n)| *n)
+        .map(|(src, _)| std::path::PathBuf::from(src)))
+}
+
 pub fn overlay(
     transaction: &cache::Transaction,
     input1: git2::Oid,
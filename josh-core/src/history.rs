@@ -461,27 +461,33 @@ pub fn unapply_filter(
 
         // Convert original_parents to a vector of (rust) references
         let original_parents: Vec<&git2::Commit> = original_parents.iter().collect();
-        let tree = module_commit.tree()?;
         let commit_message = module_commit.summary().unwrap_or("NO COMMIT MESSAGE");
 
-        let new_trees: JoshResult<Vec<_>> = {
-            let span = tracing::span!(
-                tracing::Level::TRACE,
-                "unapply filter",
-                ?commit_message,
-                ?rev,
-                ?filtered_parent_ids,
-                ?original_parents
-            );
-            let _span_guard = span.enter();
-
-            original_parents
-                .iter()
-                .map(|commit| -> JoshResult<_> {
-                    Ok(filter::unapply(transaction, filter, tree.clone(), commit.tree()?)?.id())
-                })
-                .collect()
-        };
+        let new_trees: JoshResult<Vec<_>> =
+            {
+                let span = tracing::span!(
+                    tracing::Level::TRACE,
+                    "unapply filter",
+                    ?commit_message,
+                    ?rev,
+                    ?filtered_parent_ids,
+                    ?original_parents
+                );
+                let _span_guard = span.enter();
+
+                original_parents
+                    .iter()
+                    .map(|commit| -> JoshResult<_> {
+                        Ok(filter::unapply_commit(
+                            transaction,
+                            filter,
+                            &module_commit,
+                            commit.tree()?,
+                        )?
+                        .id())
+                    })
+                    .collect()
+            };
 
         let new_trees = match new_trees {
             Ok(new_trees) => new_trees,
@@ -512,10 +518,10 @@ pub fn unapply_filter(
             // dealing with either a force push or a push with the "merge" option set.
             0 => {
                 tracing::debug!("unrelated history");
-                filter::unapply(
+                filter::unapply_commit(
                     transaction,
                     filter,
-                    tree,
+                    &module_commit,
                     filter::tree::empty(transaction.repo()),
                 )?
             }
@@ -558,10 +564,10 @@ pub fn unapply_filter(
                         Some(&mergeopts),
                     )?;
                     let base_tree = merged_index.write_tree_to(transaction.repo())?;
-                    let tid_ours = filter::unapply(
+                    let tid_ours = filter::unapply_commit(
                         transaction,
                         filter,
-                        tree.clone(),
+                        &module_commit,
                         transaction.repo().find_tree(base_tree)?,
                     )?
                     .id();
@@ -574,10 +580,10 @@ pub fn unapply_filter(
                         Some(&mergeopts),
                     )?;
                     let base_tree = merged_index.write_tree_to(transaction.repo())?;
-                    let tid_theirs = filter::unapply(
+                    let tid_theirs = filter::unapply_commit(
                         transaction,
                         filter,
-                        tree.clone(),
+                        &module_commit,
                         transaction.repo().find_tree(base_tree)?,
                     )?
                     .id();
@@ -608,14 +614,17 @@ pub fn unapply_filter(
             }
         };
 
+        let (author, committer) =
+            filter::unapply_mailmap(transaction, filter, &module_commit, &new_tree)?;
+
         ret = rewrite_commit(
             transaction.repo(),
             &module_commit,
             &original_parents,
             RewriteData {
                 tree: new_tree.clone(),
-                author: None,
-                committer: None,
+                author,
+                committer,
                 message: None,
             },
             false,
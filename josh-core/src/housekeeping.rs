@@ -286,33 +286,103 @@ pub fn refresh_known_filters(
     transaction_mirror: &cache::Transaction,
     transaction_overlay: &cache::Transaction,
 ) -> JoshResult<Vec<(String, git2::Oid)>> {
-    let known_filters = KNOWN_FILTERS.lock()?;
+    let upstream_repos = KNOWN_FILTERS.lock()?.keys().cloned().collect::<Vec<_>>();
     let mut updated_refs = vec![];
-    for (upstream_repo, e) in known_filters.iter() {
-        info!("background rebuild root: {:?}", upstream_repo);
-
-        for filter_spec in e.1.iter() {
-            tracing::trace!("background rebuild: {:?} {:?}", upstream_repo, filter_spec);
-
-            if let Ok((from, to_ref)) = memorize_from_to(
-                transaction_mirror.repo(),
-                &to_filtered_ref(upstream_repo, filter_spec),
-                upstream_repo,
-            ) {
-                let (mut u, _) = filter_refs(
-                    transaction_overlay,
-                    filter::parse(filter_spec)?,
-                    &[from],
-                    filter::empty(),
-                );
-                u[0].0 = to_ref;
-                updated_refs.append(&mut u);
-            }
+    for upstream_repo in upstream_repos.iter() {
+        updated_refs.append(&mut refresh_filters(
+            transaction_mirror,
+            transaction_overlay,
+            upstream_repo,
+        )?);
+    }
+    Ok(updated_refs)
+}
+
+/// Like `refresh_known_filters`, but only for the filters known for `upstream_repo`
+#[tracing::instrument(skip(transaction_mirror, transaction_overlay))]
+pub fn refresh_filters(
+    transaction_mirror: &cache::Transaction,
+    transaction_overlay: &cache::Transaction,
+    upstream_repo: &str,
+) -> JoshResult<Vec<(String, git2::Oid)>> {
+    let upstream_repo = upstream_repo.trim_start_matches('/');
+    let filter_specs = KNOWN_FILTERS
+        .lock()?
+        .get(upstream_repo)
+        .map(|e| e.1.clone())
+        .unwrap_or_default();
+
+    let mut updated_refs = vec![];
+    info!("background rebuild root: {:?}", upstream_repo);
+
+    for filter_spec in filter_specs.iter() {
+        tracing::trace!("background rebuild: {:?} {:?}", upstream_repo, filter_spec);
+
+        if let Ok((from, to_ref)) = memorize_from_to(
+            transaction_mirror.repo(),
+            &to_filtered_ref(upstream_repo, filter_spec),
+            upstream_repo,
+        ) {
+            let (mut u, _) = filter_refs(
+                transaction_overlay,
+                filter::parse(filter_spec)?,
+                &[from],
+                filter::empty(),
+            );
+            u[0].0 = to_ref;
+            updated_refs.append(&mut u);
         }
     }
     Ok(updated_refs)
 }
 
+/// Drop the cache of filters that were not used recently, see `cache::GcOptions`, together
+/// with their `refs/josh/filtered` refs in `repos`. Dropped filters are no longer refreshed.
+/// Returns the names of the removed refs.
+pub fn gc_cache(
+    repos: &[&git2::Repository],
+    options: &cache::GcOptions,
+) -> JoshResult<(cache::GcReport, Vec<String>)> {
+    let report = cache::gc(options)?;
+    let dropped = report
+        .dropped
+        .iter()
+        .map(|t| t.name.clone())
+        .collect::<BTreeSet<_>>();
+    let is_dropped = |spec: &str| {
+        filter::parse(spec)
+            .map(|f| dropped.contains(&filter::spec(f)))
+            .unwrap_or(false)
+    };
+
+    let mut removed_refs = vec![];
+    for repo in repos {
+        for reference in repo.references_glob("refs/josh/filtered/*")? {
+            let mut reference = reference?;
+            let name = reference
+                .name()
+                .ok_or_else(|| josh_error("reference without name"))?
+                .to_string();
+            let filtered = some_or!(FilteredRefRegex::from_str(&name), { continue });
+
+            if is_dropped(&from_ns(&filtered.filter_spec)) {
+                if !options.dry_run {
+                    reference.delete()?;
+                }
+                removed_refs.push(name);
+            }
+        }
+    }
+
+    if !options.dry_run {
+        for (_, filter_specs) in KNOWN_FILTERS.lock()?.values_mut() {
+            filter_specs.retain(|spec| !is_dropped(spec));
+        }
+    }
+
+    Ok((report, removed_refs))
+}
+
 pub fn get_known_filters() -> JoshResult<std::collections::BTreeMap<String, BTreeSet<String>>> {
     Ok(KNOWN_FILTERS
         .lock()?
@@ -321,11 +391,18 @@ pub fn get_known_filters() -> JoshResult<std::collections::BTreeMap<String, BTre
         .collect())
 }
 
-pub fn run(repo_path: &std::path::Path, do_gc: bool) -> JoshResult<()> {
+pub fn run(
+    repo_path: &std::path::Path,
+    do_gc: bool,
+    discover: bool,
+    cache_gc: Option<&cache::GcOptions>,
+) -> JoshResult<()> {
     const CRUFT_PACK_SIZE: usize = 1024 * 1024 * 64;
 
     let transaction_mirror = cache::Transaction::open(&repo_path.join("mirror"), None)?;
     let transaction_overlay = cache::Transaction::open(&repo_path.join("overlay"), None)?;
+    transaction_mirror.disable_access_tracking();
+    transaction_overlay.disable_access_tracking();
 
     transaction_overlay
         .repo()
@@ -348,13 +425,29 @@ pub fn run(repo_path: &std::path::Path, do_gc: bool) -> JoshResult<()> {
         )
         .replace('\n', "  ")
     );
-    if std::env::var("JOSH_NO_DISCOVER").is_err() {
+    if let Some(options) = cache_gc {
+        let (report, removed_refs) = gc_cache(
+            &[transaction_mirror.repo(), transaction_overlay.repo()],
+            options,
+        )?;
+        info!(
+            "cache gc: dropped {} filters, kept {}, reclaimed ~{} bytes, removed {} refs",
+            report.dropped.len(),
+            report.kept,
+            report.reclaimed(),
+            removed_refs.len()
+        );
+    }
+    if discover {
         housekeeping::discover_filter_candidates(&transaction_mirror)?;
     }
     if std::env::var("JOSH_NO_REFRESH").is_err() {
         refresh_known_filters(&transaction_mirror, &transaction_overlay)?;
     }
     if do_gc {
+        for dir in cache::remove_stale_versions(repo_path)? {
+            info!("removed stale cache {}", dir.display());
+        }
         info!(
             "\n----------\n{}\n----------",
             run_command(
@@ -134,6 +134,15 @@ pub fn josh_error(s: &str) -> JoshError {
     JoshError(s.to_owned())
 }
 
+const MISSING_PERMISSIONS: &str = "missing permissions for commit";
+
+impl JoshError {
+    /// Whether filtering failed because the user can't access all paths of a commit
+    pub fn is_missing_permissions(&self) -> bool {
+        self.0 == MISSING_PERMISSIONS
+    }
+}
+
 impl std::fmt::Display for JoshError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "JoshError({})", self.0)
@@ -235,7 +244,7 @@ pub fn filter_commit(
                 warn = true,
                 oid = format!("{:?}", oid),
             );
-            return Err(josh_error("missing permissions for commit"));
+            return Err(josh_error(MISSING_PERMISSIONS));
         }
     }
 
@@ -370,41 +379,87 @@ pub fn get_acl(
 ) -> JoshResult<(filter::Filter, filter::Filter)> {
     let users =
         std::fs::read_to_string(users).map_err(|_| josh_error("failed to read users file"))?;
-    let users: Users = serde_yaml::from_str(&users)
-        .map_err(|err| josh_error(format!("failed to parse users file: {}", err).as_str()))?;
     let groups =
         std::fs::read_to_string(groups).map_err(|_| josh_error("failed to read groups file"))?;
-    let groups: Groups = serde_yaml::from_str(&groups)
-        .map_err(|err| josh_error(format!("failed to parse groups file: {}", err).as_str()))?;
+    let (users, groups) = parse_users_and_groups(&users, &groups)?;
 
-    return users
-        .get(user)
-        .and_then(|u| {
-            let mut whitelist = filter::empty();
-            let mut blacklist = filter::empty();
-            for g in &u.groups {
-                let lists = groups.get(repo).and_then(|repo| {
-                    repo.get(g.as_str()).map(|group| {
-                        let w = filter::parse(&group.whitelist);
-                        let b = filter::parse(&group.blacklist);
-                        (w, b)
-                    })
-                })?;
-                if let Err(e) = lists.0 {
-                    return Some(Err(JoshError(format!("Error parsing whitelist: {}", e))));
-                }
-                if let Err(e) = lists.1 {
-                    return Some(Err(JoshError(format!("Error parsing blacklist: {}", e))));
-                }
-                if let Ok(w) = lists.0 {
-                    whitelist = filter::compose(whitelist, w);
-                }
-                if let Ok(b) = lists.1 {
-                    blacklist = filter::compose(blacklist, b);
-                }
-            }
+    return find_acl(&users, &groups, user, repo)
+        .map(|acl| {
+            let (whitelist, blacklist) = acl?;
             println!("w: {:?}, b: {:?}", whitelist, blacklist);
-            Some(Ok((whitelist, blacklist)))
+            Ok((whitelist, blacklist))
         })
         .unwrap_or_else(|| Ok((filter::empty(), filter::nop())));
 }
+
+/// Like `get_acl`, but takes the contents of the users and groups files
+pub fn parse_acl(
+    users: &str,
+    groups: &str,
+    user: &str,
+    repo: &str,
+) -> JoshResult<(filter::Filter, filter::Filter)> {
+    AclFiles::parse(users, groups)?.acl(user, repo)
+}
+
+/// Parsed contents of the users and groups files, to avoid parsing them for every request
+#[derive(Debug)]
+pub struct AclFiles {
+    users: Users,
+    groups: Groups,
+}
+
+impl AclFiles {
+    pub fn parse(users: &str, groups: &str) -> JoshResult<AclFiles> {
+        let (users, groups) = parse_users_and_groups(users, groups)?;
+        Ok(AclFiles { users, groups })
+    }
+
+    /// Whitelist and blacklist of `user` for `repo`
+    pub fn acl(&self, user: &str, repo: &str) -> JoshResult<(filter::Filter, filter::Filter)> {
+        find_acl(&self.users, &self.groups, user, repo)
+            .unwrap_or_else(|| Ok((filter::empty(), filter::nop())))
+    }
+}
+
+fn parse_users_and_groups(users: &str, groups: &str) -> JoshResult<(Users, Groups)> {
+    let users: Users = serde_yaml::from_str(users)
+        .map_err(|err| josh_error(format!("failed to parse users file: {}", err).as_str()))?;
+    let groups: Groups = serde_yaml::from_str(groups)
+        .map_err(|err| josh_error(format!("failed to parse groups file: {}", err).as_str()))?;
+    Ok((users, groups))
+}
+
+fn find_acl(
+    users: &Users,
+    groups: &Groups,
+    user: &str,
+    repo: &str,
+) -> Option<JoshResult<(filter::Filter, filter::Filter)>> {
+    users.get(user).and_then(|u| {
+        let mut whitelist = filter::empty();
+        let mut blacklist = filter::empty();
+        for g in &u.groups {
+            let lists = groups.get(repo).and_then(|repo| {
+                repo.get(g.as_str()).map(|group| {
+                    let w = filter::parse(&group.whitelist);
+                    let b = filter::parse(&group.blacklist);
+                    (w, b)
+                })
+            })?;
+            if let Err(e) = lists.0 {
+                return Some(Err(JoshError(format!("Error parsing whitelist: {}", e))));
+            }
+            if let Err(e) = lists.1 {
+                return Some(Err(JoshError(format!("Error parsing blacklist: {}", e))));
+            }
+            if let Ok(w) = lists.0 {
+                whitelist = filter::compose(whitelist, w);
+            }
+            if let Ok(b) = lists.1 {
+                blacklist = filter::compose(blacklist, b);
+            }
+        }
+        Some(Ok((whitelist, blacklist)))
+    })
+}
@@ -80,6 +80,75 @@ fn make_app() -> clap::Command {
                 .help("Don't load cache")
                 .short('n'),
         )
+        .arg(
+            clap::Arg::new("cache-backend")
+                .long("cache-backend")
+                .value_parser(clap::value_parser!(josh::cache::BackendKind))
+                .default_value("sled")
+                .help("Storage of the cache: sled, sqlite or memory"),
+        )
+        .arg(
+            clap::Arg::new("cache-gc")
+                .action(clap::ArgAction::SetTrue)
+                .help("Drop unused filters from the cache and exit")
+                .long("cache-gc"),
+        )
+        .arg(
+            clap::Arg::new("max-age")
+                .long("max-age")
+                .help("With --cache-gc: drop filters not used for this long, like \"90d\""),
+        )
+        .arg(
+            clap::Arg::new("max-size")
+                .long("max-size")
+                .help("With --cache-gc: drop least recently used filters above this size"),
+        )
+        .arg(
+            clap::Arg::new("dry-run")
+                .action(clap::ArgAction::SetTrue)
+                .help("With --cache-gc: only show what would be dropped")
+                .long("dry-run"),
+        )
+        .arg(
+            clap::Arg::new("cache-export")
+                .long("cache-export")
+                .help("Write the cache and filtered refs to a bundle file and exit"),
+        )
+        .arg(
+            clap::Arg::new("cache-import")
+                .long("cache-import")
+                .help("Add the content of a bundle file to the cache and exit"),
+        )
+        .arg(
+            clap::Arg::new("export-repo")
+                .action(clap::ArgAction::Append)
+                .long("export-repo")
+                .help("With --cache-export: only export filters of this upstream repo"),
+        )
+        .arg(
+            clap::Arg::new("export-filter")
+                .action(clap::ArgAction::Append)
+                .long("export-filter")
+                .help("With --cache-export: only export this filter"),
+        )
+        .arg(
+            clap::Arg::new("verify-cache")
+                .action(clap::ArgAction::SetTrue)
+                .help("Filter the cached commits again and compare the results, then exit")
+                .long("verify-cache"),
+        )
+        .arg(
+            clap::Arg::new("sample")
+                .long("sample")
+                .value_parser(clap::value_parser!(usize))
+                .help("With --verify-cache: only check this many entries per filter"),
+        )
+        .arg(
+            clap::Arg::new("repair")
+                .action(clap::ArgAction::SetTrue)
+                .help("With --verify-cache: fix the cache entries that are wrong")
+                .long("repair"),
+        )
         .arg(
             clap::Arg::new("pack")
                 .action(clap::ArgAction::SetTrue)
@@ -146,7 +215,122 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
 
     let mut filterobj = josh::filter::parse(&specstr)?;
 
-    let transaction = josh::cache::Transaction::open_from_env(!args.get_flag("no-cache"))?;
+    let cache_backend = if args.get_flag("no-cache") {
+        josh::cache::BackendKind::Memory
+    } else {
+        *args
+            .get_one::<josh::cache::BackendKind>("cache-backend")
+            .unwrap()
+    };
+    let transaction = josh::cache::Transaction::open_from_env(Some(cache_backend))?;
+
+    if args.get_flag("cache-gc") {
+        let options = josh::cache::GcOptions {
+            max_age: args
+                .get_one::<String>("max-age")
+                .map(|age| josh::cache::parse_age(age))
+                .transpose()?,
+            max_size: args
+                .get_one::<String>("max-size")
+                .map(|size| josh::cache::parse_size(size))
+                .transpose()?,
+            dry_run: args.get_flag("dry-run"),
+        };
+        let (report, removed_refs) = josh::housekeeping::gc_cache(&[transaction.repo()], &options)?;
+        for tree in report.dropped.iter() {
+            println!("dropped {} ({} entries)", tree.name, tree.entries);
+        }
+        for name in removed_refs.iter() {
+            println!("removed {}", name);
+        }
+        if !options.dry_run {
+            for dir in josh::cache::remove_stale_versions(transaction.repo().path())? {
+                println!("removed {}", dir.display());
+            }
+        }
+        println!(
+            "dropped {} filters, kept {}, reclaimed ~{} bytes",
+            report.dropped.len(),
+            report.kept,
+            report.reclaimed()
+        );
+        return Ok(0);
+    }
+
+    if let Some(path) = args.get_one::<String>("cache-export") {
+        let selection = josh::cache::BundleSelection {
+            upstream_repos: args
+                .get_many::<String>("export-repo")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+            filters: args
+                .get_many::<String>("export-filter")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+        };
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let report = josh::cache::export_bundle(transaction.repo(), &selection, &mut out)?;
+        for filter in report.filters.iter() {
+            println!("exported {} ({} entries)", filter.spec, filter.entries);
+        }
+        println!(
+            "exported {} filters, {} refs, {} objects",
+            report.filters.len(),
+            report.refs,
+            report.objects
+        );
+        return Ok(0);
+    }
+
+    if let Some(path) = args.get_one::<String>("cache-import") {
+        let mut input = std::io::BufReader::new(std::fs::File::open(path)?);
+        let report = josh::cache::import_bundle(transaction.repo(), &mut input)?;
+        for filter in report.filters.iter() {
+            println!(
+                "imported {} ({} entries, {} skipped)",
+                filter.spec, filter.entries, filter.skipped
+            );
+        }
+        println!(
+            "imported {} filters, {} refs, {} refs skipped",
+            report.filters.len(),
+            report.refs,
+            report.skipped_refs
+        );
+        return Ok(0);
+    }
+
+    if args.get_flag("verify-cache") {
+        let options = josh::cache::VerifyOptions {
+            sample: args.get_one::<usize>("sample").copied(),
+            repair: args.get_flag("repair"),
+        };
+        let report = josh::cache::verify(&transaction, &options)?;
+        for issue in report.issues.iter() {
+            match issue.problem {
+                josh::cache::Problem::Mismatch(expected) => println!(
+                    "mismatch {} {}: cached {}, expected {}",
+                    issue.spec, issue.from, issue.to, expected
+                ),
+                josh::cache::Problem::MissingCommit => {
+                    println!("missing commit {} {}", issue.spec, issue.from)
+                }
+                josh::cache::Problem::MissingResult => {
+                    println!("missing result {} {}: {}", issue.spec, issue.from, issue.to)
+                }
+            }
+        }
+        println!(
+            "checked {} entries of {} filters, {} problems{}",
+            report.checked,
+            report.filters,
+            report.issues.len(),
+            if options.repair { ", repaired" } else { "" }
+        );
+        return Ok(if report.issues.is_empty() { 0 } else { 1 });
+    }
 
     let repo = transaction.repo();
     let input_ref = args.get_one::<String>("input").unwrap();
@@ -416,7 +600,7 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
     std::mem::drop(finish);
 
     if let Some(query) = args.get_one::<String>("query") {
-        let transaction = josh::cache::Transaction::open_from_env(false)?;
+        let transaction = josh::cache::Transaction::open_from_env(None)?;
         let commit_id = transaction.repo().refname_to_id(update_target)?;
         print!(
             "{}",
@@ -439,16 +623,17 @@ fn main() {
         args
     };
 
-    std::process::exit(if let Err(e) = run_filter(args) {
-        println!(
-            "ERROR: {}",
-            match e {
-                JoshError(s) => s,
-            }
-        );
-        1
-    } else {
-        0
+    let result = run_filter(args);
+
+    // Writes to the cache are committed in the background, don't lose them when exiting
+    josh::cache::flush().ok();
+
+    std::process::exit(match result {
+        Ok(code) => code,
+        Err(JoshError(s)) => {
+            println!("ERROR: {}", s);
+            1
+        }
     })
 }
 
@@ -1,5 +1,6 @@
 use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 // Import the base64 crate Engine trait anonymously so we can
 // call its methods without adding to the namespace.
@@ -60,18 +61,30 @@ impl std::fmt::Debug for Handle {
 }
 
 impl Handle {
+    fn header(&self) -> josh::JoshResult<String> {
+        let line = AUTH
+            .lock()
+            .unwrap()
+            .get(self)
+            .and_then(|h| h.header.as_ref())
+            .map(|h| h.as_bytes().to_owned())
+            .ok_or_else(|| josh::josh_error("no auth found"))?;
+
+        Ok(String::from_utf8(line)?)
+    }
+
+    // Returns the token of "Authorization: Bearer" headers
+    pub fn bearer_token(&self) -> Option<String> {
+        self.header()
+            .ok()?
+            .strip_prefix("Bearer ")
+            .map(|token| token.trim().to_string())
+    }
+
     // Returns a pair: (username, password)
     pub fn parse(&self) -> Option<(String, String)> {
         let get_result = || -> josh::JoshResult<(String, String)> {
-            let line = AUTH
-                .lock()
-                .unwrap()
-                .get(self)
-                .and_then(|h| h.header.as_ref())
-                .map(|h| h.as_bytes().to_owned())
-                .ok_or_else(|| josh::josh_error("no auth found"))?;
-
-            let line = String::from_utf8(line)?;
+            let line = self.header()?;
             let (_, token) = line
                 .split_once(' ')
                 .ok_or_else(|| josh::josh_error("Unsupported auth type"))?;
@@ -263,3 +276,148 @@ pub fn strip_auth(
 
     Ok((Handle { hash: None }, req))
 }
+
+/// Where the credentials of incoming requests are checked
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Pass the credentials on to the upstream and let it decide
+    Upstream,
+    /// Check user names and passwords against an htpasswd file.
+    /// Supported are bcrypt and SHA1 ("{SHA}") hashes.
+    Htpasswd(std::path::PathBuf),
+    /// Check static tokens from a file with one "user:token" entry per line.
+    /// Tokens can be passed as bearer token or as password.
+    Tokens(std::path::PathBuf),
+}
+
+lazy_static! {
+    // Verifying bcrypt hashes is slow on purpose, so remember successful checks
+    // until the file is modified. Each upstream can have its own file, so a check
+    // is only valid for the backend that made it.
+    static ref LOCAL_AUTH: std::sync::Mutex<lru::LruCache<(Backend, Handle), SystemTime>> =
+        std::sync::Mutex::new(lru::LruCache::new(AUTH_LRU_CACHE_SIZE));
+}
+
+fn read_entries(path: &std::path::Path) -> josh::JoshResult<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| josh::josh_error(&format!("failed to read {}: {}", path.display(), e)))?;
+
+    Ok(content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(user, secret)| (user.to_string(), secret.to_string()))
+        .collect())
+}
+
+fn verify_htpasswd(password: &str, hash: &str) -> bool {
+    use sha1::{Digest, Sha1};
+
+    if let Some(hash) = hash.strip_prefix("{SHA}") {
+        BASE64.encode(Sha1::digest(password.as_bytes())) == hash
+    } else if hash.starts_with("$2") {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else {
+        tracing::warn!("verify_htpasswd: unsupported hash format");
+        false
+    }
+}
+
+pub(crate) fn same_secret(a: &str, b: &str) -> bool {
+    use sha2::{Digest, Sha256};
+
+    // Compare hashes so the time taken doesn't depend on the common prefix
+    Sha256::digest(a.as_bytes()) == Sha256::digest(b.as_bytes())
+}
+
+impl Backend {
+    /// Whether the credentials are checked by the proxy itself rather than the upstream
+    pub fn is_local(&self) -> bool {
+        !matches!(self, Backend::Upstream)
+    }
+
+    /// Check the credentials of a request to `url`. The upstream backend accepts
+    /// requests without credentials unless `required` is set, the other backends never do.
+    /// Local upstreams have no credentials to check them against, so the upstream backend
+    /// rejects all requests to them.
+    pub async fn check(&self, url: &str, auth: &Handle, required: bool) -> josh::JoshResult<bool> {
+        let path = match self {
+            Backend::Upstream if url.starts_with("file://") => return Ok(false),
+            Backend::Upstream => return check_http_auth(url, auth, required).await,
+            Backend::Htpasswd(path) | Backend::Tokens(path) => path.clone(),
+        };
+
+        if auth.hash.is_none() {
+            return Ok(false);
+        }
+
+        let modified = std::fs::metadata(&path)?.modified()?;
+        let key = (self.clone(), auth.clone());
+        if LOCAL_AUTH.lock()?.get(&key) == Some(&modified) {
+            return Ok(true);
+        }
+
+        let backend = self.clone();
+        let handle = auth.clone();
+        let valid = tokio::task::spawn_blocking(move || backend.verify(&handle)).await??;
+
+        if valid {
+            LOCAL_AUTH.lock()?.put(key, modified);
+        }
+
+        Ok(valid)
+    }
+
+    fn verify(&self, auth: &Handle) -> josh::JoshResult<bool> {
+        Ok(match self {
+            Backend::Upstream => false,
+            Backend::Htpasswd(path) => {
+                let (username, password) = match auth.parse() {
+                    Some(credentials) => credentials,
+                    None => return Ok(false),
+                };
+                read_entries(path)?
+                    .iter()
+                    .any(|(user, hash)| *user == username && verify_htpasswd(&password, hash))
+            }
+            Backend::Tokens(path) => self.token_user(path, auth)?.is_some(),
+        })
+    }
+
+    fn token_user(
+        &self,
+        path: &std::path::Path,
+        auth: &Handle,
+    ) -> josh::JoshResult<Option<String>> {
+        let (username, token) = match (auth.bearer_token(), auth.parse()) {
+            (Some(token), _) => (None, token),
+            (None, Some((username, password))) => (Some(username), password),
+            (None, None) => return Ok(None),
+        };
+
+        Ok(read_entries(path)?
+            .into_iter()
+            .find(|(user, secret)| {
+                username.as_ref().is_none_or(|u| u == user) && same_secret(secret, &token)
+            })
+            .map(|(user, _)| user))
+    }
+
+    /// Name of the user a request was made by, only meaningful after `check` succeeded
+    pub fn user(&self, auth: &Handle) -> Option<String> {
+        match self {
+            Backend::Upstream | Backend::Htpasswd(_) => auth.parse().map(|(user, _)| user),
+            Backend::Tokens(path) => self.token_user(path, auth).ok().flatten(),
+        }
+    }
+
+    /// Credentials to use for the upstream. Only the upstream backend
+    /// passes on the credentials of the request.
+    pub fn upstream_auth(&self, auth: &Handle) -> Handle {
+        match self {
+            Backend::Upstream => auth.clone(),
+            _ => Handle { hash: None },
+        }
+    }
+}
@@ -3,8 +3,8 @@ extern crate lazy_static;
 extern crate clap;
 
 use clap::Parser;
-use josh_proxy::cli;
 use josh_proxy::{FetchError, MetaConfig, RemoteAuth, RepoConfig, RepoUpdate, run_git_with_auth};
+use josh_proxy::{cli, config};
 use opentelemetry::global;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::Layer;
@@ -51,12 +51,15 @@ type Polls =
     Arc<std::sync::Mutex<std::collections::HashSet<(String, josh_proxy::auth::Handle, String)>>>;
 
 type HeadsMap = Arc<std::sync::RwLock<std::collections::HashMap<String, String>>>;
+type Subscriptions = Arc<std::sync::Mutex<josh_proxy::notify::Subscriptions>>;
 
 #[derive(Serialize, Clone, Debug)]
 enum JoshProxyUpstream {
     Http(String),
     Ssh(String),
     Both { http: String, ssh: String },
+    // Repos on the local filesystem, used for requests over both protocols
+    Local(String),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -74,28 +77,89 @@ impl JoshProxyUpstream {
             | (JoshProxyUpstream::Both { http: _, ssh }, UpstreamProtocol::Ssh) => {
                 Some(ssh.clone())
             }
+            (JoshProxyUpstream::Local(url), _) => Some(url.clone()),
             _ => None,
         }
     }
 }
 
+// An upstream together with the path prefix of the repos it serves and
+// the credentials used to access it
+#[derive(Debug)]
+struct Route {
+    prefix: String,
+    upstream: JoshProxyUpstream,
+    service_auth: Option<PathBuf>,
+    service_ssh_key: Option<PathBuf>,
+    auth: josh_proxy::auth::Backend,
+}
+
+// The parts of the service that are derived from the configuration,
+// they are replaced when the configuration is reloaded
+struct Settings {
+    // Longest prefix first, the default route without prefix last
+    routes: Vec<Route>,
+    auth: josh_proxy::auth::Backend,
+    // Users and groups files given on the command line
+    acl: Option<josh::AclFiles>,
+}
+
+impl Settings {
+    fn route(&self, upstream_repo: &str) -> Option<&Route> {
+        self.routes.iter().find(|route| {
+            upstream_repo
+                .strip_prefix(&route.prefix)
+                .is_some_and(|path| route.prefix.is_empty() || path.starts_with('/'))
+        })
+    }
+
+    // How requests for `upstream_repo` are authenticated
+    fn auth_for(&self, upstream_repo: &str) -> &josh_proxy::auth::Backend {
+        self.route(upstream_repo)
+            .map(|route| &route.auth)
+            .unwrap_or(&self.auth)
+    }
+
+    // Url of `upstream_repo` on the upstream serving it
+    fn remote_url(&self, protocol: UpstreamProtocol, upstream_repo: &str) -> Option<String> {
+        let route = self.route(upstream_repo)?;
+        let path = &upstream_repo[route.prefix.len()..];
+
+        // Repos of a local upstream must stay below its directory
+        if matches!(route.upstream, JoshProxyUpstream::Local(_))
+            && path.split('/').any(|component| component == "..")
+        {
+            return None;
+        }
+
+        Some(route.upstream.get(protocol)? + path)
+    }
+}
+
 #[derive(Clone)]
 struct JoshProxyService {
     port: String,
     repo_path: std::path::PathBuf,
-    upstream: JoshProxyUpstream,
+    settings: Arc<RwLock<Arc<Settings>>>,
     fetch_timers: Arc<RwLock<FetchTimers>>,
     heads_map: HeadsMap,
     fetch_permits: Arc<std::sync::Mutex<HashMap<String, Arc<tokio::sync::Semaphore>>>>,
     filter_permits: Arc<tokio::sync::Semaphore>,
     poll: Polls,
+    subscriptions: Subscriptions,
+}
+
+impl JoshProxyService {
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap().clone()
+    }
 }
 
 impl std::fmt::Debug for JoshProxyService {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("JoshProxyService")
             .field("repo_path", &self.repo_path)
-            .field("upstream", &self.upstream)
+            .field("routes", &self.settings().routes)
             .finish()
     }
 }
@@ -116,7 +180,7 @@ async fn fetch_needed(
 
         if let Some(last) = last {
             let since = std::time::Instant::now().duration_since(last);
-            let max = std::time::Duration::from_secs(ARGS.cache_duration);
+            let max = std::time::Duration::from_secs(config::current().cache_duration);
 
             tracing::trace!("last: {:?}, since: {:?}, max: {:?}", last, since, max);
             since < max
@@ -225,7 +289,11 @@ async fn fetch_upstream(
         .entry(upstream_repo.clone())
         .or_insert(Arc::new(tokio::sync::Semaphore::new(1)))
         .clone();
+    let wait_timer = josh_proxy::metrics::SEMAPHORE_WAIT
+        .with_label_values(&["fetch"])
+        .start_timer();
     let permit = semaphore.acquire().await;
+    wait_timer.observe_duration();
 
     // Check the fetch condition once again after locking the semaphore, as an unknown
     // amount of time might have passed and the outcome of this check might have changed
@@ -243,6 +311,7 @@ async fn fetch_upstream(
         return Ok(());
     }
 
+    let fetch_timer = josh_proxy::metrics::FETCH_DURATION.start_timer();
     let fetch_result = {
         let span = tracing::span!(tracing::Level::INFO, "fetch_refs_from_url");
 
@@ -263,6 +332,10 @@ async fn fetch_upstream(
         })
         .await?
     };
+    fetch_timer.observe_duration();
+    if fetch_result.is_err() {
+        josh_proxy::metrics::FETCH_FAILURES.inc();
+    }
 
     let hres = {
         let span = tracing::span!(tracing::Level::INFO, "get_head");
@@ -291,12 +364,14 @@ async fn fetch_upstream(
         fetch_timers
             .write()?
             .insert(remote_url.clone(), std::time::Instant::now());
+        // Don't make the request wait for the subscribers' filters
+        tokio::spawn(notify_subscribers(service.clone(), upstream_repo.clone()).in_current_span());
     }
 
     match (fetch_result, remote_auth) {
         (Ok(_), RemoteAuth::Http { auth }) => {
             if let Some((auth_user, _)) = auth.parse() {
-                if matches!(&ARGS.poll_user, Some(user) if auth_user == user.as_str()) {
+                if matches!(&config::current().poll, Some(user) if auth_user == user.as_str()) {
                     service
                         .poll
                         .lock()?
@@ -311,6 +386,32 @@ async fn fetch_upstream(
     }
 }
 
+// Update the filtered refs of the repo's subscriptions and send out events for the
+// ones that changed
+async fn notify_subscribers(service: Arc<JoshProxyService>, upstream_repo: String) {
+    let subscriptions = match service.subscriptions.lock() {
+        Ok(subscriptions) => subscriptions.get(&upstream_repo).cloned(),
+        Err(_) => None,
+    };
+    let subscriptions = match subscriptions {
+        Some(subscriptions) => subscriptions,
+        None => return,
+    };
+
+    let repo_path = service.repo_path.clone();
+    let events = tokio::task::spawn_blocking(move || {
+        josh_proxy::notify::update_filtered_refs(&repo_path, &upstream_repo, &subscriptions)
+    })
+    .in_current_span()
+    .await;
+
+    match events {
+        Ok(Ok(events)) => josh_proxy::notify::send(events).await,
+        Ok(Err(e)) => tracing::warn!("notify_subscribers: {}", e.0),
+        Err(e) => tracing::warn!("notify_subscribers: {}", e),
+    }
+}
+
 async fn static_paths(
     service: &JoshProxyService,
     path: &str,
@@ -323,7 +424,7 @@ async fn static_paths(
         )));
     }
     if path == "/remote" {
-        return match service.upstream.get(UpstreamProtocol::Http) {
+        return match service.settings().remote_url(UpstreamProtocol::Http, "") {
             None => Ok(Some(make_response(
                 hyper::Body::from("HTTP remote is not configured"),
                 hyper::StatusCode::SERVICE_UNAVAILABLE,
@@ -349,10 +450,12 @@ async fn static_paths(
         let body_str = tokio::task::spawn_blocking(move || -> josh::JoshResult<_> {
             let transaction_mirror =
                 josh::cache::Transaction::open(&service.repo_path.join("mirror"), None)?;
+            transaction_mirror.disable_access_tracking();
             josh::housekeeping::discover_filter_candidates(&transaction_mirror)?;
             if refresh {
                 let transaction_overlay =
                     josh::cache::Transaction::open(&service.repo_path.join("overlay"), None)?;
+                transaction_overlay.disable_access_tracking();
                 josh::housekeeping::refresh_known_filters(
                     &transaction_mirror,
                     &transaction_overlay,
@@ -372,16 +475,29 @@ async fn static_paths(
     Ok(None)
 }
 
-async fn repo_update_fn(req: Request<hyper::Body>) -> josh::JoshResult<Response<hyper::Body>> {
-    let body = hyper::body::to_bytes(req.into_body()).await;
+async fn repo_update_fn(
+    serv: Arc<JoshProxyService>,
+    req: Request<hyper::Body>,
+) -> josh::JoshResult<Response<hyper::Body>> {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let repo_update: RepoUpdate = serde_json::from_str(std::str::from_utf8(&body)?)?;
+
+    // Subscribers of the repo get notified after fetching the pushed changes
+    let upstream_repo = format!("/{}", josh::from_ns(&repo_update.base_ns));
+    let refetch = if serv.subscriptions.lock()?.contains_key(&upstream_repo) {
+        Some((
+            upstream_repo,
+            repo_update.remote_url.clone(),
+            repo_update.remote_auth.clone(),
+        ))
+    } else {
+        None
+    };
 
     let s = tracing::span!(tracing::Level::TRACE, "repo update worker");
 
     let result = tokio::task::spawn_blocking(move || {
         let _e = s.enter();
-        let body = body?;
-        let buffer = std::str::from_utf8(&body)?;
-        let repo_update: RepoUpdate = serde_json::from_str(buffer)?;
         let context_propagator = repo_update.context_propagator.clone();
         let parent_context =
             global::get_text_map_propagator(|propagator| propagator.extract(&context_propagator));
@@ -392,6 +508,22 @@ async fn repo_update_fn(req: Request<hyper::Body>) -> josh::JoshResult<Response<
     .instrument(Span::current())
     .await?;
 
+    if let (Ok(_), Some((upstream_repo, remote_url, remote_auth))) = (&result, refetch) {
+        let fetch_result = fetch_upstream(
+            serv,
+            upstream_repo,
+            &remote_auth,
+            remote_url,
+            None,
+            None,
+            true,
+        )
+        .await;
+        if let Err(FetchError::Other(e)) = fetch_result {
+            tracing::warn!("repo_update_fn: fetch after push failed: {}", e.0);
+        }
+    }
+
     Ok(match result {
         Ok(stderr) => Response::builder()
             .status(hyper::StatusCode::OK)
@@ -402,6 +534,157 @@ async fn repo_update_fn(req: Request<hyper::Body>) -> josh::JoshResult<Response<
     }?)
 }
 
+// Called by the upstream on every push, so filtered views can be updated right away
+// instead of waiting for the next poll or request
+// Webhooks of the upstream of an `[[upstream]]` entry are sent to "/~/webhook<prefix>",
+// as the payload only names the repo as seen by the upstream
+async fn handle_webhook(
+    serv: Arc<JoshProxyService>,
+    req: Request<hyper::Body>,
+    prefix: &str,
+) -> josh::JoshResult<Response<hyper::Body>> {
+    if req.method() != hyper::Method::POST {
+        return Ok(make_response(
+            hyper::Body::empty(),
+            StatusCode::METHOD_NOT_ALLOWED,
+        ));
+    }
+
+    let secret = match config::current().webhook_secret.clone() {
+        Some(secret) => secret,
+        None => {
+            return Ok(make_response(
+                hyper::Body::from("Webhooks are not configured"),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ));
+        }
+    };
+
+    let headers = req.headers().clone();
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    if !josh_proxy::webhook::verify(&headers, &body, &secret) {
+        return Ok(make_response(
+            hyper::Body::from("Invalid webhook signature"),
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+
+    let upstream_repo = match josh_proxy::webhook::parse(&headers, &body) {
+        Ok(josh_proxy::webhook::Event::Push(upstream_repo)) => {
+            format!("{}{}", prefix, upstream_repo)
+        }
+        Ok(josh_proxy::webhook::Event::Ignored) => {
+            return Ok(make_response(
+                hyper::Body::from("Ignored event"),
+                StatusCode::OK,
+            ));
+        }
+        Err(e) => {
+            return Ok(make_response(
+                hyper::Body::from(format!("Invalid webhook payload: {}", e.0)),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let settings = serv.settings();
+    if settings
+        .route(&upstream_repo)
+        .map(|route| route.prefix.as_str())
+        != Some(prefix)
+    {
+        return Ok(make_response(
+            hyper::Body::from(format!("No upstream configured for {}", upstream_repo)),
+            StatusCode::NOT_FOUND,
+        ));
+    }
+    let remote_url = match settings.remote_url(UpstreamProtocol::Http, &upstream_repo) {
+        Some(remote_url) => remote_url,
+        None => {
+            return Ok(make_response(
+                hyper::Body::from("HTTP remote is not configured"),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ));
+        }
+    };
+
+    // Use the credentials of the poll user if the repo is polled
+    let polled_auth = serv
+        .poll
+        .lock()?
+        .iter()
+        .find(|(repo, _, url)| repo == &upstream_repo && url == &remote_url)
+        .map(|(_, auth, _)| auth.clone());
+    let service_auth = settings
+        .route(&upstream_repo)
+        .and_then(|route| route.service_auth.as_ref());
+    let auth = match (service_auth, polled_auth) {
+        (Some(path), _) => read_service_credentials(path)?,
+        (None, Some(auth)) => auth,
+        (None, None) => josh_proxy::auth::Handle { hash: None },
+    };
+
+    match fetch_upstream(
+        serv.clone(),
+        upstream_repo.clone(),
+        &RemoteAuth::Http { auth },
+        remote_url,
+        None,
+        None,
+        true,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(FetchError::AuthRequired) => {
+            return Ok(make_response(
+                hyper::Body::from("Access to upstream repo denied"),
+                StatusCode::FORBIDDEN,
+            ));
+        }
+        Err(FetchError::Other(e)) => {
+            return Ok(make_response(
+                hyper::Body::from(e.0),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    }
+
+    let repo_path = serv.repo_path.clone();
+    let refreshed = {
+        let upstream_repo = upstream_repo.clone();
+        tokio::task::spawn_blocking(move || -> josh::JoshResult<_> {
+            let transaction_mirror =
+                josh::cache::Transaction::open(&repo_path.join("mirror"), None)?;
+            let transaction_overlay =
+                josh::cache::Transaction::open(&repo_path.join("overlay"), None)?;
+            transaction_mirror.disable_access_tracking();
+            transaction_overlay.disable_access_tracking();
+            transaction_overlay
+                .repo()
+                .odb()?
+                .add_disk_alternate(repo_path.join("mirror").join("objects").to_str().unwrap())?;
+            josh::housekeeping::refresh_filters(
+                &transaction_mirror,
+                &transaction_overlay,
+                &upstream_repo,
+            )
+        })
+        .in_current_span()
+        .await??
+    };
+
+    Ok(make_response(
+        hyper::Body::from(format!(
+            "Fetched {}, refreshed {} filters\n",
+            upstream_repo,
+            refreshed.len()
+        )),
+        StatusCode::OK,
+    ))
+}
+
 fn resolve_ref(
     transaction: &josh::cache::Transaction,
     repo: &str,
@@ -419,22 +702,29 @@ fn resolve_ref(
         .ok_or(josh_error("Could not resolve ref"))?)
 }
 
-#[tracing::instrument(skip(service))]
+#[tracing::instrument(skip(service, settings))]
 async fn do_filter(
     repo_path: std::path::PathBuf,
     service: Arc<JoshProxyService>,
+    settings: Arc<Settings>,
     meta: josh_proxy::MetaConfig,
     temp_ns: Arc<josh_proxy::TmpGitNamespace>,
     filter: josh::filter::Filter,
     head_ref: &HeadRef,
-) -> josh::JoshResult<()> {
+    user: &str,
+) -> josh::JoshResult<bool> {
+    let wait_timer = josh_proxy::metrics::SEMAPHORE_WAIT
+        .with_label_values(&["filter"])
+        .start_timer();
     let permit = service.filter_permits.acquire().await;
+    wait_timer.observe_duration();
     let heads_map = service.heads_map.clone();
 
     let tracing_span = tracing::span!(tracing::Level::INFO, "do_filter worker");
     let head_ref = head_ref.clone();
+    let user = user.to_string();
 
-    tokio::task::spawn_blocking(move || {
+    let permitted = tokio::task::spawn_blocking(move || {
         let _span_guard = tracing_span.enter();
         tracing::trace!("in do_filter worker");
         let filter_spec = josh::filter::spec(filter);
@@ -512,7 +802,20 @@ async fn do_filter(
         t2.repo()
             .odb()?
             .add_disk_alternate(repo_path.join("mirror").join("objects").to_str().unwrap())?;
-        let (updated_refs, _) = josh::filter_refs(&t2, filter, &refs_list, josh::filter::empty());
+        let permissions = permissions_filter(&settings, &meta, &user, filter)?;
+        let (updated_refs, errors) = josh::filter_refs(&t2, filter, &refs_list, permissions);
+        let filter_label = josh_proxy::metrics::filter_label(&josh::filter::spec(filter));
+        josh_proxy::metrics::FILTER_COMPUTATIONS
+            .with_label_values(&[&filter_label])
+            .inc();
+        josh_proxy::metrics::FILTER_CACHE_MISSES
+            .with_label_values(&[&filter_label])
+            .inc_by(t2.misses() as u64);
+        // Other errors only leave the affected refs out, like without permissions
+        if errors.iter().any(|(_, e)| e.is_missing_permissions()) {
+            tracing::warn!(user = %user, refs = ?errors, "do_filter: access denied");
+            return Ok(false);
+        }
         let mut updated_refs = josh_proxy::refs_locking(updated_refs, &meta);
         josh::housekeeping::namespace_refs(&mut updated_refs, temp_ns.name());
         josh::update_refs(&t2, &mut updated_refs, &temp_ns.reference(&head_ref));
@@ -525,13 +828,13 @@ async fn do_filter(
             )
             .ok();
 
-        Ok::<_, JoshError>(())
+        Ok::<_, JoshError>(true)
     })
     .await??;
 
     std::mem::drop(permit);
 
-    Ok(())
+    Ok(permitted)
 }
 
 fn make_response(body: hyper::Body, code: hyper::StatusCode) -> Response<hyper::Body> {
@@ -547,7 +850,7 @@ async fn handle_ui_request(
     resource_path: &str,
 ) -> josh::JoshResult<Response<hyper::Body>> {
     // Proxy: can be used for UI development or to serve a different UI
-    if let Some(proxy) = &ARGS.static_resource_proxy_target {
+    if let Some(proxy) = &config::current().static_resource_proxy_target {
         let client_ip = IpAddr::from_str("127.0.0.1").unwrap();
         return match hyper_reverse_proxy::call(client_ip, proxy, req).await {
             Ok(response) => Ok(response),
@@ -583,17 +886,16 @@ async fn handle_ui_request(
 
 async fn query_meta_repo(
     serv: Arc<JoshProxyService>,
+    settings: &Settings,
     meta_repo: &str,
     upstream_protocol: UpstreamProtocol,
     upstream_repo: &str,
     remote_auth: &RemoteAuth,
 ) -> josh::JoshResult<josh_proxy::MetaConfig> {
-    let upstream = serv
-        .upstream
-        .get(upstream_protocol)
+    let remote_url = settings
+        .remote_url(upstream_protocol, meta_repo)
         .ok_or(josh_error("no remote specified for the requested protocol"))?;
 
-    let remote_url = [upstream.as_str(), meta_repo].join("");
     match fetch_upstream(
         serv.clone(),
         meta_repo.to_owned(),
@@ -648,57 +950,119 @@ async fn query_meta_repo(
         meta.refs_lock = serde_yaml::from_str(&meta_blob)?;
     }
 
+    let users = josh::filter::tree::get_blob(
+        transaction.repo(),
+        &meta_tree,
+        std::path::Path::new("users.yml"),
+    );
+    let groups = josh::filter::tree::get_blob(
+        transaction.repo(),
+        &meta_tree,
+        std::path::Path::new("groups.yml"),
+    );
+
+    if !users.is_empty() && !groups.is_empty() {
+        meta.acl = Some(josh_proxy::Acl { users, groups });
+    }
+
     Ok(meta)
 }
 
+fn acl_enabled(settings: &Settings, meta: &MetaConfig) -> bool {
+    settings.acl.is_some() || meta.acl.is_some()
+}
+
+// Users and groups files given on the command line take precedence over
+// the ones in the meta repo. Without either every user can access all paths.
+fn permissions_filter(
+    settings: &Settings,
+    meta: &MetaConfig,
+    user: &str,
+    filter: josh::filter::Filter,
+) -> josh::JoshResult<josh::filter::Filter> {
+    let (whitelist, blacklist) = if let Some(acl) = &settings.acl {
+        acl.acl(user, &meta.config.repo)?
+    } else if let Some(acl) = &meta.acl {
+        josh::parse_acl(&acl.users, &acl.groups, user, &meta.config.repo)?
+    } else {
+        return Ok(josh::filter::empty());
+    };
+
+    Ok(josh::filter::make_permissions_filter(
+        filter, whitelist, blacklist,
+    ))
+}
+
+fn access_denied_response() -> Response<hyper::Body> {
+    make_response(
+        hyper::Body::from("Access denied: the requested view contains paths you can't access"),
+        hyper::StatusCode::FORBIDDEN,
+    )
+}
+
 async fn make_meta_config(
     serv: Arc<JoshProxyService>,
+    proxy_config: &config::Config,
+    settings: &Settings,
     remote_auth: &RemoteAuth,
     upstream_protocol: UpstreamProtocol,
     parsed_url: &FilteredRepoUrl,
 ) -> josh::JoshResult<MetaConfig> {
-    let meta_repo = std::env::var("JOSH_META_REPO");
-    let auth_token = std::env::var("JOSH_META_AUTH_TOKEN");
-
-    match meta_repo {
-        Err(_) => Ok(MetaConfig {
+    match &proxy_config.meta_repo {
+        None => Ok(MetaConfig {
             config: RepoConfig {
                 repo: parsed_url.upstream_repo.clone(),
                 ..Default::default()
             },
             ..Default::default()
         }),
-        Ok(meta_repo) => {
+        Some(meta_repo) => {
             let auth = match remote_auth {
-                RemoteAuth::Ssh { auth_socket } => RemoteAuth::Ssh {
-                    auth_socket: auth_socket.clone(),
-                },
                 RemoteAuth::Http { auth } => {
-                    let auth = if let Ok(token) = auth_token {
-                        josh_proxy::auth::add_auth(&token)?
+                    let auth = if let Some(token) = &proxy_config.meta_auth_token {
+                        josh_proxy::auth::add_auth(token)?
                     } else {
                         auth.clone()
                     };
 
                     RemoteAuth::Http { auth }
                 }
+                remote_auth => remote_auth.clone(),
             };
 
-            query_meta_repo(
+            let meta = query_meta_repo(
                 serv.clone(),
-                &meta_repo,
+                settings,
+                meta_repo,
                 upstream_protocol,
                 &parsed_url.upstream_repo,
                 &auth,
             )
-            .await
+            .await?;
+
+            let mut subscriptions = serv.subscriptions.lock()?;
+            let changed = if meta.config.notify.is_empty() {
+                subscriptions.remove(&meta.config.repo).is_some()
+            } else {
+                subscriptions.insert(meta.config.repo.clone(), meta.config.notify.clone())
+                    != Some(meta.config.notify.clone())
+            };
+            if changed {
+                if let Err(e) =
+                    josh_proxy::notify::save_subscriptions(&serv.repo_path, &subscriptions)
+                {
+                    tracing::warn!("failed to save subscriptions: {}", e.0);
+                }
+            }
+
+            Ok(meta)
         }
     }
 }
 
 async fn ssh_list_refs(
     url: &str,
-    auth_socket: std::path::PathBuf,
+    remote_auth: RemoteAuth,
     refs: Option<&[&str]>,
 ) -> JoshResult<HashMap<String, String>> {
     let temp_dir = tempfile::TempDir::with_prefix("josh")?;
@@ -717,12 +1081,8 @@ async fn ssh_list_refs(
     let result = tokio::task::spawn_blocking(move || -> JoshResult<(String, String, i32)> {
         let command = command.iter().map(String::as_str).collect::<Vec<_>>();
 
-        let (stdout, stderr, code) = run_git_with_auth(
-            temp_dir.path(),
-            &command,
-            &RemoteAuth::Ssh { auth_socket },
-            None,
-        )?;
+        let (stdout, stderr, code) =
+            run_git_with_auth(temp_dir.path(), &command, &remote_auth, None)?;
 
         Ok((stdout, stderr, code))
     })
@@ -903,17 +1263,11 @@ async fn serve_namespace(
     }
 }
 
-fn is_repo_blocked(config: &RepoConfig) -> bool {
-    let block = std::env::var("JOSH_REPO_BLOCK").unwrap_or("".to_owned());
-    let block = block.split(';').collect::<Vec<_>>();
-
-    for b in block {
-        if b == config.repo {
-            return true;
-        }
-    }
-
-    false
+fn is_repo_blocked(config: &config::Config, repo_config: &RepoConfig) -> bool {
+    config
+        .repo_block
+        .iter()
+        .any(|blocked| blocked == &repo_config.repo)
 }
 
 #[derive(Clone, Debug)]
@@ -949,6 +1303,7 @@ fn make_repo_update(
     serv: Arc<JoshProxyService>,
     filter: josh::filter::Filter,
     remote_auth: RemoteAuth,
+    pusher: Option<String>,
     meta: &MetaConfig,
     repo_path: &Path,
     ns: Arc<josh_proxy::TmpGitNamespace>,
@@ -959,6 +1314,7 @@ fn make_repo_update(
         refs: HashMap::new(),
         remote_url: remote_url.to_string(),
         remote_auth,
+        pusher,
         port: serv.port.clone(),
         filter_spec: josh::filter::spec(filter),
         base_ns: josh::to_ns(&meta.config.repo),
@@ -1018,13 +1374,24 @@ async fn handle_serve_namespace_request(
     eprintln!("params: {:?}", params);
     eprintln!("parsed_url.upstream_repo: {:?}", parsed_url.upstream_repo);
 
-    let auth_socket = params.ssh_socket.clone();
-    let remote_auth = RemoteAuth::Ssh {
-        auth_socket: auth_socket.clone(),
+    // Snapshot the configuration once, like for http requests
+    let config = config::current();
+    let settings = serv.settings();
+
+    let service_ssh_key = settings
+        .route(&parsed_url.upstream_repo)
+        .and_then(|route| route.service_ssh_key.clone());
+    let remote_auth = match service_ssh_key {
+        Some(key_path) => RemoteAuth::SshKey { key_path },
+        None => RemoteAuth::Ssh {
+            auth_socket: params.ssh_socket.clone(),
+        },
     };
 
     let meta_config = match make_meta_config(
         serv.clone(),
+        &config,
+        &settings,
         &remote_auth,
         UpstreamProtocol::Ssh,
         &parsed_url,
@@ -1040,15 +1407,15 @@ async fn handle_serve_namespace_request(
         }
     };
 
-    if is_repo_blocked(&meta_config.config) {
+    if is_repo_blocked(&config, &meta_config.config) {
         return Ok(make_response(
             hyper::Body::from("Access to this repo is blocked via JOSH_REPO_BLOCK"),
             StatusCode::UNPROCESSABLE_ENTITY,
         ));
     }
 
-    let upstream = match serv.upstream.get(UpstreamProtocol::Ssh) {
-        Some(upstream) => upstream,
+    let remote_url = match settings.remote_url(UpstreamProtocol::Ssh, &meta_config.config.repo) {
+        Some(remote_url) => remote_url,
         None => {
             return Ok(make_response(
                 hyper::Body::from("SSH remote is not configured"),
@@ -1057,7 +1424,6 @@ async fn handle_serve_namespace_request(
         }
     };
 
-    let remote_url = upstream + meta_config.config.repo.as_str();
     let head_ref = head_ref_or_default(&parsed_url.headref);
 
     let resolved_ref = match params.command {
@@ -1069,7 +1435,7 @@ async fn handle_serve_namespace_request(
         _ => {
             let remote_refs = [head_ref.get()];
             let remote_refs =
-                match ssh_list_refs(&remote_url, auth_socket, Some(&remote_refs)).await {
+                match ssh_list_refs(&remote_url, remote_auth.clone(), Some(&remote_refs)).await {
                     Ok(remote_refs) => remote_refs,
                     Err(e) => {
                         return Ok(make_response(
@@ -1134,7 +1500,7 @@ async fn handle_serve_namespace_request(
 
     let filter = josh::filter::chain(
         query_filter,
-        match &ARGS.filter_prefix {
+        match &config.filter_prefix {
             Some(filter_prefix) => {
                 let filter_prefix = match josh::filter::parse(filter_prefix) {
                     Ok(filter) => filter,
@@ -1155,8 +1521,20 @@ async fn handle_serve_namespace_request(
         },
     );
 
-    let temp_ns = match prepare_namespace(serv.clone(), &meta_config, filter, &head_ref).await {
-        Ok(ns) => ns,
+    // SSH connections don't carry a user name, so only paths accessible
+    // to anonymous users can be served
+    let temp_ns = match prepare_namespace(
+        serv.clone(),
+        settings.clone(),
+        &meta_config,
+        filter,
+        &head_ref,
+        "",
+    )
+    .await
+    {
+        Ok(Some(ns)) => ns,
+        Ok(None) => return Ok(access_denied_response()),
         Err(e) => {
             return Ok(make_response(
                 hyper::Body::from(e.to_string()),
@@ -1171,6 +1549,7 @@ async fn handle_serve_namespace_request(
         serv.clone(),
         filter,
         remote_auth,
+        None,
         &meta_config,
         &overlay_path,
         temp_ns.clone(),
@@ -1196,6 +1575,11 @@ async fn call_service(
 ) -> josh::JoshResult<Response<hyper::Body>> {
     let (auth, req) = req_auth;
 
+    // Snapshot the configuration once, so a reload during the request doesn't
+    // mix the old and the new one
+    let config = config::current();
+    let settings = serv.settings();
+
     let path = {
         let mut path = req.uri().path().to_owned();
         while path.contains("//") {
@@ -1216,13 +1600,19 @@ async fn call_service(
 
     // When exposed to internet, should be blocked
     if path == "/repo_update" {
-        return repo_update_fn(req).await;
+        return repo_update_fn(serv.clone(), req).await;
     }
 
     if path == "/serve_namespace" {
         return handle_serve_namespace_request(serv.clone(), req).await;
     }
 
+    if let Some(prefix) = path.strip_prefix("/~/webhook") {
+        if prefix.is_empty() || prefix.starts_with('/') {
+            return handle_webhook(serv.clone(), req, prefix).await;
+        }
+    }
+
     // Need to have some way of passing the filter (via remote path like what github does?)
     let parsed_url = {
         if let Some(parsed_url) = FilteredRepoUrl::from_str(&path) {
@@ -1266,9 +1656,28 @@ async fn call_service(
         }
     };
 
-    let remote_auth = RemoteAuth::Http { auth: auth.clone() };
+    // A verified client certificate identifies the user, and replaces the credentials
+    // when they are checked by the proxy itself
+    let identity = req
+        .extensions()
+        .get::<josh_proxy::tls::ClientIdentity>()
+        .cloned();
+    let user = match &identity {
+        Some(identity) => identity.0.clone(),
+        None => settings
+            .auth_for(&parsed_url.upstream_repo)
+            .user(&auth)
+            .unwrap_or_default(),
+    };
+    let authenticated =
+        identity.is_some() && settings.auth_for(&parsed_url.upstream_repo).is_local();
+    let remote_auth = RemoteAuth::Http {
+        auth: upstream_auth(&settings, &auth, &parsed_url.upstream_repo)?,
+    };
     let meta = make_meta_config(
         serv.clone(),
+        &config,
+        &settings,
         &remote_auth,
         UpstreamProtocol::Http,
         &parsed_url,
@@ -1280,16 +1689,7 @@ async fn call_service(
         josh::filter::parse(&parsed_url.filter_spec)?,
     );
 
-    let upstream = match serv.upstream.get(UpstreamProtocol::Http) {
-        Some(upstream) => upstream,
-        None => {
-            return Ok(make_response(
-                hyper::Body::from("HTTP remote is not configured"),
-                hyper::StatusCode::SERVICE_UNAVAILABLE,
-            ));
-        }
-    };
-    if let Some(filter_prefix) = &ARGS.filter_prefix {
+    if let Some(filter_prefix) = &config.filter_prefix {
         filter = josh::filter::chain(josh::filter::parse(filter_prefix)?, filter);
     }
     let mut fetch_repos = vec![meta.config.repo.clone()];
@@ -1304,7 +1704,21 @@ async fn call_service(
 
     ///////////////
 
-    let remote_url = upstream.clone() + meta.config.repo.as_str();
+    // Repos joined by the filter can be served by different upstreams
+    let mut fetch_urls = vec![];
+    for fetch_repo in fetch_repos.iter() {
+        match settings.remote_url(UpstreamProtocol::Http, fetch_repo) {
+            Some(fetch_url) => fetch_urls.push((fetch_repo.clone(), fetch_url)),
+            None => {
+                return Ok(make_response(
+                    hyper::Body::from("HTTP remote is not configured"),
+                    hyper::StatusCode::SERVICE_UNAVAILABLE,
+                ));
+            }
+        }
+    }
+
+    let remote_url = fetch_urls[0].1.clone();
     let headref = head_ref_or_default(&parsed_url.headref);
 
     if parsed_url.pathinfo.starts_with("/info/lfs") {
@@ -1314,11 +1728,10 @@ async fn call_service(
             .body(hyper::Body::empty())?);
     }
 
-    let http_auth_required = ARGS.require_auth && parsed_url.pathinfo == "/git-receive-pack";
+    let http_auth_required = config.require_auth && parsed_url.pathinfo == "/git-receive-pack";
 
-    for fetch_repo in fetch_repos.iter() {
-        let fetch_url = upstream.clone() + fetch_repo.as_str();
-        if is_repo_blocked(&meta.config) {
+    for (fetch_repo, fetch_url) in fetch_urls.iter() {
+        if is_repo_blocked(&config, &meta.config) {
             return Ok(make_response(
                 hyper::Body::from(formatdoc!(
                     r#"
@@ -1329,7 +1742,12 @@ async fn call_service(
             ));
         }
 
-        if !josh_proxy::auth::check_http_auth(&fetch_url, &auth, http_auth_required).await? {
+        if !authenticated
+            && !settings
+                .auth_for(fetch_repo)
+                .check(fetch_url, &auth, http_auth_required)
+                .await?
+        {
             tracing::trace!("require-auth");
             let builder = Response::builder()
                 .header(
@@ -1341,8 +1759,20 @@ async fn call_service(
         }
     }
 
+    // GraphQL queries can apply arbitrary filters, so they can't be restricted
+    // to the paths a user has access to
+    if (parsed_url.api == "/~/graphql" || parsed_url.api == "/~/graphiql")
+        && acl_enabled(&settings, &meta)
+    {
+        return Ok(make_response(
+            hyper::Body::from("GraphQL API is not available when access control is enabled"),
+            hyper::StatusCode::FORBIDDEN,
+        ));
+    }
+
     if parsed_url.api == "/~/graphql" {
-        return serve_graphql(serv, req, meta.config.repo.to_owned(), upstream, auth).await;
+        let auth = upstream_auth(&settings, &auth, &meta.config.repo)?;
+        return serve_graphql(serv, req, meta.config.repo.to_owned(), remote_url, auth).await;
     }
 
     if parsed_url.api == "/~/graphiql" {
@@ -1354,12 +1784,14 @@ async fn call_service(
         .await??);
     }
 
-    for fetch_repo in fetch_repos.iter() {
-        let fetch_url = upstream.clone() + fetch_repo.as_str();
+    for (fetch_repo, fetch_url) in fetch_urls.iter() {
+        let fetch_auth = RemoteAuth::Http {
+            auth: upstream_auth(&settings, &auth, fetch_repo)?,
+        };
         match fetch_upstream(
             serv.clone(),
             fetch_repo.to_owned(),
-            &remote_auth,
+            &fetch_auth,
             fetch_url.to_owned(),
             Some(headref.get()),
             None,
@@ -1390,10 +1822,31 @@ async fn call_service(
         req.uri().query().map(|x| x.to_string()),
         parsed_url.pathinfo.is_empty(),
     ) {
-        return serve_query(serv, q, meta.config.repo, filter, headref.get()).await;
+        let permissions = permissions_filter(&settings, &meta, &user, filter)?;
+        return serve_query(
+            serv,
+            q,
+            meta.config.repo,
+            filter,
+            permissions,
+            headref.get(),
+        )
+        .await;
     }
 
-    let temp_ns = prepare_namespace(serv.clone(), &meta, filter, &headref).await?;
+    let temp_ns = match prepare_namespace(
+        serv.clone(),
+        settings.clone(),
+        &meta,
+        filter,
+        &headref,
+        &user,
+    )
+    .await?
+    {
+        Some(ns) => ns,
+        None => return Ok(access_denied_response()),
+    };
     let overlay_path = serv.repo_path.join("overlay");
 
     let repo_update = make_repo_update(
@@ -1401,6 +1854,10 @@ async fn call_service(
         serv.clone(),
         filter,
         remote_auth,
+        settings
+            .route(&meta.config.repo)
+            .and_then(|route| route.service_auth.as_ref())
+            .map(|_| user.clone()),
         &meta,
         &overlay_path,
         temp_ns.clone(),
@@ -1448,6 +1905,7 @@ async fn serve_query(
     q: String,
     upstream_repo: String,
     filter: josh::filter::Filter,
+    permissions: josh::filter::Filter,
     head_ref: &str,
 ) -> josh::JoshResult<Response<hyper::Body>> {
     let tracing_span = tracing::span!(tracing::Level::TRACE, "render worker");
@@ -1479,8 +1937,7 @@ async fn serve_query(
                 .repo()
                 .refname_to_id(&transaction_mirror.refname(&head_ref))?
         };
-        let commit_id =
-            josh::filter_commit(&transaction, filter, commit_id, josh::filter::empty())?;
+        let commit_id = josh::filter_commit(&transaction, filter, commit_id, permissions)?;
 
         josh_templates::render(&transaction, "", commit_id, &q, true)
     })
@@ -1508,13 +1965,15 @@ async fn serve_query(
     })
 }
 
-#[tracing::instrument(skip(serv))]
+#[tracing::instrument(skip(serv, settings))]
 async fn prepare_namespace(
     serv: Arc<JoshProxyService>,
+    settings: Arc<Settings>,
     meta: &josh_proxy::MetaConfig,
     filter: josh::filter::Filter,
     head_ref: &HeadRef,
-) -> josh::JoshResult<std::sync::Arc<josh_proxy::TmpGitNamespace>> {
+    user: &str,
+) -> josh::JoshResult<Option<std::sync::Arc<josh_proxy::TmpGitNamespace>>> {
     let temp_ns = Arc::new(josh_proxy::TmpGitNamespace::new(
         &serv.repo_path.join("overlay"),
         tracing::Span::current(),
@@ -1522,17 +1981,18 @@ async fn prepare_namespace(
 
     let serv = serv.clone();
 
-    do_filter(
+    Ok(do_filter(
         serv.repo_path.clone(),
         serv.clone(),
+        settings,
         meta.clone(),
         temp_ns.to_owned(),
         filter,
         head_ref,
+        user,
     )
-    .await?;
-
-    Ok(temp_ns)
+    .await?
+    .then_some(temp_ns))
 }
 
 fn trace_http_response(trace_span: Span, response: &Response<hyper::Body>) {
@@ -1558,11 +2018,12 @@ fn trace_http_response(trace_span: Span, response: &Response<hyper::Body>) {
 /// Turn a list of [cli::Remote] into a [JoshProxyUpstream] struct.
 fn make_upstream(remotes: &Vec<cli::Remote>) -> josh::JoshResult<JoshProxyUpstream> {
     if remotes.is_empty() {
-        unreachable!() // already checked in the parser
+        unreachable!() // already checked when loading the configuration
     } else if remotes.len() == 1 {
         Ok(match &remotes[0] {
             cli::Remote::Http(url) => JoshProxyUpstream::Http(url.to_string()),
             cli::Remote::Ssh(url) => JoshProxyUpstream::Ssh(url.to_string()),
+            cli::Remote::Local(url) => JoshProxyUpstream::Local(url.to_string()),
         })
     } else if remotes.len() == 2 {
         Ok(match (&remotes[0], &remotes[1]) {
@@ -1570,6 +2031,11 @@ fn make_upstream(remotes: &Vec<cli::Remote>) -> josh::JoshResult<JoshProxyUpstre
             | (cli::Remote::Ssh(_), cli::Remote::Ssh(_)) => {
                 return Err(josh_error("two cli::remotes of the same type passed"));
             }
+            (cli::Remote::Local(_), _) | (_, cli::Remote::Local(_)) => {
+                return Err(josh_error(
+                    "a local remote can't be combined with other remotes",
+                ));
+            }
             (cli::Remote::Http(http_url), cli::Remote::Ssh(ssh_url))
             | (cli::Remote::Ssh(ssh_url), cli::Remote::Http(http_url)) => JoshProxyUpstream::Both {
                 http: http_url.to_string(),
@@ -1581,6 +2047,92 @@ fn make_upstream(remotes: &Vec<cli::Remote>) -> josh::JoshResult<JoshProxyUpstre
     }
 }
 
+fn make_auth_backend(
+    auth: cli::AuthBackend,
+    htpasswd: Option<&String>,
+    auth_tokens: Option<&String>,
+) -> josh::JoshResult<josh_proxy::auth::Backend> {
+    let file = |path: Option<&String>, backend: &str, option: &str| {
+        let path =
+            path.ok_or_else(|| josh_error(&format!("--auth={} requires --{}", backend, option)))?;
+        std::fs::metadata(path).map_err(|e| josh_error(&format!("can't read {}: {}", path, e)))?;
+        Ok::<_, JoshError>(PathBuf::from(path))
+    };
+
+    Ok(match auth {
+        cli::AuthBackend::Upstream => josh_proxy::auth::Backend::Upstream,
+        cli::AuthBackend::Htpasswd => {
+            josh_proxy::auth::Backend::Htpasswd(file(htpasswd, "htpasswd", "htpasswd")?)
+        }
+        cli::AuthBackend::Tokens => {
+            josh_proxy::auth::Backend::Tokens(file(auth_tokens, "tokens", "auth-tokens")?)
+        }
+    })
+}
+
+// The service credentials are read again for every request, so they can be
+// rotated without restarting the proxy
+fn read_service_credentials(path: &Path) -> josh::JoshResult<josh_proxy::auth::Handle> {
+    let credentials = std::fs::read_to_string(path)
+        .map_err(|e| josh_error(&format!("can't read {}: {}", path.display(), e)))?;
+    let credentials = credentials.trim();
+    if !credentials.contains(':') {
+        return Err(josh_error(&format!(
+            "{} does not contain \"user:token\"",
+            path.display()
+        )));
+    }
+
+    josh_proxy::auth::add_auth(credentials)
+}
+
+fn make_service_credentials(
+    auth: cli::AuthBackend,
+    upstream_credentials: &Option<String>,
+    upstream_ssh_key: &Option<String>,
+) -> josh::JoshResult<(Option<PathBuf>, Option<PathBuf>)> {
+    let canonical_path = |path: &String| {
+        std::fs::canonicalize(path).map_err(|e| josh_error(&format!("can't read {}: {}", path, e)))
+    };
+
+    let service_auth = match upstream_credentials {
+        Some(path) => {
+            if auth == cli::AuthBackend::Upstream {
+                return Err(josh_error(
+                    "--upstream-credentials requires --auth=htpasswd or --auth=tokens",
+                ));
+            }
+
+            let path = canonical_path(path)?;
+            read_service_credentials(&path)?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    let service_ssh_key = match upstream_ssh_key {
+        Some(path) => Some(canonical_path(path)?),
+        None => None,
+    };
+
+    Ok((service_auth, service_ssh_key))
+}
+
+// Credentials to access the upstream with on behalf of a request
+fn upstream_auth(
+    settings: &Settings,
+    auth: &josh_proxy::auth::Handle,
+    upstream_repo: &str,
+) -> josh::JoshResult<josh_proxy::auth::Handle> {
+    match settings
+        .route(upstream_repo)
+        .and_then(|route| route.service_auth.as_ref())
+    {
+        Some(path) => read_service_credentials(path),
+        None => Ok(settings.auth_for(upstream_repo).upstream_auth(auth)),
+    }
+}
+
 #[tracing::instrument(skip_all, fields(url.path, http.request.method))]
 async fn handle_http_request(
     proxy_service: Arc<JoshProxyService>,
@@ -1592,6 +2144,8 @@ async fn handle_http_request(
     span.record(URL_PATH, req.uri().path());
     span.record(HTTP_REQUEST_METHOD, req.method().to_string());
 
+    let endpoint = josh_proxy::metrics::endpoint(req.uri().path(), req.uri().query().is_some());
+
     async move {
         let response = if let Ok(req_auth) = josh_proxy::auth::strip_auth(req) {
             call_service(proxy_service, req_auth)
@@ -1612,6 +2166,9 @@ async fn handle_http_request(
         };
 
         trace_http_response(span.clone(), &response);
+        josh_proxy::metrics::HTTP_REQUESTS
+            .with_label_values(&[endpoint, response.status().as_str()])
+            .inc();
         response
     }
     .map(Ok::<_, hyper::http::Error>)
@@ -1622,59 +2179,95 @@ async fn handle_http_request(
 async fn run_proxy() -> josh::JoshResult<i32> {
     init_trace();
 
-    let addr = format!("[::]:{}", ARGS.port).parse()?;
-    let upstream = make_upstream(&ARGS.remote).map_err(|e| {
-        eprintln!("Upstream parsing error: {}", &e);
+    let config = config::load(&ARGS).map_err(|e| {
+        eprintln!("Configuration error: {}", &e);
         e
     })?;
 
-    let local = std::path::PathBuf::from(&ARGS.local.as_ref().unwrap());
+    let addr = format!("[::]:{}", config.port).parse()?;
+
+    let local = std::path::PathBuf::from(config.local.as_ref().unwrap());
     let local = if local.is_absolute() {
         local
     } else {
         std::env::current_dir()?.join(local)
     };
 
+    let settings = make_settings(&config).map_err(|e| {
+        eprintln!("Configuration error: {}", &e);
+        e
+    })?;
+
+    let tls_acceptor = make_tls_acceptor(&config).map_err(|e| {
+        eprintln!("TLS error: {}", &e);
+        e
+    })?;
+
+    // With TLS, the requests the proxy makes to itself go to a plain HTTP
+    // listener that is only reachable from the local host
+    let internal_listener = match tls_acceptor {
+        Some(_) => Some(std::net::TcpListener::bind("127.0.0.1:0")?),
+        None => None,
+    };
+    let port = match &internal_listener {
+        Some(listener) => listener.local_addr()?.port(),
+        None => config.port,
+    };
+
+    let metrics_addr = match config.metrics_port {
+        Some(port) => Some(format!("[::]:{}", port).parse()?),
+        None => None,
+    };
+
+    let no_background = config.no_background;
+    config::set(config);
+
     josh_proxy::create_repo(&local)?;
-    josh::cache::load(&local)?;
+    josh::cache::load_backend(&local, config::current().cache_backend)?;
+    josh_proxy::metrics::init();
 
     let proxy_service = Arc::new(JoshProxyService {
-        port: ARGS.port.to_string(),
+        port: port.to_string(),
         repo_path: local.to_owned(),
-        upstream,
+        settings: Arc::new(RwLock::new(Arc::new(settings))),
         fetch_timers: Arc::new(RwLock::new(FetchTimers::new())),
         heads_map: Arc::new(RwLock::new(std::collections::HashMap::new())),
         poll: Arc::new(std::sync::Mutex::new(std::collections::HashSet::new())),
+        subscriptions: Arc::new(std::sync::Mutex::new(
+            josh_proxy::notify::load_subscriptions(&local)?,
+        )),
         fetch_permits: Default::default(),
         filter_permits: Arc::new(tokio::sync::Semaphore::new(10)),
     });
 
     let ps = proxy_service.clone();
 
-    let make_service = make_service_fn(move |_| {
-        let proxy_service = proxy_service.clone();
-        let service = service_fn(move |req| {
-            let proxy_service = proxy_service.clone();
-            handle_http_request(proxy_service, req)
-        });
-
-        future::ok::<_, hyper::http::Error>(service)
-    });
-
-    let server = Server::bind(&addr).serve(make_service);
-
     println!("Now listening on {}", addr);
 
-    let server_future = server.with_graceful_shutdown(shutdown_signal());
+    let server_future = async move {
+        let proxy_future = async move {
+            match (tls_acceptor, internal_listener) {
+                (Some(tls_acceptor), Some(internal_listener)) => tokio::try_join!(
+                    serve_tls(addr, tls_acceptor, proxy_service.clone()),
+                    serve_http(Server::from_tcp(internal_listener)?, proxy_service),
+                )
+                .map(|_| ()),
+                _ => serve_http(Server::bind(&addr), proxy_service).await,
+            }
+        };
+        tokio::try_join!(proxy_future, serve_metrics(metrics_addr)).map(|_| ())
+    };
 
-    if ARGS.no_background {
+    if no_background {
         tokio::select!(
+            r = run_reload(ps.clone()) => println!("run_reload exited: {:?}", r),
             r = server_future => println!("http server exited: {:?}", r),
         );
     } else {
         tokio::select!(
-            r = run_housekeeping(local) => println!("run_housekeeping exited: {:?}", r),
+            r = run_housekeeping(ps.clone()) => println!("run_housekeeping exited: {:?}", r),
             r = run_polling(ps.clone()) => println!("run_polling exited: {:?}", r),
+            r = run_reload(ps.clone()) => println!("run_reload exited: {:?}", r),
             r = server_future => println!("http server exited: {:?}", r),
         );
     }
@@ -1682,6 +2275,200 @@ async fn run_proxy() -> josh::JoshResult<i32> {
     Ok(0)
 }
 
+// Settings of an `[[upstream]]` entry that are left out are taken from the top level
+fn make_route(config: &config::Config, upstream: &config::Upstream) -> josh::JoshResult<Route> {
+    let remote = make_upstream(&upstream.remote)
+        .map_err(|e| josh_error(&format!("Upstream parsing error: {}", e.0)))?;
+    let auth_backend = upstream.auth.unwrap_or(config.auth);
+    // There is no upstream to check the credentials of requests to local repos
+    if matches!(remote, JoshProxyUpstream::Local(_)) && auth_backend == cli::AuthBackend::Upstream {
+        return Err(josh_error(
+            "a local remote requires the htpasswd or tokens auth backend",
+        ));
+    }
+    let auth = make_auth_backend(
+        auth_backend,
+        upstream.htpasswd.as_ref().or(config.htpasswd.as_ref()),
+        upstream
+            .auth_tokens
+            .as_ref()
+            .or(config.auth_tokens.as_ref()),
+    )
+    .map_err(|e| josh_error(&format!("Auth backend error: {}", e.0)))?;
+    let (service_auth, service_ssh_key) = make_service_credentials(
+        auth_backend,
+        &upstream.upstream_credentials,
+        &upstream.upstream_ssh_key,
+    )
+    .map_err(|e| josh_error(&format!("Upstream credentials error: {}", e.0)))?;
+
+    Ok(Route {
+        prefix: upstream.prefix.clone(),
+        upstream: remote,
+        service_auth,
+        service_ssh_key,
+        auth,
+    })
+}
+
+fn make_settings(config: &config::Config) -> josh::JoshResult<Settings> {
+    let auth = make_auth_backend(
+        config.auth,
+        config.htpasswd.as_ref(),
+        config.auth_tokens.as_ref(),
+    )
+    .map_err(|e| josh_error(&format!("Auth backend error: {}", e.0)))?;
+
+    let mut routes = config
+        .upstream
+        .iter()
+        .map(|upstream| make_route(config, upstream))
+        .collect::<josh::JoshResult<Vec<_>>>()?;
+    routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+
+    if !config.remote.is_empty() {
+        routes.push(make_route(
+            config,
+            &config::Upstream {
+                prefix: "".to_string(),
+                remote: config.remote.clone(),
+                upstream_credentials: config.upstream_credentials.clone(),
+                upstream_ssh_key: config.upstream_ssh_key.clone(),
+                auth: None,
+                htpasswd: None,
+                auth_tokens: None,
+            },
+        )?);
+    }
+
+    let acl = match (&config.users, &config.groups) {
+        (Some(users), Some(groups)) => {
+            let users = std::fs::read_to_string(users)
+                .map_err(|_| josh_error("failed to read users file"))?;
+            let groups = std::fs::read_to_string(groups)
+                .map_err(|_| josh_error("failed to read groups file"))?;
+            Some(josh::AclFiles::parse(&users, &groups)?)
+        }
+        _ => None,
+    };
+
+    Ok(Settings { routes, auth, acl })
+}
+
+fn make_tls_acceptor(
+    config: &config::Config,
+) -> josh::JoshResult<Option<tokio_rustls::TlsAcceptor>> {
+    match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Ok(Some(josh_proxy::tls::make_acceptor(
+            Path::new(cert),
+            Path::new(key),
+            config.tls_client_ca.as_deref().map(Path::new),
+        )?)),
+        _ => Ok(None),
+    }
+}
+
+async fn serve_http(
+    builder: hyper::server::Builder<hyper::server::conn::AddrIncoming>,
+    proxy_service: Arc<JoshProxyService>,
+) -> josh::JoshResult<()> {
+    let make_service = make_service_fn(move |_| {
+        let proxy_service = proxy_service.clone();
+        let service = service_fn(move |req| {
+            let proxy_service = proxy_service.clone();
+            handle_http_request(proxy_service, req)
+        });
+
+        future::ok::<_, hyper::http::Error>(service)
+    });
+
+    builder
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+// The metrics are not served with the repos, where every client could read them
+async fn serve_metrics(addr: Option<std::net::SocketAddr>) -> josh::JoshResult<()> {
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return Ok(()),
+    };
+    println!("Serving metrics on {}", addr);
+
+    let make_service = make_service_fn(|_| {
+        future::ok::<_, hyper::http::Error>(service_fn(|req: Request<hyper::Body>| async move {
+            if req.uri().path() != "/~/metrics" {
+                return Ok(make_response(hyper::Body::empty(), StatusCode::NOT_FOUND));
+            }
+            match josh_proxy::metrics::render() {
+                Ok(metrics) => Response::builder()
+                    .status(StatusCode::OK)
+                    .header(hyper::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(hyper::Body::from(metrics)),
+                Err(e) => Ok(make_response(
+                    hyper::Body::from(e.0),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )),
+            }
+        }))
+    });
+
+    Server::bind(&addr)
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+async fn serve_tls(
+    addr: std::net::SocketAddr,
+    tls_acceptor: tokio_rustls::TlsAcceptor,
+    proxy_service: Arc<JoshProxyService>,
+) -> josh::JoshResult<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut shutdown => return Ok(()),
+        };
+
+        let tls_acceptor = tls_acceptor.clone();
+        let proxy_service = proxy_service.clone();
+
+        tokio::spawn(async move {
+            let stream = match tls_acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("serve_tls: handshake failed: {}", e);
+                    return;
+                }
+            };
+
+            let identity = josh_proxy::tls::client_identity(stream.get_ref().1);
+            let service = service_fn(move |mut req| {
+                if let Some(identity) = &identity {
+                    req.extensions_mut().insert(identity.clone());
+                }
+                handle_http_request(proxy_service.clone(), req)
+            });
+
+            if let Err(e) = hyper::server::conn::Http::new()
+                .serve_connection(stream, service)
+                .await
+            {
+                tracing::debug!("serve_tls: connection error: {}", e);
+            }
+        });
+    }
+}
+
 async fn run_polling(serv: Arc<JoshProxyService>) -> josh::JoshResult<()> {
     loop {
         let polls = serv.poll.lock()?.clone();
@@ -1712,14 +2499,62 @@ async fn run_polling(serv: Arc<JoshProxyService>) -> josh::JoshResult<()> {
     }
 }
 
-async fn run_housekeeping(local: std::path::PathBuf) -> josh::JoshResult<()> {
+// Reload the configuration on SIGHUP. Requests that are already running finish
+// with the previous configuration.
+async fn run_reload(serv: Arc<JoshProxyService>) -> josh::JoshResult<()> {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    while hangup.recv().await.is_some() {
+        match reload_config(&serv) {
+            Ok(()) => tracing::info!("reload: done"),
+            Err(e) => tracing::warn!("reload: keeping the current configuration: {}", e.0),
+        }
+    }
+
+    Ok(())
+}
+
+fn reload_config(serv: &JoshProxyService) -> josh::JoshResult<()> {
+    let config = config::reload(&ARGS)?;
+    let settings = make_settings(&config)?;
+
+    config::set(config);
+    *serv.settings.write()? = Arc::new(settings);
+
+    Ok(())
+}
+
+async fn run_housekeeping(serv: Arc<JoshProxyService>) -> josh::JoshResult<()> {
     let mut i: usize = 0;
     loop {
-        let local = local.clone();
-        tokio::task::spawn_blocking(move || {
-            josh::housekeeping::run(&local, (i % 60 == 0) && ARGS.gc)
+        let local = serv.repo_path.clone();
+        let config = config::current();
+        tokio::task::spawn_blocking(move || -> josh::JoshResult<()> {
+            let cache_gc = match i % 60 {
+                0 => config.cache_gc()?,
+                _ => None,
+            };
+            josh::housekeeping::run(
+                &local,
+                (i % 60 == 0) && config.gc,
+                !config.no_discover,
+                cache_gc.as_ref(),
+            )
         })
         .await??;
+
+        // Also sends the events of changes that were fetched while the subscribers
+        // couldn't be notified, like right before a restart
+        let subscribed = serv
+            .subscriptions
+            .lock()?
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        for upstream_repo in subscribed {
+            notify_subscribers(serv.clone(), upstream_repo).await;
+        }
+
         tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
         i += 1;
     }
@@ -1802,10 +2637,9 @@ async fn serve_graphql(
     serv: Arc<JoshProxyService>,
     req: Request<hyper::Body>,
     upstream_repo: String,
-    upstream: String,
+    remote_url: String,
     auth: josh_proxy::auth::Handle,
 ) -> josh::JoshResult<Response<hyper::Body>> {
-    let remote_url = upstream.clone() + upstream_repo.as_str();
     let parsed_request = match josh_proxy::juniper_hyper::parse_req(req).await {
         Ok(parsed_request) => {
             // Even though there's a mutex, it's just to manage access
@@ -1955,7 +2789,9 @@ async fn serve_graphql(
 
         for (oid, refname, repo) in to_push {
             let url = if let Some(repo) = repo {
-                format!("{}/{}", upstream, repo)
+                serv.settings()
+                    .remote_url(UpstreamProtocol::Http, &format!("/{}", repo))
+                    .ok_or(josh_error("no remote specified for the requested protocol"))?
             } else {
                 remote_url.clone()
             };
@@ -1,7 +1,18 @@
-#[derive(Clone, Debug)]
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(try_from = "String")]
 pub enum Remote {
     Http(String),
     Ssh(String),
+    // A directory with bare repos, always as a file:// url
+    Local(String),
+}
+
+impl TryFrom<String> for Remote {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        parse_remote(&s).map_err(|e| format!("{}: {}", e, s))
+    }
 }
 
 fn parse_remote(s: &str) -> Result<Remote, &'static str> {
@@ -10,16 +21,32 @@ fn parse_remote(s: &str) -> Result<Remote, &'static str> {
             Ok(Remote::Http(s.to_string()))
         }
         s if s.starts_with("ssh://") => Ok(Remote::Ssh(s.to_string())),
+        s if s.starts_with("file:///") => Ok(Remote::Local(s.trim_end_matches('/').to_string())),
+        s if s.starts_with('/') => Ok(Remote::Local(format!("file://{}", s.trim_end_matches('/')))),
         _ => return Err("unsupported scheme"),
     }
 }
 
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthBackend {
+    #[default]
+    Upstream,
+    Htpasswd,
+    Tokens,
+}
+
 #[derive(clap::Parser, Debug)]
 #[command(name = "josh-proxy")]
 pub struct Args {
-    #[arg(long, required = true, value_parser = parse_remote)]
+    #[arg(
+        long,
+        help = "TOML or YAML configuration file, overridden by the other options"
+    )]
+    pub config: Option<String>,
+    #[arg(long, value_parser = parse_remote)]
     pub remote: Vec<Remote>,
-    #[arg(long, required = true)]
+    #[arg(long)]
     pub local: Option<String>,
     #[arg(name = "poll", long)]
     pub poll_user: Option<String>,
@@ -27,6 +54,29 @@ pub struct Args {
     pub gc: bool,
     #[arg(long)]
     pub require_auth: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "How to check the credentials of requests [default: upstream]"
+    )]
+    pub auth: Option<AuthBackend>,
+    #[arg(long, help = "htpasswd file used by the htpasswd auth backend")]
+    pub htpasswd: Option<String>,
+    #[arg(
+        long,
+        help = "File with one \"user:token\" entry per line used by the tokens auth backend"
+    )]
+    pub auth_tokens: Option<String>,
+    #[arg(
+        long,
+        help = "File with \"user:token\" credentials used for all requests to an http upstream"
+    )]
+    pub upstream_credentials: Option<String>,
+    #[arg(
+        long,
+        help = "SSH private key used for all requests to an ssh upstream"
+    )]
+    pub upstream_ssh_key: Option<String>,
     #[arg(long)]
     pub no_background: bool,
 
@@ -36,17 +86,100 @@ pub struct Args {
     )]
     _n: Option<String>,
 
-    #[arg(long, default_value = "8000")]
-    pub port: u16,
+    #[arg(long, help = "[default: 8000]")]
+    pub port: Option<u16>,
     #[arg(
-        short,
-        default_value = "0",
-        help = "Duration between forced cache refresh"
+        long,
+        help = "Port of a separate listener serving the Prometheus metrics at /~/metrics"
+    )]
+    pub metrics_port: Option<u16>,
+    #[arg(
+        long,
+        help = "Serve HTTPS with the certificate (chain) in this PEM file"
+    )]
+    pub tls_cert: Option<String>,
+    #[arg(long, help = "PEM file with the private key of --tls-cert")]
+    pub tls_key: Option<String>,
+    #[arg(
+        long,
+        help = "Accept client certificates issued by the CA in this PEM file"
     )]
+    pub tls_client_ca: Option<String>,
+    #[arg(short, help = "Duration between forced cache refresh [default: 0]")]
     #[arg(long, short)]
-    pub cache_duration: u64,
+    pub cache_duration: Option<u64>,
+    #[arg(
+        long,
+        help = "Storage of the filter cache: sled, sqlite or memory [default: sled]"
+    )]
+    pub cache_backend: Option<josh::cache::BackendKind>,
+    #[arg(
+        long,
+        help = "Drop the cache of filters not used for this long, like \"90d\" or \"12h\""
+    )]
+    pub cache_max_age: Option<String>,
+    #[arg(
+        long,
+        help = "Drop the cache of the least recently used filters above this size, like \"500G\""
+    )]
+    pub cache_max_size: Option<String>,
     #[arg(long, help = "Proxy static resource requests to a different URL")]
     pub static_resource_proxy_target: Option<String>,
     #[arg(long, help = "Filter to be prefixed to all queries of this instance")]
     pub filter_prefix: Option<String>,
+    #[arg(long, help = "Users file restricting the paths each user can access")]
+    pub users: Option<String>,
+    #[arg(
+        long,
+        help = "Groups file with the path whitelist and blacklist of each group"
+    )]
+    pub groups: Option<String>,
+    #[arg(
+        long,
+        env = "JOSH_META_REPO",
+        help = "Repo on the upstream with the configuration of the other repos"
+    )]
+    pub meta_repo: Option<String>,
+    #[arg(
+        long,
+        env = "JOSH_META_AUTH_TOKEN",
+        hide_env_values = true,
+        help = "Credentials used to access the meta repo"
+    )]
+    pub meta_auth_token: Option<String>,
+    #[arg(
+        long,
+        env = "JOSH_REPO_BLOCK",
+        value_delimiter = ';',
+        help = "Upstream repos that can't be accessed"
+    )]
+    pub repo_block: Vec<String>,
+    #[arg(
+        long,
+        env = "JOSH_REWRITE_REFS",
+        value_parser = clap::builder::FalseyValueParser::new(),
+        help = "Keep a ref to commits rewritten on push"
+    )]
+    pub rewrite_refs: bool,
+    #[arg(
+        long,
+        env = "JOSH_KEEP_NS",
+        value_parser = clap::builder::FalseyValueParser::new(),
+        help = "Don't remove the temporary namespaces of requests"
+    )]
+    pub keep_ns: bool,
+    #[arg(
+        long,
+        env = "JOSH_NO_DISCOVER",
+        value_parser = clap::builder::FalseyValueParser::new(),
+        help = "Don't look for new filters during maintenance"
+    )]
+    pub no_discover: bool,
+    #[arg(
+        long,
+        env = "JOSH_WEBHOOK_SECRET",
+        hide_env_values = true,
+        help = "Secret used to verify webhook requests"
+    )]
+    pub webhook_secret: Option<String>,
 }
@@ -0,0 +1,314 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use josh::{JoshResult, josh_error};
+
+use crate::cli::{Args, AuthBackend, Remote};
+
+lazy_static! {
+    static ref CURRENT: std::sync::RwLock<Arc<Config>> = Default::default();
+}
+
+/// An additional upstream serving the repos below `prefix`. The prefix is removed from
+/// the repo path to get the path of the repo on the upstream.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Upstream {
+    pub prefix: String,
+    pub remote: Vec<Remote>,
+    #[serde(default)]
+    pub upstream_credentials: Option<String>,
+    #[serde(default)]
+    pub upstream_ssh_key: Option<String>,
+    /// Authentication of requests for the repos of this upstream, with its own
+    /// `htpasswd` or `auth_tokens` file
+    #[serde(default)]
+    pub auth: Option<AuthBackend>,
+    #[serde(default)]
+    pub htpasswd: Option<String>,
+    #[serde(default)]
+    pub auth_tokens: Option<String>,
+}
+
+/// Settings of josh-proxy. They are read from the file given with `--config`, every
+/// command line option (or its environment variable) takes precedence over the file.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub remote: Vec<Remote>,
+    pub upstream: Vec<Upstream>,
+    pub local: Option<String>,
+    pub port: u16,
+    pub metrics_port: Option<u16>,
+    pub poll: Option<String>,
+    pub gc: bool,
+    pub require_auth: bool,
+    pub auth: AuthBackend,
+    pub htpasswd: Option<String>,
+    pub auth_tokens: Option<String>,
+    pub upstream_credentials: Option<String>,
+    pub upstream_ssh_key: Option<String>,
+    pub no_background: bool,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
+    pub cache_duration: u64,
+    pub cache_backend: josh::cache::BackendKind,
+    pub cache_max_age: Option<String>,
+    pub cache_max_size: Option<String>,
+    pub static_resource_proxy_target: Option<String>,
+    pub filter_prefix: Option<String>,
+    pub users: Option<String>,
+    pub groups: Option<String>,
+    pub meta_repo: Option<String>,
+    pub meta_auth_token: Option<String>,
+    pub repo_block: Vec<String>,
+    pub rewrite_refs: bool,
+    pub keep_ns: bool,
+    pub no_discover: bool,
+    pub webhook_secret: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            remote: vec![],
+            upstream: vec![],
+            local: None,
+            port: 8000,
+            metrics_port: None,
+            poll: None,
+            gc: false,
+            require_auth: false,
+            auth: AuthBackend::Upstream,
+            htpasswd: None,
+            auth_tokens: None,
+            upstream_credentials: None,
+            upstream_ssh_key: None,
+            no_background: false,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            cache_duration: 0,
+            cache_backend: Default::default(),
+            cache_max_age: None,
+            cache_max_size: None,
+            static_resource_proxy_target: None,
+            filter_prefix: None,
+            users: None,
+            groups: None,
+            meta_repo: None,
+            meta_auth_token: None,
+            repo_block: vec![],
+            rewrite_refs: false,
+            keep_ns: false,
+            no_discover: false,
+            webhook_secret: None,
+        }
+    }
+}
+
+impl Config {
+    fn apply_args(&mut self, args: &Args) {
+        fn set<T: Clone>(value: &mut Option<T>, arg: &Option<T>) {
+            if arg.is_some() {
+                *value = arg.clone();
+            }
+        }
+
+        if !args.remote.is_empty() {
+            self.remote = args.remote.clone();
+        }
+        if !args.repo_block.is_empty() {
+            self.repo_block = args.repo_block.clone();
+        }
+
+        set(&mut self.local, &args.local);
+        set(&mut self.metrics_port, &args.metrics_port);
+        set(&mut self.poll, &args.poll_user);
+        set(&mut self.htpasswd, &args.htpasswd);
+        set(&mut self.auth_tokens, &args.auth_tokens);
+        set(&mut self.upstream_credentials, &args.upstream_credentials);
+        set(&mut self.upstream_ssh_key, &args.upstream_ssh_key);
+        set(&mut self.tls_cert, &args.tls_cert);
+        set(&mut self.tls_key, &args.tls_key);
+        set(&mut self.tls_client_ca, &args.tls_client_ca);
+        set(
+            &mut self.static_resource_proxy_target,
+            &args.static_resource_proxy_target,
+        );
+        set(&mut self.filter_prefix, &args.filter_prefix);
+        set(&mut self.cache_max_age, &args.cache_max_age);
+        set(&mut self.cache_max_size, &args.cache_max_size);
+        set(&mut self.users, &args.users);
+        set(&mut self.groups, &args.groups);
+        set(&mut self.meta_repo, &args.meta_repo);
+        set(&mut self.meta_auth_token, &args.meta_auth_token);
+        set(&mut self.webhook_secret, &args.webhook_secret);
+
+        self.port = args.port.unwrap_or(self.port);
+        self.auth = args.auth.unwrap_or(self.auth);
+        self.cache_duration = args.cache_duration.unwrap_or(self.cache_duration);
+        self.cache_backend = args.cache_backend.unwrap_or(self.cache_backend);
+
+        self.gc |= args.gc;
+        self.require_auth |= args.require_auth;
+        self.no_background |= args.no_background;
+        self.rewrite_refs |= args.rewrite_refs;
+        self.keep_ns |= args.keep_ns;
+        self.no_discover |= args.no_discover;
+    }
+
+    fn validate(&self) -> JoshResult<()> {
+        if self.remote.is_empty() && self.upstream.is_empty() {
+            return Err(josh_error("no remote configured"));
+        }
+        for (i, upstream) in self.upstream.iter().enumerate() {
+            let prefix = &upstream.prefix;
+            if !prefix.starts_with('/') || prefix.ends_with('/') {
+                return Err(josh_error(&format!(
+                    "upstream prefix has to start and must not end with \"/\": {}",
+                    prefix
+                )));
+            }
+            if upstream.remote.is_empty() {
+                return Err(josh_error(&format!("no remote configured for {}", prefix)));
+            }
+            if self.upstream[..i]
+                .iter()
+                .any(|other| &other.prefix == prefix)
+            {
+                return Err(josh_error(&format!(
+                    "duplicate upstream prefix: {}",
+                    prefix
+                )));
+            }
+        }
+        if self.local.is_none() {
+            return Err(josh_error("no local directory configured"));
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err(josh_error("tls_cert and tls_key have to be used together"));
+        }
+        if self.tls_client_ca.is_some() && self.tls_cert.is_none() {
+            return Err(josh_error("tls_client_ca requires tls_cert"));
+        }
+        if self.users.is_some() != self.groups.is_some() {
+            return Err(josh_error("users and groups have to be used together"));
+        }
+
+        if let Some(filter_prefix) = &self.filter_prefix {
+            josh::filter::parse(filter_prefix)
+                .map_err(|e| josh_error(&format!("invalid filter_prefix: {}", e.0)))?;
+        }
+        self.cache_gc()?;
+
+        Ok(())
+    }
+
+    /// Settings of the cache GC, if it is enabled
+    pub fn cache_gc(&self) -> JoshResult<Option<josh::cache::GcOptions>> {
+        if self.cache_max_age.is_none() && self.cache_max_size.is_none() {
+            return Ok(None);
+        }
+
+        let max_age = match &self.cache_max_age {
+            Some(age) => Some(
+                josh::cache::parse_age(age)
+                    .map_err(|e| josh_error(&format!("cache_max_age: {}", e.0)))?,
+            ),
+            None => None,
+        };
+        let max_size = match &self.cache_max_size {
+            Some(size) => Some(
+                josh::cache::parse_size(size)
+                    .map_err(|e| josh_error(&format!("cache_max_size: {}", e.0)))?,
+            ),
+            None => None,
+        };
+
+        Ok(Some(josh::cache::GcOptions {
+            max_age,
+            max_size,
+            dry_run: false,
+        }))
+    }
+
+    /// Whether `other` differs in a setting that is only used on startup
+    fn needs_restart(&self, other: &Config) -> bool {
+        self.local != other.local
+            || self.port != other.port
+            || self.metrics_port != other.metrics_port
+            || self.no_background != other.no_background
+            || self.cache_backend != other.cache_backend
+            || self.tls_cert != other.tls_cert
+            || self.tls_key != other.tls_key
+            || self.tls_client_ca != other.tls_client_ca
+    }
+}
+
+fn read(path: &Path) -> JoshResult<Config> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| josh_error(&format!("can't read {}: {}", path.display(), e)))?;
+
+    let config = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+        _ => toml::from_str(&content).map_err(|e| match e.span() {
+            Some(span) => format!(
+                "{} at line {}",
+                e.message(),
+                content[..span.start].lines().count().max(1)
+            ),
+            None => e.message().to_string(),
+        }),
+    };
+
+    config.map_err(|e| josh_error(&format!("{}: {}", path.display(), e)))
+}
+
+/// Read the configuration file given in `args`, apply the overrides from `args` and
+/// check the result
+pub fn load(args: &Args) -> JoshResult<Config> {
+    let mut config = match &args.config {
+        Some(path) => read(Path::new(path))?,
+        None => Config::default(),
+    };
+
+    config.apply_args(args);
+    config.validate()?;
+
+    Ok(config)
+}
+
+/// Load the configuration again. Settings that are only used on startup keep
+/// their current values.
+pub fn reload(args: &Args) -> JoshResult<Config> {
+    let current = current();
+    let mut config = load(args)?;
+
+    if current.needs_restart(&config) {
+        tracing::warn!(
+            "reload: changes to local, port, metrics_port, no_background, cache_backend and tls_* need a restart"
+        );
+    }
+
+    config.local = current.local.clone();
+    config.port = current.port;
+    config.metrics_port = current.metrics_port;
+    config.no_background = current.no_background;
+    config.cache_backend = current.cache_backend;
+    config.tls_cert = current.tls_cert.clone();
+    config.tls_key = current.tls_key.clone();
+    config.tls_client_ca = current.tls_client_ca.clone();
+
+    Ok(config)
+}
+
+/// The configuration currently in effect
+pub fn current() -> Arc<Config> {
+    CURRENT.read().unwrap().clone()
+}
+
+pub fn set(config: Config) {
+    *CURRENT.write().unwrap() = Arc::new(config);
+}
@@ -1,7 +1,12 @@
 pub mod auth;
 pub mod cli;
+pub mod config;
 pub mod juniper_hyper;
+pub mod metrics;
+pub mod notify;
+pub mod tls;
 pub mod trace;
+pub mod webhook;
 
 #[macro_use]
 extern crate lazy_static;
@@ -19,6 +24,17 @@ enum PushMode {
     Split,
 }
 
+impl PushMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PushMode::Normal => "normal",
+            PushMode::Review => "review",
+            PushMode::Stack => "stack",
+            PushMode::Split => "split",
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
 pub struct Ref {
     pub target: josh::Oid,
@@ -35,12 +51,23 @@ pub struct RepoConfig {
 
     #[serde(default)]
     pub lock_refs: bool,
+
+    #[serde(default)]
+    pub notify: Vec<notify::Subscription>,
+}
+
+/// Contents of the users and groups files used for access control
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    pub users: String,
+    pub groups: String,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct MetaConfig {
     pub config: RepoConfig,
     pub refs_lock: RefsLock,
+    pub acl: Option<Acl>,
 }
 
 pub fn refs_locking(refs: Vec<(String, git2::Oid)>, meta: &MetaConfig) -> Vec<(String, git2::Oid)> {
@@ -94,6 +121,7 @@ fn baseref_and_options(refname: &str) -> josh::JoshResult<(String, String, Vec<S
 pub enum RemoteAuth {
     Http { auth: auth::Handle },
     Ssh { auth_socket: PathBuf },
+    SshKey { key_path: PathBuf },
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -101,6 +129,7 @@ pub struct RepoUpdate {
     pub refs: std::collections::HashMap<String, (String, String)>,
     pub remote_url: String,
     pub remote_auth: RemoteAuth,
+    pub pusher: Option<String>,
     pub port: String,
     pub filter_spec: String,
     pub base_ns: String,
@@ -133,6 +162,24 @@ impl Default for PushOptions {
 }
 
 pub fn process_repo_update(repo_update: RepoUpdate) -> josh::JoshResult<String> {
+    let push_mode = match repo_update.refs.keys().next() {
+        Some(refname) => baseref_and_options(refname)?.3,
+        None => PushMode::Normal,
+    };
+
+    let result = push_repo_update(repo_update);
+
+    metrics::PUSHES
+        .with_label_values(&[
+            push_mode.as_str(),
+            if result.is_ok() { "success" } else { "failure" },
+        ])
+        .inc();
+
+    result
+}
+
+fn push_repo_update(repo_update: RepoUpdate) -> josh::JoshResult<String> {
     let push_options_path = std::path::PathBuf::from(&repo_update.git_dir)
         .join("refs/namespaces")
         .join(&repo_update.git_ns)
@@ -364,6 +411,13 @@ pub fn process_repo_update(repo_update: RepoUpdate) -> josh::JoshResult<String>
                 return Err(josh::josh_error(&text));
             }
 
+            // The push already happened, so failing to log it must not fail the request
+            if let Some(pusher) = &repo_update.pusher {
+                if let Err(e) = log_push(&repo_update, pusher, &reference, oid) {
+                    tracing::error!(pusher = %pusher, "failed to log push: {}", e.0);
+                }
+            }
+
             resp.push(text.to_string());
             let mut warnings = josh::filter::compute_warnings(
                 &transaction,
@@ -384,7 +438,7 @@ pub fn process_repo_update(repo_update: RepoUpdate) -> josh::JoshResult<String>
         )?;
 
         if new_oid != reapply {
-            if std::env::var("JOSH_REWRITE_REFS").is_ok() {
+            if config::current().rewrite_refs {
                 transaction.repo().reference(
                     &format!(
                         "refs/josh/rewrites/{}/{:?}/r_{}",
@@ -482,6 +536,45 @@ fn split_changes(
     Ok(())
 }
 
+// Pushes done with the service credentials don't show who made them on the
+// upstream, so keep track of them next to the mirror repo
+fn log_push(
+    repo_update: &RepoUpdate,
+    pusher: &str,
+    reference: &str,
+    oid: git2::Oid,
+) -> josh::JoshResult<()> {
+    use std::io::Write;
+
+    let path = std::path::Path::new(&repo_update.mirror_git_dir)
+        .parent()
+        .ok_or(josh_error("invalid mirror path"))?
+        .join("push.log");
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    tracing::info!(
+        pusher = %pusher,
+        remote_url = %repo_update.remote_url,
+        reference = %reference,
+        oid = %oid,
+        "log_push"
+    );
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(
+        file,
+        "{} {} {} {} {}",
+        time, pusher, repo_update.remote_url, reference, oid
+    )?;
+
+    Ok(())
+}
+
 pub fn push_head_url(
     repo: &git2::Repository,
     alternate: &str,
@@ -633,7 +726,7 @@ pub fn create_repo(path: &std::path::Path) -> josh::JoshResult<()> {
     )
     .expect("can't symlink pre-receive hook");
 
-    if std::env::var_os("JOSH_KEEP_NS").is_none() {
+    if !config::current().keep_ns {
         std::fs::remove_dir_all(overlay_path.join("refs/namespaces")).ok();
     }
 
@@ -689,6 +782,20 @@ pub fn run_git_with_auth(
 
             Ok(shell.command_env(cmd, &env, &env_notrace))
         }
+        RemoteAuth::SshKey { key_path } => {
+            let key_path = key_path
+                .to_str()
+                .ok_or(josh_error("failed to convert path"))?;
+            let ssh_command = format!(
+                "{} -o IdentitiesOnly=yes -i {}",
+                make_ssh_command(),
+                shell_words::quote(key_path)
+            );
+
+            let env = [("GIT_SSH_COMMAND", ssh_command.as_str())];
+
+            Ok(shell.command_env(cmd, &env, &maybe_object_dir))
+        }
         RemoteAuth::Http { auth } => {
             let (username, password) = auth.parse().unwrap_or_default();
             let env_notrace = [
@@ -854,7 +961,7 @@ impl std::fmt::Debug for TmpGitNamespace {
 
 impl Drop for TmpGitNamespace {
     fn drop(&mut self) {
-        if std::env::var_os("JOSH_KEEP_NS").is_some() {
+        if config::current().keep_ns {
             return;
         }
 
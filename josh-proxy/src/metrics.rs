@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use prometheus::{
+    Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, exponential_buckets,
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge,
+};
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "josh_proxy_http_requests_total",
+        "HTTP requests by endpoint and response status",
+        &["endpoint", "status"]
+    )
+    .unwrap();
+    pub static ref FETCH_DURATION: Histogram = register_histogram!(
+        "josh_proxy_upstream_fetch_duration_seconds",
+        "Duration of fetches from the upstream",
+        exponential_buckets(0.05, 2.0, 12).unwrap()
+    )
+    .unwrap();
+    pub static ref FETCH_FAILURES: IntCounter = register_int_counter!(
+        "josh_proxy_upstream_fetch_failures_total",
+        "Failed fetches from the upstream"
+    )
+    .unwrap();
+    pub static ref FILTER_COMPUTATIONS: IntCounterVec = register_int_counter_vec!(
+        "josh_filter_computations_total",
+        "Filter applications to the refs of a repo",
+        &["filter"]
+    )
+    .unwrap();
+    pub static ref FILTER_CACHE_MISSES: IntCounterVec = register_int_counter_vec!(
+        "josh_filter_cache_misses_total",
+        "Commits that had to be filtered because they were not found in the cache",
+        &["filter"]
+    )
+    .unwrap();
+    pub static ref PUSHES: IntCounterVec = register_int_counter_vec!(
+        "josh_proxy_pushes_total",
+        "Pushes to the upstream by push mode and result",
+        &["mode", "result"]
+    )
+    .unwrap();
+    pub static ref SEMAPHORE_WAIT: HistogramVec = register_histogram_vec!(
+        "josh_proxy_semaphore_wait_seconds",
+        "Time spent waiting for a filter or fetch permit",
+        &["semaphore"],
+        exponential_buckets(0.001, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    static ref CACHE_SIZE: IntGauge =
+        register_int_gauge!("josh_cache_size_bytes", "Size of the filter cache on disk").unwrap();
+    static ref FILTER_LABELS: Mutex<HashSet<String>> = Default::default();
+}
+
+/// Number of distinct filters that get their own label value
+const MAX_FILTER_LABELS: usize = 100;
+
+/// Register all metrics, so they are exported before their first use
+pub fn init() {
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&FETCH_DURATION);
+    lazy_static::initialize(&FETCH_FAILURES);
+    lazy_static::initialize(&FILTER_COMPUTATIONS);
+    lazy_static::initialize(&FILTER_CACHE_MISSES);
+    lazy_static::initialize(&PUSHES);
+    lazy_static::initialize(&SEMAPHORE_WAIT);
+    lazy_static::initialize(&CACHE_SIZE);
+}
+
+/// Label for the kind of request, the path itself would make the number of
+/// time series grow without bounds
+pub fn endpoint(path: &str, has_query: bool) -> &'static str {
+    match path {
+        "/version" => "version",
+        "/remote" => "remote",
+        "/flush" => "flush",
+        "/filters" | "/filters/refresh" => "filters",
+        "/repo_update" => "repo_update",
+        "/serve_namespace" => "serve_namespace",
+        "/~/webhook" => "webhook",
+        _ if path.starts_with("/~/webhook/") => "webhook",
+        _ if path.starts_with("/~/ui") => "ui",
+        _ if path.starts_with("/~/graphql") => "graphql",
+        _ if path.starts_with("/~/graphiql") => "graphiql",
+        _ if path.contains("/info/lfs") => "lfs",
+        _ if path.ends_with("/info/refs") => "info_refs",
+        _ if path.ends_with("/git-upload-pack") => "upload_pack",
+        _ if path.ends_with("/git-receive-pack") => "receive_pack",
+        _ if path.ends_with(".git") && has_query => "query",
+        _ => "other",
+    }
+}
+
+/// Label for a filter. Every URL can contain another filter, so only the first
+/// `MAX_FILTER_LABELS` filters get their own time series and all others are "other".
+pub fn filter_label(spec: &str) -> String {
+    let mut labels = match FILTER_LABELS.lock() {
+        Ok(labels) => labels,
+        Err(_) => return "other".to_string(),
+    };
+    if labels.contains(spec) {
+        return spec.to_string();
+    }
+    if labels.len() < MAX_FILTER_LABELS {
+        labels.insert(spec.to_string());
+        return spec.to_string();
+    }
+    "other".to_string()
+}
+
+/// Render all metrics in the Prometheus text format
+pub fn render() -> josh::JoshResult<String> {
+    match josh::cache::size_on_disk() {
+        Ok(size) => CACHE_SIZE.set(size as i64),
+        Err(e) => tracing::warn!("metrics: can't get cache size: {}", e.0),
+    }
+
+    let mut buffer = vec![];
+    prometheus::TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_label_is_capped() {
+        for i in 0..MAX_FILTER_LABELS {
+            filter_label(&format!(":/dir{}", i));
+        }
+        assert_eq!(filter_label(":/dir0"), ":/dir0");
+        assert_eq!(filter_label(":/new"), "other");
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    // Serializes updates of the filtered refs, so concurrent fetches of the same
+    // repo don't send the same event twice
+    static ref UPDATE_LOCK: std::sync::Mutex<()> = Default::default();
+}
+
+/// Entry of the `notify` list in the meta repo config of a repo: `url` gets an
+/// `Event` posted whenever the result of `filter` changes
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub url: String,
+    pub filter: josh::filter::Filter,
+}
+
+/// Subscriptions of all repos, by upstream repo
+pub type Subscriptions = std::collections::HashMap<String, Vec<Subscription>>;
+
+// The subscriptions are kept next to the mirror, so they still get notified after a
+// restart before their repos are accessed again
+fn subscriptions_path(repo_path: &Path) -> PathBuf {
+    repo_path.join("subscriptions.json")
+}
+
+pub fn load_subscriptions(repo_path: &Path) -> josh::JoshResult<Subscriptions> {
+    match std::fs::read_to_string(subscriptions_path(repo_path)) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_subscriptions(repo_path: &Path, subscriptions: &Subscriptions) -> josh::JoshResult<()> {
+    // Replace the file in one step, so it can't be left half written
+    let path = subscriptions_path(repo_path);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(subscriptions)?)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Event {
+    pub upstream_repo: String,
+    pub filter: String,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub old: String,
+    pub new: String,
+    pub original: String,
+}
+
+/// Apply the filters of all `subscriptions` to the current HEAD of `upstream_repo` and
+/// update their refs in the overlay repo. Returns the events for all refs that moved,
+/// refs that didn't exist before are created without an event.
+pub fn update_filtered_refs(
+    repo_path: &Path,
+    upstream_repo: &str,
+    subscriptions: &[Subscription],
+) -> josh::JoshResult<Vec<(String, Event)>> {
+    let _lock = UPDATE_LOCK.lock()?;
+
+    let transaction_mirror = josh::cache::Transaction::open(&repo_path.join("mirror"), None)?;
+    let transaction_overlay = josh::cache::Transaction::open(&repo_path.join("overlay"), None)?;
+    transaction_overlay
+        .repo()
+        .odb()?
+        .add_disk_alternate(repo_path.join("mirror").join("objects").to_str().unwrap())?;
+
+    let mut events = vec![];
+
+    for subscription in subscriptions {
+        let filter_spec = josh::filter::spec(subscription.filter);
+        let ((from, original), to_ref) = match josh::housekeeping::memorize_from_to(
+            transaction_mirror.repo(),
+            &josh::to_filtered_ref(upstream_repo, &filter_spec),
+            upstream_repo,
+        ) {
+            Ok(from_to) => from_to,
+            Err(_) => continue,
+        };
+
+        let (updated, errors) = josh::filter_refs(
+            &transaction_overlay,
+            subscription.filter,
+            &[(from, original)],
+            josh::filter::empty(),
+        );
+        if let Some((_, e)) = errors.first() {
+            tracing::warn!(filter = %filter_spec, "update_filtered_refs: {}", e.0);
+            continue;
+        }
+
+        let new = updated[0].1;
+        let old = transaction_overlay.repo().refname_to_id(&to_ref).ok();
+        if new == git2::Oid::zero() || old == Some(new) {
+            continue;
+        }
+
+        transaction_overlay
+            .repo()
+            .reference(&to_ref, new, true, "update_filtered_refs")?;
+
+        if let Some(old) = old {
+            events.push((
+                subscription.url.clone(),
+                Event {
+                    upstream_repo: upstream_repo.to_string(),
+                    filter: filter_spec,
+                    reference: to_ref,
+                    old: old.to_string(),
+                    new: new.to_string(),
+                    original: original.to_string(),
+                },
+            ));
+        }
+    }
+
+    Ok(events)
+}
+
+/// Post the events as json to their subscribers. Failures are only logged,
+/// notifications are not retried.
+pub async fn send(events: Vec<(String, Event)>) {
+    let client = reqwest::Client::new();
+
+    for (url, event) in events {
+        tracing::info!(url = %url, event = ?event, "notify");
+
+        match client.post(&url).json(&event).send().await {
+            Ok(resp) if resp.status().is_success() => {}
+            Ok(resp) => {
+                tracing::warn!(url = %url, status = %resp.status(), "notify: failed")
+            }
+            Err(e) => tracing::warn!(url = %url, "notify: failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use tokio_rustls::rustls::sign::CertifiedKey;
+
+/// Identity of a client that authenticated with a certificate, taken from the
+/// common name of the certificate's subject. Attached to the extensions of requests.
+#[derive(Clone, Debug)]
+pub struct ClientIdentity(pub String);
+
+fn read_certs(path: &Path) -> josh::JoshResult<Vec<rustls::pki_types::CertificateDer<'static>>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(josh::josh_error(&format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> josh::JoshResult<CertifiedKey> {
+    let certs = read_certs(cert_path)?;
+    let mut reader = std::io::BufReader::new(std::fs::File::open(key_path)?);
+    let key = rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+        josh::josh_error(&format!("no private key found in {}", key_path.display()))
+    })?;
+    let key = rustls::crypto::ring::sign::any_supported_type(&key)?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+fn modified(paths: &[&Path]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+// Files are checked at most this often, not on every handshake
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct LoadedKey {
+    modified: Option<SystemTime>,
+    checked: Instant,
+    key: Arc<CertifiedKey>,
+}
+
+// Checks the modification time of the files during handshakes, so renewed
+// certificates are used without restarting the proxy
+#[derive(Debug)]
+struct ReloadingResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: std::sync::Mutex<LoadedKey>,
+}
+
+impl ResolvesServerCert for ReloadingResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let mut current = self.current.lock().ok()?;
+        if current.checked.elapsed() < CHECK_INTERVAL {
+            return Some(current.key.clone());
+        }
+        current.checked = Instant::now();
+
+        let modified = modified(&[&self.cert_path, &self.key_path]);
+        if modified != current.modified {
+            match load_certified_key(&self.cert_path, &self.key_path) {
+                Ok(key) => {
+                    tracing::info!("tls: reloaded certificate");
+                    current.modified = modified;
+                    current.key = Arc::new(key);
+                }
+                Err(e) => tracing::warn!("tls: can't reload certificate: {}", e.0),
+            }
+        }
+
+        Some(current.key.clone())
+    }
+}
+
+/// Create an acceptor serving the certificate in `cert_path`. With `client_ca`, clients can
+/// authenticate with a certificate issued by that CA, clients without a certificate are
+/// still accepted.
+pub fn make_acceptor(
+    cert_path: &Path,
+    key_path: &Path,
+    client_ca: Option<&Path>,
+) -> josh::JoshResult<tokio_rustls::TlsAcceptor> {
+    let resolver = ReloadingResolver {
+        cert_path: cert_path.to_owned(),
+        key_path: key_path.to_owned(),
+        current: std::sync::Mutex::new(LoadedKey {
+            modified: modified(&[cert_path, key_path]),
+            checked: Instant::now(),
+            key: Arc::new(load_certified_key(cert_path, key_path)?),
+        }),
+    };
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in read_certs(client_ca)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder.with_cert_resolver(Arc::new(resolver));
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(tokio_rustls::TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Identity of the verified client certificate of a connection, if any
+pub fn client_identity(connection: &rustls::ServerConnection) -> Option<ClientIdentity> {
+    let cert = connection.peer_certificates()?.first()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    let name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+
+    Some(ClientIdentity(name.to_string()))
+}
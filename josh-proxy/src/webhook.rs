@@ -0,0 +1,73 @@
+use hmac::{Hmac, Mac};
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    // A push to the given upstream repo, in the form "/path/to/repo.git"
+    Push(String),
+    Ignored,
+}
+
+fn verify_hmac(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature.trim_start_matches("sha256=")) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    let mut mac = match Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Check that a webhook request was sent by someone knowing `secret`.
+/// GitHub, Gitea and generic senders sign the body with HMAC-SHA256, GitLab sends
+/// the secret itself.
+pub fn verify(headers: &hyper::HeaderMap, body: &[u8], secret: &str) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(signature) = header("x-hub-signature-256")
+        .or_else(|| header("x-gitea-signature"))
+        .or_else(|| header("x-josh-signature-256"))
+    {
+        verify_hmac(secret, body, signature)
+    } else if let Some(token) = header("x-gitlab-token") {
+        crate::auth::same_secret(token, secret)
+    } else {
+        false
+    }
+}
+
+/// Find out which upstream repo a webhook request refers to. Requests without an event
+/// header are treated as generic push events with a `{"repository": "path/to/repo.git"}` body.
+pub fn parse(headers: &hyper::HeaderMap, body: &[u8]) -> josh::JoshResult<Event> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let is_push = match header("x-github-event")
+        .or_else(|| header("x-gitea-event"))
+        .or_else(|| header("x-gitlab-event"))
+    {
+        Some(event) => ["push", "Push Hook", "Tag Push Hook"].contains(&event),
+        None => true,
+    };
+
+    if !is_push {
+        return Ok(Event::Ignored);
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(body)?;
+    let repo = [
+        "/repository/full_name",
+        "/project/path_with_namespace",
+        "/repository",
+    ]
+    .iter()
+    .find_map(|pointer| payload.pointer(pointer).and_then(|v| v.as_str()))
+    .ok_or_else(|| josh::josh_error("no repository found in webhook payload"))?;
+
+    let repo = repo.trim_start_matches('/');
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+
+    Ok(Event::Push(format!("/{}.git", repo)))
+}
@@ -0,0 +1,45 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+
+The cache can be stored in SQLite instead of sled
+
+  $ josh-filter -s :/sub1 --cache-backend sqlite
+  [2] :/sub1
+  $ ls .git/josh/23
+  sqlite
+  $ ls .git/josh/23/sqlite
+  cache.db
+  cache.db-shm
+  cache.db-wal
+  $ git log --graph --pretty=%s FILTERED_HEAD
+  * add file2
+  * add file1
+
+The cache is kept for the next run
+
+  $ josh-filter -s :prefix=x --cache-backend sqlite
+  [2] :/sub1
+  [2] :prefix=x
+
+With the memory backend nothing is written to disk
+
+  $ josh-filter -s :prefix=x --cache-backend memory
+  [2] :prefix=x
+  $ ls .git/josh/23
+  sqlite
+
+  $ josh-filter -s :/sub1 --cache-backend lmdb
+  error: invalid value for one of the arguments
+  [2]
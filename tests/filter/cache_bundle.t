@@ -0,0 +1,104 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ mkdir sub2
+  $ echo contents2 > sub2/file2
+  $ git add sub2
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ echo contents3 > sub1/file3
+  $ git add sub1
+  $ git commit -m "add file3" 1> /dev/null
+
+  $ josh-filter -s :/sub1
+  [2] :/sub1
+  $ git update-ref refs/josh/filtered/libs.git/%3A%2Fsub1/HEAD FILTERED_HEAD
+  $ josh-filter -s :/sub2
+  [2] :/sub1
+  [2] :/sub2
+
+Only the filters of the selected upstream repos are exported
+
+  $ josh-filter --cache-export ${TESTTMP}/sub1.bundle --export-repo libs.git
+  exported :/sub1 (2 entries)
+  exported 1 filters, 1 refs, 6 objects
+  $ josh-filter --cache-export ${TESTTMP}/sub2.bundle --export-filter :/sub2
+  exported :/sub2 (2 entries)
+  exported 1 filters, 0 refs, 3 objects
+  $ josh-filter --cache-export ${TESTTMP}/all.bundle
+  exported :/sub1 (2 entries)
+  exported :/sub2 (2 entries)
+  exported 2 filters, 1 refs, 9 objects
+  $ head -n 2 ${TESTTMP}/all.bundle
+  josh-cache-bundle 1
+  pack
+  $ grep -a "^ref " ${TESTTMP}/all.bundle
+  ref * refs/josh/filtered/libs.git/%3A%2Fsub1/HEAD (glob)
+  $ tail -n 1 ${TESTTMP}/all.bundle
+  end
+
+A replica that has the upstream history gets the cache and refs
+
+  $ cd ${TESTTMP}
+  $ git clone -q libs replica
+  $ cd replica
+  $ josh-filter --cache-import ${TESTTMP}/all.bundle
+  imported :/sub1 (2 entries, 0 skipped)
+  imported :/sub2 (2 entries, 0 skipped)
+  imported 2 filters, 1 refs, 0 refs skipped
+  $ git log --graph --pretty=%s refs/josh/filtered/libs.git/%3A%2Fsub1/HEAD
+  * add file3
+  * add file1
+  $ josh-filter -s :/sub1 origin/master
+  [2] :/sub1
+  [2] :/sub2
+  $ git rev-parse FILTERED_HEAD
+  * (glob)
+  $ test $(git rev-parse FILTERED_HEAD) = $(git -C ${TESTTMP}/libs rev-parse refs/josh/filtered/libs.git/%3A%2Fsub1/HEAD)
+
+Entries of commits missing in the target repo are skipped
+
+  $ cd ${TESTTMP}
+  $ git init -q other 1> /dev/null
+  $ cd other
+  $ josh-filter --cache-import ${TESTTMP}/all.bundle
+  imported :/sub1 (0 entries, 2 skipped)
+  imported :/sub2 (0 entries, 2 skipped)
+  imported 2 filters, 1 refs, 0 refs skipped
+  $ josh-filter --cache-import ${TESTTMP}/libs/sub1/file1
+  ERROR: not a josh cache bundle
+  [1]
+
+Bundles can only create refs of filtered repos
+
+  $ cd ${TESTTMP}/libs
+  $ josh-filter --cache-export ${TESTTMP}/empty.bundle --export-filter :/sub3
+  exported 0 filters, 0 refs, 0 objects
+  $ cat ${TESTTMP}/empty.bundle
+  josh-cache-bundle 1
+  pack
+  0
+  end
+  $ sed -i "s#^end#ref $(git rev-parse HEAD) refs/heads/master#" ${TESTTMP}/empty.bundle
+  $ echo end >> ${TESTTMP}/empty.bundle
+  $ cd ${TESTTMP}/other
+  $ josh-filter --cache-import ${TESTTMP}/empty.bundle
+  ERROR: invalid ref in bundle: "refs/heads/master"
+  [1]
+  $ git show-ref
+  * refs/josh/filtered/libs.git/%3A%2Fsub1/HEAD (glob)
+
+A truncated bundle is rejected
+
+  $ head -n 3 ${TESTTMP}/empty.bundle > ${TESTTMP}/truncated.bundle
+  $ josh-filter --cache-import ${TESTTMP}/truncated.bundle
+  ERROR: truncated bundle
+  [1]
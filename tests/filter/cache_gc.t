@@ -0,0 +1,59 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ mkdir sub2
+  $ echo contents2 > sub2/file2
+  $ git add sub2
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ josh-filter -s :/sub1 --cache-backend sqlite
+  [1] :/sub1
+  $ git update-ref refs/josh/filtered/repo.git/%3A%2Fsub1/HEAD FILTERED_HEAD
+  $ josh-filter -s :/sub2 --cache-backend sqlite
+  [1] :/sub1
+  [2] :/sub2
+
+Nothing is dropped while the cache is within its limits
+
+  $ josh-filter --cache-gc --max-size 1M --max-age 1d --cache-backend sqlite
+  dropped 0 filters, kept 2, reclaimed ~0 bytes
+
+A dry run only shows what would be dropped
+
+  $ josh-filter --cache-gc --max-age 0s --dry-run --cache-backend sqlite
+  dropped :/sub1 (1 entries)
+  dropped :/sub2 (2 entries)
+  removed refs/josh/filtered/repo.git/%3A%2Fsub1/HEAD
+  dropped 2 filters, kept 0, reclaimed ~120 bytes
+  $ git show-ref | grep josh/filtered
+  * refs/josh/filtered/repo.git/%3A%2Fsub1/HEAD (glob)
+
+The least recently used filters are dropped together with their refs to stay below
+the size limit
+
+  $ josh-filter --cache-gc --max-size 80 --cache-backend sqlite
+  dropped :/sub1 (1 entries)
+  removed refs/josh/filtered/repo.git/%3A%2Fsub1/HEAD
+  dropped 1 filters, kept 1, reclaimed ~40 bytes
+  $ git show-ref | grep josh/filtered
+  [1]
+  $ josh-filter -s :/sub2 --cache-backend sqlite
+  [2] :/sub2
+
+Filters not used for the given time are dropped
+
+  $ josh-filter --cache-gc --max-age 0s --cache-backend sqlite
+  dropped :/sub2 (2 entries)
+  dropped 1 filters, kept 0, reclaimed ~80 bytes
+
+  $ josh-filter --cache-gc --max-age 1x
+  ERROR: invalid value: "1x"
+  [1]
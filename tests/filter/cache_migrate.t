@@ -0,0 +1,53 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ josh-filter -s :/sub1 --cache-backend sqlite
+  [1] :/sub1
+
+Caches of older versions without a migration are not used and removed by the GC
+
+  $ mkdir -p .git/josh/22/sqlite
+  $ cp .git/josh/23/sqlite/cache.db .git/josh/22/sqlite/
+  $ rm -r .git/josh/23
+  $ josh-filter -s :/sub1 --cache-backend sqlite
+  [1] :/sub1
+  $ ls .git/josh
+  22
+  23
+  $ josh-filter --cache-gc --dry-run --cache-backend sqlite
+  dropped 0 filters, kept 1, reclaimed ~0 bytes
+  $ josh-filter --cache-gc --cache-backend sqlite
+  removed *.git/josh/22 (glob)
+  dropped 0 filters, kept 1, reclaimed ~0 bytes
+  $ ls .git/josh
+  23
+
+A cache whose migration was interrupted is not used
+
+  $ touch .git/josh/23/sqlite/partial .git/josh/23/sqlite.migrating
+  $ josh-filter -s :/sub1 --cache-backend sqlite
+  [1] :/sub1
+  $ ls .git/josh/23
+  sqlite
+  $ ls .git/josh/23/sqlite
+  cache.db
+  cache.db-shm
+  cache.db-wal
+
+Older versions are kept while a migration is running
+
+  $ mkdir -p .git/josh/22/sled
+  $ touch .git/josh/23/sled.migrating
+  $ josh-filter --cache-gc --cache-backend sqlite
+  dropped 0 filters, kept 1, reclaimed ~0 bytes
+  $ ls .git/josh
+  22
+  23
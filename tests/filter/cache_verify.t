@@ -0,0 +1,72 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ mkdir sub2
+  $ echo contents2 > sub2/file2
+  $ git add sub2
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ echo contents3 > sub1/file3
+  $ git add sub1
+  $ git commit -m "add file3" 1> /dev/null
+
+  $ josh-filter -s :/sub1
+  [2] :/sub1
+  $ josh-filter --verify-cache
+  checked 2 entries of 1 filters, 0 problems
+
+Put a wrong entry into the cache
+
+  $ cat > ${TESTTMP}/wrong.bundle <<EOF2
+  > josh-cache-bundle 1
+  > pack
+  > 0
+  > filter :/sub1
+  > $(git rev-parse HEAD) $(git rev-parse HEAD~1)
+  > end
+  > EOF2
+  $ josh-filter --cache-import ${TESTTMP}/wrong.bundle
+  imported :/sub1 (1 entries, 0 skipped)
+  imported 1 filters, 0 refs, 0 refs skipped
+
+  $ josh-filter --verify-cache
+  mismatch :/sub1 * (glob)
+  checked 2 entries of 1 filters, 1 problems
+  [1]
+  $ josh-filter --verify-cache --sample 1
+  mismatch :/sub1 * (glob)
+  checked 1 entries of 1 filters, 1 problems
+  [1]
+  $ josh-filter --verify-cache --repair
+  mismatch :/sub1 * (glob)
+  checked 2 entries of 1 filters, 1 problems, repaired
+  [1]
+  $ josh-filter --verify-cache
+  checked 2 entries of 1 filters, 0 problems
+  $ josh-filter :/sub1
+  $ git log --graph --pretty=%s FILTERED_HEAD
+  * add file3
+  * add file1
+
+Cached commits that were removed from the object database are written again
+
+  $ FILTERED=$(git rev-parse FILTERED_HEAD)
+  $ git update-ref -d FILTERED_HEAD
+  $ rm .git/objects/$(echo ${FILTERED} | cut -c1-2)/$(echo ${FILTERED} | cut -c3-)
+  $ git cat-file -t ${FILTERED}
+  fatal: git cat-file: could not get object info
+  [128]
+  $ josh-filter --verify-cache
+  missing result :/sub1 * (glob)
+  checked 2 entries of 1 filters, 1 problems
+  [1]
+  $ git cat-file -t ${FILTERED}
+  commit
@@ -0,0 +1,82 @@
+The :follow filter keeps the history of a directory across renames
+
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q real_repo 1> /dev/null
+  $ cd real_repo
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ echo contents3 > unrelated_file
+  $ git add unrelated_file
+  $ git commit -m "add unrelated_file" 1> /dev/null
+
+  $ mkdir libs
+  $ git mv sub1 libs/sub1_new
+  $ echo contents4 >> libs/sub1_new/file1
+  $ git add libs
+  $ git commit -m "mv sub1" 1> /dev/null
+
+  $ echo contents5 > libs/sub1_new/file3
+  $ git add libs
+  $ git commit -m "add file3" 1> /dev/null
+
+  $ josh-filter -s :/libs/sub1_new master --update refs/josh/filter/subdir
+  [2] :/sub1_new
+  [3] :/libs
+  $ git log refs/josh/filter/subdir --graph --pretty=%s
+  * add file3
+  * mv sub1
+
+  $ josh-filter -s :follow=/libs/sub1_new master --update refs/heads/filtered
+  [2] :/sub1_new
+  [2] :follow=sub1
+  [3] :/libs
+  [3] :follow=libs/sub1_new
+  $ git log refs/heads/filtered --graph --pretty=%s
+  * add file3
+  * mv sub1
+  * add file2
+  * add file1
+  $ git ls-tree --name-only -r refs/heads/filtered~2
+  file1
+  file2
+
+  $ git checkout -q filtered 1> /dev/null
+  $ echo contents6 > file4
+  $ git add file4
+  $ git commit -m "add file4" 1> /dev/null
+
+  $ josh-filter -s :follow=libs/sub1_new --reverse master --update refs/heads/filtered
+  [2] :/sub1_new
+  [2] :follow=sub1
+  [3] :/libs
+  [3] :follow=libs/sub1_new
+
+  $ git checkout -q master 1> /dev/null
+  $ git log --graph --pretty=%s
+  * add file4
+  * add file3
+  * mv sub1
+  * add unrelated_file
+  * add file2
+  * add file1
+  $ tree
+  .
+  |-- libs
+  |   `-- sub1_new
+  |       |-- file1
+  |       |-- file2
+  |       |-- file3
+  |       `-- file4
+  `-- unrelated_file
+  
+  3 directories, 5 files
@@ -0,0 +1,168 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ mkdir -p src/a/b src/test/y docs/test
+  $ echo contents > root.proto
+  $ echo contents > src/x.proto
+  $ echo contents > src/a/y.proto
+  $ echo contents > src/a/b/z.proto
+  $ echo contents > src/a/b/z.txt
+  $ echo contents > src/a/b1.c
+  $ echo contents > src/a/c2.c
+  $ echo contents > src/a/d.c
+  $ echo contents > src/test/y/u.proto
+  $ echo contents > docs/test/t.txt
+  $ git add .
+  $ git commit -q -m "initial"
+
+  $ josh-filter -p "::src/a/b**"
+  ::src/a/b*
+  $ josh-filter -p ::src/***/*.proto
+  ::src/**/*.proto
+  $ josh-filter -p ":[::src/**/*.proto,::src/**/*.c]"
+  :[
+      ::src/**/*.proto
+      ::src/**/*.c
+  ]
+  $ josh-filter -p ":[::src/**/*.proto,::!src/test/**,::**/*.c]"
+  :[
+      ::src/**/*.proto
+      ::!src/test/**
+      ::**/*.c
+  ]
+  $ josh-filter -p ::!src/test/**
+  ERROR: Negated patterns can only be used inside of a composition:
+  
+    :[::pattern,::!pattern]
+  
+  [1]
+
+  $ josh-filter -s ::src/**/*.proto master --update refs/heads/proto
+  [1] ::src/**/*.proto
+  $ git ls-tree -r --name-only refs/heads/proto
+  src/a/b/z.proto
+  src/a/y.proto
+  src/test/y/u.proto
+  src/x.proto
+
+  $ josh-filter -s ':glob="src/a/[bc][0-9].c"' master --update refs/heads/class
+  [1] ::src/**/*.proto
+  [1] :glob="src/a/[bc][0-9].c"
+  $ git ls-tree -r --name-only refs/heads/class
+  src/a/b1.c
+  src/a/c2.c
+
+  $ josh-filter -s ::**/test/ master --update refs/heads/dirs
+  [1] ::**/test/
+  [1] ::src/**/*.proto
+  [1] :glob="src/a/[bc][0-9].c"
+  $ git ls-tree -r --name-only refs/heads/dirs
+  docs/test/t.txt
+  src/test/y/u.proto
+
+  $ josh-filter -s ":[::src/**/*.proto,::!src/test/**,::**/*.c]" master --update refs/heads/negated
+  [1] ::**/test/
+  [1] ::src/**/*.proto
+  [1] :[
+      ::src/**/*.proto
+      ::!src/test/**
+      ::**/*.c
+  ]
+  [1] :glob="src/a/[bc][0-9].c"
+  $ git ls-tree -r --name-only refs/heads/negated
+  src/a/b/z.proto
+  src/a/b1.c
+  src/a/c2.c
+  src/a/d.c
+  src/a/y.proto
+  src/x.proto
+
+  $ josh-filter -s ":[::src/,::!src/a/b/]" master --update refs/heads/negated_dir
+  [1] :/src
+  [1] ::**/test/
+  [1] ::src/**/*.proto
+  [1] :[
+      ::src/**/*.proto
+      ::!src/test/**
+      ::**/*.c
+  ]
+  [1] :exclude[::src/a/b/]
+  [1] :glob="src/a/[bc][0-9].c"
+  [1] :prefix=src
+  $ git ls-tree -r --name-only refs/heads/negated_dir
+  src/a/b1.c
+  src/a/c2.c
+  src/a/d.c
+  src/a/y.proto
+  src/test/y/u.proto
+  src/x.proto
+
+  $ git checkout -q negated 1> /dev/null
+  $ echo contents2 > src/a/d.c
+  $ echo contents2 > src/e.proto
+  $ git add .
+  $ git commit -q -m "change files"
+
+  $ josh-filter -s ":[::src/**/*.proto,::!src/test/**,::**/*.c]" --reverse master --update refs/heads/negated
+  [1] :/src
+  [1] ::**/test/
+  [1] ::src/**/*.proto
+  [1] :[
+      ::src/**/*.proto
+      ::!src/test/**
+      ::**/*.c
+  ]
+  [1] :exclude[::src/a/b/]
+  [1] :glob="src/a/[bc][0-9].c"
+  [1] :prefix=src
+
+  $ git checkout -q master 1> /dev/null
+  $ git log --pretty=%s
+  change files
+  initial
+  $ git ls-tree -r --name-only HEAD
+  docs/test/t.txt
+  root.proto
+  src/a/b/z.proto
+  src/a/b/z.txt
+  src/a/b1.c
+  src/a/c2.c
+  src/a/d.c
+  src/a/y.proto
+  src/e.proto
+  src/test/y/u.proto
+  src/x.proto
+  $ cat src/a/d.c
+  contents2
+
+Negated patterns match the paths in the result of the preceding entries
+
+  $ josh-filter ":[lib=:/src/a,::!lib/b/,::!**/*.proto]" master
+  $ git ls-tree -r --name-only FILTERED_HEAD
+  lib/b1.c
+  lib/c2.c
+  lib/d.c
+  $ josh-filter -p ":[lib=:/src/a,::!lib/b/]"
+  :/src/a:prefix=lib:exclude[::lib/b/]
+
+Without a "*" paths containing "?" or "[" name a single file, ":glob=" matches them as patterns
+
+  $ echo contents > "file?.txt"
+  $ echo contents > file1.txt
+  $ git add . && git commit -q -m "add question marks"
+  $ josh-filter "::\"file?.txt\"" master
+  $ git ls-tree -r --name-only FILTERED_HEAD
+  file?.txt
+  $ josh-filter ":glob=\"file?.txt\"" master
+  $ git ls-tree -r --name-only FILTERED_HEAD
+  file1.txt
+  file?.txt
+  $ josh-filter -p "::\"file?.txt\""
+  ::"file?.txt"
+  $ josh-filter -p ":glob=\"file?.txt\""
+  :glob="file?.txt"
+  $ josh-filter -p ":[::a/,::!\"x?\"]"
+  ::a/:exclude[:glob="x?"]
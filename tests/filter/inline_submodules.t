@@ -0,0 +1,57 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ cd ${TESTTMP}
+  $ git init -q app 1> /dev/null
+  $ cd app
+  $ echo contents > app.txt
+  $ git add app.txt
+  $ git commit -m "init" 1> /dev/null
+  $ git submodule add ../libs 2> /dev/null
+  $ git commit -m "add libs" 1> /dev/null
+
+  $ josh-filter -p :inline_submodules
+  :inline_submodules
+
+  $ git fetch -q ../libs master
+  $ josh-filter -s :inline_submodules master --update refs/heads/inlined
+  [2] :inline_submodules
+  $ git ls-tree -r --name-only refs/heads/inlined
+  .gitmodules
+  app.txt
+  libs/sub1/file1
+
+  $ cd ${TESTTMP}
+  $ git clone -q -b inlined app work 1> /dev/null
+  $ cd work
+  $ echo contents2 > libs/sub1/file1
+  $ echo contents2 > app.txt
+  $ git add .
+  $ git commit -q -m "change files"
+  $ git push -q origin HEAD:inlined
+
+  $ cd ${TESTTMP}/app
+  $ josh-filter -s :inline_submodules --reverse master --update refs/heads/inlined
+  [2] :inline_submodules
+
+  $ git log --pretty=%s master
+  change files
+  add libs
+  init
+  $ git show master:app.txt
+  contents2
+  $ git cat-file -t master:libs
+  commit
+  $ git log --pretty=%s master:libs
+  change files
+  add file1
+  $ git show $(git rev-parse master:libs):sub1/file1
+  contents2
@@ -0,0 +1,71 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ cat > .mailmap <<EOF
+  > # Comment
+  > Josh Contributor <contributor@example.com> Josh <josh@example.com>
+  > <jane@example.com> <jane@internal.example.com>
+  > EOF
+  $ git add .mailmap
+  $ git commit -q -m "add mailmap"
+
+  $ echo contents1 > file1
+  $ git add file1
+  $ git commit -q -m "add file1"
+
+  $ echo contents2 > file2
+  $ git add file2
+  $ GIT_AUTHOR_NAME="Jane Doe" GIT_AUTHOR_EMAIL="jane@internal.example.com" git commit -q -m "add file2"
+
+  $ josh-filter -p ':mailmap("A <a@example.com> <b@example.com>","C <c@example.com>")'
+  :mailmap(
+      "A <a@example.com> <b@example.com>"
+      "C <c@example.com>"
+  )
+  $ josh-filter -p ':mailmap=.mailmap'
+  :mailmap=.mailmap
+
+  $ josh-filter -s ':mailmap("Jane <jane@example.com> <jane@internal.example.com>")' master --update refs/heads/inline
+  [3] :mailmap(
+      "Jane <jane@example.com> <jane@internal.example.com>"
+  )
+  $ git log refs/heads/inline --pretty="%an <%ae> %cn <%ce> %s"
+  Jane <jane@example.com> Josh <josh@example.com> add file2
+  Josh <josh@example.com> Josh <josh@example.com> add file1
+  Josh <josh@example.com> Josh <josh@example.com> add mailmap
+
+  $ josh-filter -s :mailmap=.mailmap master --update refs/heads/filtered
+  [3] :mailmap(
+      "Jane <jane@example.com> <jane@internal.example.com>"
+  )
+  [3] :mailmap=.mailmap
+  $ git log refs/heads/filtered --pretty="%an <%ae> %cn <%ce> %s"
+  Jane Doe <jane@example.com> Josh Contributor <contributor@example.com> add file2
+  Josh Contributor <contributor@example.com> Josh Contributor <contributor@example.com> add file1
+  Josh Contributor <contributor@example.com> Josh Contributor <contributor@example.com> add mailmap
+
+  $ git checkout -q filtered
+  $ echo contents3 > file3
+  $ git add file3
+  $ export GIT_COMMITTER_NAME="Josh Contributor"
+  $ export GIT_COMMITTER_EMAIL="contributor@example.com"
+  $ GIT_AUTHOR_NAME="Jane Doe" GIT_AUTHOR_EMAIL="jane@example.com" git commit -q -m "add file3"
+  $ echo contents4 > file4
+  $ git add file4
+  $ GIT_AUTHOR_NAME="Someone" GIT_AUTHOR_EMAIL="someone@example.com" git commit -q -m "add file4"
+
+  $ josh-filter -s :mailmap=.mailmap --reverse master --update refs/heads/filtered
+  [3] :mailmap(
+      "Jane <jane@example.com> <jane@internal.example.com>"
+  )
+  [3] :mailmap=.mailmap
+
+  $ git log master --pretty="%an <%ae> %cn <%ce> %s"
+  Someone <someone@example.com> Josh <josh@example.com> add file4
+  Jane Doe <jane@internal.example.com> Josh <josh@example.com> add file3
+  Jane Doe <jane@internal.example.com> Josh <josh@example.com> add file2
+  Josh <josh@example.com> Josh <josh@example.com> add file1
+  Josh <josh@example.com> Josh <josh@example.com> add mailmap
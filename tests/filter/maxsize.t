@@ -0,0 +1,46 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ mkdir -p src vendor
+  $ echo contents1 > src/file1
+  $ head -c 3000 /dev/zero | tr '\0' 'a' > src/large_text
+  $ printf 'binary\0data' > vendor/small.bin
+  $ head -c 2048 /dev/zero > vendor/large.bin
+  $ git add .
+  $ git commit -q -m "initial"
+
+  $ josh-filter -p :maxsize=2048
+  :maxsize=2K
+  $ josh-filter -p :maxsize=1536
+  :maxsize=1536
+  $ josh-filter -p :maxsize=1m
+  :maxsize=1M
+  $ josh-filter -p :maxsize=big
+  ERROR: invalid size: "big"
+  [1]
+
+  $ josh-filter -s :maxsize=2K master --update refs/heads/maxsize
+  [1] :maxsize=2K
+  $ git ls-tree -r --name-only refs/heads/maxsize
+  src/file1
+  vendor/large.bin
+  vendor/small.bin
+
+  $ josh-filter -s :exclude_binary master --update refs/heads/text
+  [1] :exclude_binary
+  [1] :maxsize=2K
+  $ git ls-tree -r --name-only refs/heads/text
+  src/file1
+  src/large_text
+
+  $ josh-filter -s :/vendor:maxsize=100:prefix=vendor master --update refs/heads/combined
+  [1] :/vendor
+  [1] :exclude_binary
+  [1] :maxsize=100
+  [1] :maxsize=2K
+  [1] :prefix=vendor
+  $ git ls-tree -r --name-only refs/heads/combined
+  vendor/small.bin
@@ -0,0 +1,57 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ echo contents1 > file1
+  $ git add file1
+  $ git commit -q -m "add file1" -m "Fixes: INTERNAL-123" -m "Reviewed-on: https://review.example.com/1"
+
+  $ echo contents2 > file2
+  $ git add file2
+  $ git commit -q -m "add file2" -m "Reviewed-on: https://review.example.com/2"
+
+  $ josh-filter -p ':message_replace("(?m)\n*^Reviewed-on: .*$":"","INTERNAL-([0-9]+)":"#$1")'
+  :message_replace(
+      "(?m)\n*^Reviewed-on: .*$":""
+      "INTERNAL-([0-9]+)":"#$1"
+  )
+  $ josh-filter -s ':message_replace("(?m)\n*^Reviewed-on: .*$":"","INTERNAL-([0-9]+)":"#$1")' master --update refs/heads/filtered
+  [2] :message_replace(
+      "(?m)\n*^Reviewed-on: .*$":""
+      "INTERNAL-([0-9]+)":"#$1"
+  )
+
+  $ git log refs/heads/filtered --pretty=%B
+  add file2
+  
+  add file1
+  
+  Fixes: #123
+  
+
+  $ git checkout -q filtered
+  $ echo contents3 > file3
+  $ git add file3
+  $ git commit -q -m "add file3"
+
+  $ josh-filter -s ':message_replace("(?m)\n*^Reviewed-on: .*$":"","INTERNAL-([0-9]+)":"#$1")' --reverse master --update refs/heads/filtered
+  [2] :message_replace(
+      "(?m)\n*^Reviewed-on: .*$":""
+      "INTERNAL-([0-9]+)":"#$1"
+  )
+
+  $ git log master --pretty=%B
+  add file3
+  
+  add file2
+  
+  Reviewed-on: https://review.example.com/2
+  
+  add file1
+  
+  Fixes: INTERNAL-123
+  
+  Reviewed-on: https://review.example.com/1
+  
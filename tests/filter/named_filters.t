@@ -0,0 +1,94 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ mkdir -p libs/shared libs/a libs/b proto ws .josh
+  $ echo contents > libs/shared/file
+  $ echo contents > libs/a/file
+  $ echo contents > libs/b/file
+  $ echo contents > proto/a.proto
+  $ echo contents > proto/a.txt
+  $ cat > .josh/common.josh <<EOF
+  > ::libs/shared/
+  > ::proto/*.proto
+  > EOF
+  $ cat > ws/workspace.josh <<EOF
+  > # comment
+  > let libs = :[
+  >   ::libs/a/
+  >   ::libs/b/
+  > ]
+  > :~common
+  > deps = :~libs
+  > EOF
+  $ git add .
+  $ git commit -q -m "initial"
+
+  $ josh-filter -p ":[let a = ::libs/a/,x = :~a,y = :~a]"
+  :~a[::libs/a/]:[
+      :prefix=x
+      :prefix=y
+  ]
+  $ josh-filter -p ":~libs[::libs/a/,::libs/b/]:prefix=deps"
+  deps = :~libs[
+      ::libs/a/
+      ::libs/b/
+  ]
+  $ josh-filter -p ":~common"
+  ERROR: unknown filter definition: "common"
+  [1]
+  $ cat > local.josh <<EOF
+  > let libs = :[
+  >   ::libs/a/
+  >   ::libs/b/
+  > ]
+  > a = :~libs
+  > b = :~libs:/libs/a
+  > EOF
+  $ josh-filter -p --file local.josh
+  let libs = :[
+      ::libs/a/
+      ::libs/b/
+  ]
+  :~libs:[
+      :prefix=a
+      b = :/libs/a
+  ]
+  $ rm local.josh
+
+  $ josh-filter -s :workspace=ws master --update refs/heads/ws
+  [1] :workspace=ws
+  $ git ls-tree -r --name-only refs/heads/ws
+  deps/libs/a/file
+  deps/libs/b/file
+  libs/shared/file
+  proto/a.proto
+  workspace.josh
+
+  $ git checkout -q ws 1> /dev/null
+  $ echo contents2 > deps/libs/a/file
+  $ echo contents2 > libs/shared/file
+  $ git add .
+  $ git commit -q -m "change files"
+
+  $ josh-filter -s :workspace=ws --reverse master --update refs/heads/ws
+  [1] :workspace=ws
+
+  $ git checkout -q master 1> /dev/null
+  $ git log --pretty=%s
+  change files
+  initial
+  $ cat libs/a/file
+  contents2
+  $ cat libs/shared/file
+  contents2
+  $ cat ws/workspace.josh
+  # comment
+  let libs = :[
+      ::libs/a/
+      ::libs/b/
+  ]
+  :~common
+  deps = :~libs
@@ -0,0 +1,118 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ mkdir -p libs/a/sub libs/b tools/gen docs
+  $ echo contents > README.md
+  $ echo contents > libs/top.txt
+  $ echo contents > libs/a/file.c
+  $ echo contents > libs/a/sub/file.c
+  $ echo contents > libs/b/file.c
+  $ echo contents > tools/gen/main.py
+  $ echo contents > tools/gen/notes.txt
+  $ echo contents > docs/index.md
+  $ mkdir -p .sparse
+  $ cat > .sparse/cone <<EOF
+  > # cone mode definition
+  > /*
+  > !/*/
+  > /libs/
+  > !/libs/*/
+  > /libs/a/
+  > EOF
+  $ cat > .sparse/tools <<EOF
+  > /tools/
+  > !*.txt
+  > *.md
+  > EOF
+  $ git add .
+  $ git commit -q -m "initial"
+
+  $ josh-filter -p :sparse=.sparse/cone
+  :sparse=.sparse/cone
+  $ josh-filter -p :sparse
+  ERROR: Filter ":sparse" requires an argument.
+  
+  Note: use "=" to provide the argument value:
+  
+    :sparse=path
+  
+  Where `path` is path to a file in the sparse-checkout format
+  
+  [1]
+
+  $ josh-filter -s :sparse=.sparse/cone master --update refs/heads/cone
+  [1] :sparse=.sparse/cone
+  $ git ls-tree -r --name-only refs/heads/cone
+  README.md
+  libs/a/file.c
+  libs/a/sub/file.c
+  libs/top.txt
+
+  $ josh-filter -s :sparse=.sparse/tools master --update refs/heads/tools
+  [1] :sparse=.sparse/cone
+  [1] :sparse=.sparse/tools
+  $ git ls-tree -r --name-only refs/heads/tools
+  README.md
+  docs/index.md
+  tools/gen/main.py
+
+  $ josh-filter -s :sparse=.sparse/missing master --update refs/heads/missing
+  Warning: reference refs/heads/missing wasn't updated
+  [1] :sparse=.sparse/cone
+  [1] :sparse=.sparse/missing
+  [1] :sparse=.sparse/tools
+  $ git ls-tree -r --name-only refs/heads/missing
+  fatal: Not a valid object name refs/heads/missing
+  [128]
+
+  $ echo "/libs/b/" >> .sparse/cone
+  $ git add .
+  $ git commit -q -m "add libs/b to sparse definition"
+
+  $ josh-filter -s :sparse=.sparse/cone master --update refs/heads/cone
+  [1] :sparse=.sparse/missing
+  [1] :sparse=.sparse/tools
+  [2] :sparse=.sparse/cone
+  $ git log --pretty=%s refs/heads/cone
+  add libs/b to sparse definition
+  initial
+  $ git ls-tree -r --name-only refs/heads/cone
+  README.md
+  libs/a/file.c
+  libs/a/sub/file.c
+  libs/b/file.c
+  libs/top.txt
+
+  $ git checkout -q cone 1> /dev/null
+  $ echo contents2 > libs/b/file.c
+  $ echo contents > libs/b/new.c
+  $ git add .
+  $ git commit -q -m "change libs/b"
+
+  $ josh-filter -s :sparse=.sparse/cone --reverse master --update refs/heads/cone
+  [1] :sparse=.sparse/missing
+  [1] :sparse=.sparse/tools
+  [2] :sparse=.sparse/cone
+
+  $ git checkout -q master 1> /dev/null
+  $ git log --pretty=%s
+  change libs/b
+  add libs/b to sparse definition
+  initial
+  $ git ls-tree -r --name-only HEAD
+  .sparse/cone
+  .sparse/tools
+  README.md
+  docs/index.md
+  libs/a/file.c
+  libs/a/sub/file.c
+  libs/b/file.c
+  libs/b/new.c
+  libs/top.txt
+  tools/gen/main.py
+  tools/gen/notes.txt
+  $ cat libs/b/file.c
+  contents2
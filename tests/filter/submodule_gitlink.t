@@ -0,0 +1,110 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q repo 1>/dev/null
+  $ cd repo
+
+  $ mkdir -p libs/a app
+  $ echo contents > libs/a/file
+  $ echo contents > app/file
+  $ git add .
+  $ git commit -q -m "initial"
+  $ echo contents2 > libs/a/file
+  $ git add .
+  $ git commit -q -m "change libs"
+
+  $ josh-filter -p ':submodule=libs;"http://localhost:8001/repo.git"'
+  :submodule=libs;"http://localhost:8001/repo.git"
+  $ josh-filter -p :submodule=libs
+  ERROR: Filter ":submodule" requires two arguments.
+  
+  Note: use "=" to provide the argument values:
+  
+    :submodule=path;"url"
+  
+  Where `path` is the directory to replace and `url` is the url of the repository
+  on the proxy
+  
+  [1]
+
+  $ josh-filter -s ':submodule=libs;"http://localhost:8001/repo.git"' master --update refs/heads/sub
+  [2] :/libs
+  [2] :submodule=libs;"http://localhost:8001/repo.git"
+  $ git log --pretty=%s sub
+  change libs
+  initial
+  $ git ls-tree -r --name-only sub
+  .gitmodules
+  app/file
+  libs
+  $ git show sub:.gitmodules
+  [submodule "libs"]
+  \tpath = libs (esc)
+  \turl = http://localhost:8001/repo.git:/libs.git (esc)
+  $ git cat-file -t sub:libs
+  commit
+  $ git log --pretty=%s sub:libs
+  change libs
+  initial
+  $ git ls-tree -r --name-only sub:libs
+  a/file
+
+  $ cd ${TESTTMP}
+  $ git clone -q -b sub repo work 1> /dev/null
+  $ cd work
+  $ echo contents2 > app/file
+  $ git add .
+  $ git commit -q -m "change app"
+  $ git push -q origin HEAD:sub
+
+  $ cd ${TESTTMP}/repo
+  $ git checkout -q -b libs-work $(git rev-parse sub:libs)
+  $ echo contents3 > a/file
+  $ git commit -q -a -m "change libs in submodule"
+  $ git checkout -q sub
+  $ git update-index --cacheinfo 160000,$(git rev-parse libs-work),libs
+  $ git commit -q -m "update submodule"
+
+  $ josh-filter -s ':submodule=libs;"http://localhost:8001/repo.git"' --reverse master --update refs/heads/sub
+  [2] :/libs
+  [2] :submodule=libs;"http://localhost:8001/repo.git"
+
+  $ git log --pretty=%s master
+  update submodule
+  change app
+  change libs
+  initial
+  $ git ls-tree -r --name-only master
+  app/file
+  libs/a/file
+  $ git show master:app/file
+  contents2
+  $ git show master:libs/a/file
+  contents3
+
+An existing section for the path is replaced
+
+  $ printf '[submodule "libs"]\n\tpath = libs\n\turl = ../old.git\n' > .gitmodules
+  $ git add .gitmodules
+  $ git commit -q -m "add stale gitmodules"
+  $ josh-filter ':submodule=libs;"http://localhost:8001/repo.git"' master --update refs/heads/sub2
+  $ git show sub2:.gitmodules
+  [submodule "libs"]
+  \tpath = libs (esc)
+  \turl = http://localhost:8001/repo.git:/libs.git (esc)
+
+It can't be applied to trees, but it can be chained after a composition
+
+  $ josh-filter -p ':[a=:/libs:submodule=a;"http://localhost:8001/repo.git"]'
+  ERROR: Filter ":submodule" can't be used inside of a composition or workspace.
+  
+  Note: chain it after the composition instead:
+  
+    :[...]:submodule=path;"url"
+  
+  [1]
+  $ josh-filter ':[a=:/libs,b=:/app]:submodule=a;"http://localhost:8001/repo.git"' master --update refs/heads/sub3
+  $ git ls-tree -r --name-only sub3
+  .gitmodules
+  a
+  b/file
@@ -124,7 +124,7 @@
   ]
   .
   |-- josh
-  |   `-- 22
+  |   `-- 23
   |       `-- sled
   |           |-- blobs
   |           |-- conf
@@ -78,7 +78,7 @@
   "real_repo.git" = [":/sub1"]
   .
   |-- josh
-  |   `-- 22
+  |   `-- 23
   |       `-- sled
   |           |-- blobs
   |           |-- conf
@@ -1,5 +1,5 @@
 #!/bin/bash
-curl -s http://localhost:8002/filters/refresh
+curl -s -k "${PROXY_URL:-http://localhost:8002}/filters/refresh"
 killall -2 josh-proxy
 cd ${TESTTMP}/remote/scratch
 tree -I hooks
@@ -0,0 +1,124 @@
+  $ EXTRA_OPTS="--metrics-port=8004" . ${TESTDIR}/setup_test_env.sh
+  $ cd ${TESTTMP}
+
+  $ git clone -q http://localhost:8001/real_repo.git 1> /dev/null
+  warning: You appear to have cloned an empty repository.
+  $ cd real_repo
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+  $ git push -q origin master 1> /dev/null
+
+  $ cd ${TESTTMP}
+  $ git clone -q http://localhost:8002/real_repo.git:/sub1.git sub1
+  $ cd sub1
+  $ echo contents2 > file2
+  $ git add file2
+  $ git commit -q -m "add file2"
+  $ git push -q origin master 2> /dev/null
+  $ git push -q origin master:refs/for/master 2> /dev/null
+
+The metrics are only served on their own port
+
+  $ curl -s http://localhost:8002/~/metrics | grep -c josh_
+  0
+  [1]
+  $ curl -s -o /dev/null -w "%{http_code}\n" http://localhost:8004/other
+  404
+  $ curl -s -o /dev/null -w "%{content_type}\n" http://localhost:8004/~/metrics
+  text/plain; version=0.0.4
+  $ curl -s http://localhost:8004/~/metrics > metrics
+  $ grep -E "^josh_proxy_http_requests_total" metrics | grep -E "info_refs|receive_pack|metrics"
+  josh_proxy_http_requests_total{endpoint="info_refs",status="200"} * (glob)
+  josh_proxy_http_requests_total{endpoint="receive_pack",status="200"} 2
+  $ grep -E "^josh_proxy_pushes_total" metrics
+  josh_proxy_pushes_total{mode="normal",result="success"} 1
+  josh_proxy_pushes_total{mode="review",result="success"} 1
+  $ grep -E "^josh_filter_(computations|cache_misses)_total" metrics
+  josh_filter_cache_misses_total{filter=":/sub1"} * (glob)
+  josh_filter_computations_total{filter=":/sub1"} * (glob)
+  $ grep -E "^josh_proxy_upstream_fetch_(duration_seconds_count|failures_total)" metrics
+  josh_proxy_upstream_fetch_duration_seconds_count * (glob)
+  josh_proxy_upstream_fetch_failures_total 0
+  $ grep -E "^josh_proxy_semaphore_wait_seconds_count" metrics
+  josh_proxy_semaphore_wait_seconds_count{semaphore="fetch"} * (glob)
+  josh_proxy_semaphore_wait_seconds_count{semaphore="filter"} * (glob)
+  $ grep -c -E "^josh_cache_size_bytes [1-9]" metrics
+  1
+
+  $ . ${TESTDIR}/destroy_test_env.sh
+  "real_repo.git" = [
+      ":/sub1",
+      "::sub1/",
+  ]
+  .
+  |-- josh
+  |   `-- 23
+  |       `-- sled
+  |           |-- blobs
+  |           |-- conf
+  |           `-- db
+  |-- mirror
+  |   |-- FETCH_HEAD
+  |   |-- HEAD
+  |   |-- config
+  |   |-- description
+  |   |-- info
+  |   |   `-- exclude
+  |   |-- objects
+  |   |   |-- 3d
+  |   |   |   `-- 77ff51363c9825cc2a221fc0ba5a883a1a2c72
+  |   |   |-- 6b
+  |   |   |   `-- 46faacade805991bcaea19382c9d941828ce80
+  |   |   |-- 81
+  |   |   |   `-- b10fb4984d20142cd275b89c91c346e536876a
+  |   |   |-- a0
+  |   |   |   `-- 24003ee1acc6bf70318a46e7b6df651b9dc246
+  |   |   |-- ba
+  |   |   |   `-- 7e17233d9f79c96cb694959eb065302acd96a6
+  |   |   |-- bb
+  |   |   |   `-- 282e9cdc1b972fffd08fd21eead43bc0c83cb8
+  |   |   |-- c6
+  |   |   |   `-- 27a2e3a6bfbb7307f522ad94fdfc8c20b92967
+  |   |   |-- c8
+  |   |   |   `-- 2fc150c43f13cc56c0e9caeba01b58ec612022
+  |   |   |-- info
+  |   |   `-- pack
+  |   `-- refs
+  |       |-- heads
+  |       |-- josh
+  |       |   `-- upstream
+  |       |       `-- real_repo.git
+  |       |           |-- HEAD
+  |       |           `-- refs
+  |       |               `-- heads
+  |       |                   `-- master
+  |       `-- tags
+  `-- overlay
+      |-- HEAD
+      |-- config
+      |-- description
+      |-- info
+      |   `-- exclude
+      |-- objects
+      |   |-- 0b
+      |   |   `-- 4cf6c9efbbda1eada39fa9c1d21d2525b027bb
+      |   |-- 6b
+      |   |   `-- 46faacade805991bcaea19382c9d941828ce80
+      |   |-- 81
+      |   |   `-- b10fb4984d20142cd275b89c91c346e536876a
+      |   |-- ba
+      |   |   `-- 7e17233d9f79c96cb694959eb065302acd96a6
+      |   |-- c6
+      |   |   `-- 27a2e3a6bfbb7307f522ad94fdfc8c20b92967
+      |   |-- d8
+      |   |   `-- 388f5880393d255b371f1ed9b801d35620017e
+      |   |-- info
+      |   `-- pack
+      `-- refs
+          |-- heads
+          |-- namespaces
+          `-- tags
+  
+  41 directories, 27 files
@@ -0,0 +1,226 @@
+  $ export JOSH_META_REPO=/meta_repo.git
+  $ . ${TESTDIR}/setup_test_env.sh
+
+  $ cd ${TESTTMP}
+  $ cat > receive.sh <<'EOF'
+  > cat >> ${TESTTMP}/events
+  > echo >> ${TESTTMP}/events
+  > echo "Content-Type: text/plain"
+  > echo
+  > EOF
+  $ hyper-cgi-test-server --port=8003 --dir=${TESTTMP} --cmd=sh --args=${TESTTMP}/receive.sh > receiver.out 2>&1 &
+  $ echo $! > receiver_pid
+  $ touch events
+  $ wait_for_events() {
+  >   for i in $(seq 50); do
+  >     test "$(wc -l < ${TESTTMP}/events)" -ge "$1" && break
+  >     sleep 0.1
+  >   done
+  >   cat ${TESTTMP}/events
+  > }
+  $ wait_for_filtered_ref() {
+  >   for i in $(seq 50); do
+  >     test -n "$(git -C ${TESTTMP}/remote/scratch/overlay for-each-ref refs/josh/filtered)" && break
+  >     sleep 0.1
+  >   done
+  >   git -C ${TESTTMP}/remote/scratch/overlay for-each-ref refs/josh/filtered
+  > }
+
+  $ git clone -q http://localhost:8001/meta_repo.git
+  warning: You appear to have cloned an empty repository.
+  $ cd meta_repo
+  $ mkdir real_repo.git
+  $ cat > real_repo.git/config.yml <<EOF
+  > repo: /real_repo.git
+  > notify:
+  >   - url: http://localhost:8003/
+  >     filter: :/sub1
+  > EOF
+  $ git add .
+  $ git commit -q -m "add real_repo"
+  $ git push -q
+
+  $ cd ${TESTTMP}
+  $ git clone -q http://localhost:8001/real_repo.git backing_repo
+  warning: You appear to have cloned an empty repository.
+  $ cd backing_repo
+  $ mkdir sub1 sub2
+  $ echo contents1 > sub1/file1
+  $ echo contents2 > sub2/file2
+  $ git add .
+  $ git commit -q -m "add files"
+  $ git push -q origin master
+
+The first fetch only records the state of the filtered ref
+  $ cd ${TESTTMP}
+  $ git clone -q http://localhost:8002/real_repo.git:/sub1.git sub1
+  $ wait_for_filtered_ref
+  52be19f48566b18ccf49846b221d84f0b75cae66 commit\trefs/josh/filtered/real_repo.git/%3A%2Fsub1/HEAD (esc)
+  $ wait_for_events 0
+
+The subscriptions are kept across restarts
+  $ cat ${TESTTMP}/remote/scratch/subscriptions.json
+  {
+    "/real_repo.git": [
+      {
+        "url": "http://localhost:8003/",
+        "filter": ":/sub1"
+      }
+    ]
+  } (no-eol)
+
+Changes outside of the filter don't cause events
+  $ cd ${TESTTMP}/backing_repo
+  $ echo contents3 > sub2/file3
+  $ git add .
+  $ git commit -q -m "add file3"
+  $ git push -q origin master
+  $ git ls-remote http://localhost:8002/real_repo.git:/sub1.git master
+  52be19f48566b18ccf49846b221d84f0b75cae66\trefs/heads/master (esc)
+  $ wait_for_events 0
+
+  $ echo contents4 > sub1/file4
+  $ git add .
+  $ git commit -q -m "add file4"
+  $ git push -q origin master
+  $ git ls-remote http://localhost:8002/real_repo.git:/sub1.git master
+  35893132bcdfeb271d6607c3fb45baecba2709a3\trefs/heads/master (esc)
+  $ wait_for_events 1
+  {"upstream_repo":"/real_repo.git","filter":":/sub1","ref":"refs/josh/filtered/real_repo.git/%3A%2Fsub1/HEAD","old":"52be19f48566b18ccf49846b221d84f0b75cae66","new":"35893132bcdfeb271d6607c3fb45baecba2709a3","original":"f140c463b89abf21395e0666f6a2ba8d555509b1"}
+
+Pushes through the proxy notify right away
+  $ cd ${TESTTMP}/sub1
+  $ git pull -q --rebase
+  $ echo contents5 > file5
+  $ git add .
+  $ git commit -q -m "add file5"
+  $ git push -q origin master 2>/dev/null
+  $ cd ${TESTTMP}
+  $ wait_for_events 2
+  {"upstream_repo":"/real_repo.git","filter":":/sub1","ref":"refs/josh/filtered/real_repo.git/%3A%2Fsub1/HEAD","old":"52be19f48566b18ccf49846b221d84f0b75cae66","new":"35893132bcdfeb271d6607c3fb45baecba2709a3","original":"f140c463b89abf21395e0666f6a2ba8d555509b1"}
+  {"upstream_repo":"/real_repo.git","filter":":/sub1","ref":"refs/josh/filtered/real_repo.git/%3A%2Fsub1/HEAD","old":"35893132bcdfeb271d6607c3fb45baecba2709a3","new":"71fca29db540377f6fcd623c893239b01e266b92","original":"19d26084bfbe1435b8317aa6571837f531f6fb8a"}
+
+  $ kill $(cat receiver_pid)
+  $ . ${TESTDIR}/destroy_test_env.sh
+  "meta_repo.git" = ["::real_repo.git/"]
+  "real_repo.git" = [
+      ":/sub1",
+      "::sub1/",
+      "::sub2/",
+  ]
+  .
+  |-- josh
+  |   `-- 23
+  |       `-- sled
+  |           |-- blobs
+  |           |-- conf
+  |           `-- db
+  |-- mirror
+  |   |-- FETCH_HEAD
+  |   |-- HEAD
+  |   |-- config
+  |   |-- description
+  |   |-- info
+  |   |   `-- exclude
+  |   |-- objects
+  |   |   |-- 19
+  |   |   |   `-- d26084bfbe1435b8317aa6571837f531f6fb8a
+  |   |   |-- 1c
+  |   |   |   `-- b5d64cdb55e3db2a8d6f00d596572b4cfa9d5c
+  |   |   |-- 1d
+  |   |   |   `-- 5b092002f6a0697c8fc042c02fe0ead0c6db35
+  |   |   |-- 28
+  |   |   |   `-- 8746e9035732a1fe600ee331de94e70f9639cb
+  |   |   |-- 2e
+  |   |   |   `-- aecd4607749d422e7e90c687105eba01d6086f
+  |   |   |-- 3d
+  |   |   |   `-- 77ff51363c9825cc2a221fc0ba5a883a1a2c72
+  |   |   |-- 6b
+  |   |   |   `-- 46faacade805991bcaea19382c9d941828ce80
+  |   |   |-- 7a
+  |   |   |   `-- f235033366a286921698c5a5a3eca0c36cad67
+  |   |   |-- 95
+  |   |   |   `-- 3f19a771cbc2937546fec3b0b155fd2ffe26be
+  |   |   |-- 96
+  |   |   |   |-- 162151f29dc6041953437259c4f92fb90751d5
+  |   |   |   `-- a86b5e64edd495dae69af83732b61adda4eb5a
+  |   |   |-- a0
+  |   |   |   `-- 24003ee1acc6bf70318a46e7b6df651b9dc246
+  |   |   |-- ae
+  |   |   |   `-- a557394ce29f000108607abd97f19fed4d1b7c
+  |   |   |-- b1
+  |   |   |   `-- 5aa37223eef07febde4de27cfb6afae3e3f21f
+  |   |   |-- c7
+  |   |   |   `-- 20b1cc613636b922063b2ed7182f4d352a513c
+  |   |   |-- ca
+  |   |   |   `-- 77fb80b683ebe1fd4d4d6c2dee5d247f9befee
+  |   |   |-- e0
+  |   |   |   `-- 58eaca88d21b1c85503f88bcdfb9f25103b8f1
+  |   |   |-- e2
+  |   |   |   `-- 5a0f150ab310f572f931f56a7be0af8bb238ab
+  |   |   |-- f0
+  |   |   |   `-- 5584b306c4ad56bc20bfe1e6c22f6bce9d2fb3
+  |   |   |-- f1
+  |   |   |   `-- 40c463b89abf21395e0666f6a2ba8d555509b1
+  |   |   |-- f2
+  |   |   |   `-- 792aedf223537466e4ad08995b2bf51bf38649
+  |   |   |-- fd
+  |   |   |   `-- fb9fc0abffa6ea6cbd7b898d4268fe5356552f
+  |   |   |-- info
+  |   |   `-- pack
+  |   `-- refs
+  |       |-- heads
+  |       |-- josh
+  |       |   `-- upstream
+  |       |       |-- meta_repo.git
+  |       |       |   |-- HEAD
+  |       |       |   `-- refs
+  |       |       |       `-- heads
+  |       |       |           `-- master
+  |       |       `-- real_repo.git
+  |       |           |-- HEAD
+  |       |           `-- refs
+  |       |               `-- heads
+  |       |                   `-- master
+  |       `-- tags
+  |-- overlay
+  |   |-- HEAD
+  |   |-- config
+  |   |-- description
+  |   |-- info
+  |   |   `-- exclude
+  |   |-- objects
+  |   |   |-- 19
+  |   |   |   `-- d26084bfbe1435b8317aa6571837f531f6fb8a
+  |   |   |-- 2e
+  |   |   |   `-- aecd4607749d422e7e90c687105eba01d6086f
+  |   |   |-- 35
+  |   |   |   `-- 893132bcdfeb271d6607c3fb45baecba2709a3
+  |   |   |-- 52
+  |   |   |   `-- be19f48566b18ccf49846b221d84f0b75cae66
+  |   |   |-- 6e
+  |   |   |   `-- 3621ef2d244071b62afa55bd5c54148f7a9f9c
+  |   |   |-- 71
+  |   |   |   `-- fca29db540377f6fcd623c893239b01e266b92
+  |   |   |-- 74
+  |   |   |   `-- 960c2e3cd5fbdef69ba22d21181b2c4349d48f
+  |   |   |-- 96
+  |   |   |   `-- 450d8fa778ce0fffb58731d082cb398b928c7b
+  |   |   |-- e0
+  |   |   |   `-- 58eaca88d21b1c85503f88bcdfb9f25103b8f1
+  |   |   |-- f0
+  |   |   |   `-- 5584b306c4ad56bc20bfe1e6c22f6bce9d2fb3
+  |   |   |-- info
+  |   |   `-- pack
+  |   `-- refs
+  |       |-- heads
+  |       |-- josh
+  |       |   `-- filtered
+  |       |       `-- real_repo.git
+  |       |           `-- %3A%2Fsub1
+  |       |               `-- HEAD
+  |       |-- namespaces
+  |       `-- tags
+  `-- subscriptions.json
+  
+  65 directories, 49 files
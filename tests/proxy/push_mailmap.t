@@ -0,0 +1,146 @@
+  $ . ${TESTDIR}/setup_test_env.sh
+  $ cd ${TESTTMP}
+
+  $ git clone -q http://localhost:8001/real_repo.git 1> /dev/null
+  warning: You appear to have cloned an empty repository.
+  $ cd real_repo
+
+  $ cat > .mailmap <<EOF
+  > Jane Doe <jane@example.com> <jane@internal.example.com>
+  > EOF
+  $ mkdir sub1 sub2
+  $ echo contents1 > sub1/file1
+  $ echo contents2 > sub2/file2
+  $ git add .
+  $ GIT_AUTHOR_NAME="Jane" GIT_AUTHOR_EMAIL="jane@internal.example.com" git commit -q -m "add files"
+  $ git push -q
+
+A mailmap inside of a composition maps the identities of the whole commit
+
+  $ cd ${TESTTMP}
+  $ git clone -q "http://localhost:8002/real_repo.git:[a=:mailmap=.mailmap:/sub1,::sub2/].git" filtered
+  $ cd filtered
+  $ git log --pretty="%an <%ae> %s"
+  Jane Doe <jane@example.com> add files
+  $ tree
+  .
+  |-- a
+  |   `-- file1
+  `-- sub2
+      `-- file2
+  
+  3 directories, 2 files
+
+Pushing maps them back
+
+  $ echo contents3 > a/file3
+  $ git add a
+  $ GIT_AUTHOR_NAME="Jane Doe" GIT_AUTHOR_EMAIL="jane@example.com" git commit -q -m "add file3"
+  $ git push -q 2> /dev/null
+
+  $ cd ${TESTTMP}/real_repo
+  $ git pull -q --rebase
+  $ git log --pretty="%an <%ae> %s"
+  Jane Doe <jane@internal.example.com> add file3
+  Jane <jane@internal.example.com> add files
+  $ cat sub1/file3
+  contents3
+
+  $ bash ${TESTDIR}/destroy_test_env.sh
+  "real_repo.git" = [
+      "::sub1/",
+      "::sub2/",
+      ":[:mailmap=.mailmap:/sub1:prefix=a,::sub2/]",
+  ]
+  .
+  |-- josh
+  |   `-- 23
+  |       `-- sled
+  |           |-- blobs
+  |           |-- conf
+  |           `-- db
+  |-- mirror
+  |   |-- FETCH_HEAD
+  |   |-- HEAD
+  |   |-- config
+  |   |-- description
+  |   |-- info
+  |   |   `-- exclude
+  |   |-- objects
+  |   |   |-- 02
+  |   |   |   `-- 55aba1f697dd484938e20c38c2b94d0d3302f5
+  |   |   |-- 3d
+  |   |   |   `-- 77ff51363c9825cc2a221fc0ba5a883a1a2c72
+  |   |   |-- 6b
+  |   |   |   `-- 46faacade805991bcaea19382c9d941828ce80
+  |   |   |-- 6f
+  |   |   |   `-- 2dd3a03554b27244fca227c751fae5ba4c03f1
+  |   |   |-- a0
+  |   |   |   `-- 24003ee1acc6bf70318a46e7b6df651b9dc246
+  |   |   |-- ae
+  |   |   |   `-- a557394ce29f000108607abd97f19fed4d1b7c
+  |   |   |-- ba
+  |   |   |   `-- dc07eb0410c8e295702cbdfabab28de0ecdc80
+  |   |   |-- info
+  |   |   `-- pack
+  |   `-- refs
+  |       |-- heads
+  |       |-- josh
+  |       |   `-- upstream
+  |       |       `-- real_repo.git
+  |       |           |-- HEAD
+  |       |           `-- refs
+  |       |               `-- heads
+  |       |                   `-- master
+  |       `-- tags
+  `-- overlay
+      |-- HEAD
+      |-- config
+      |-- description
+      |-- info
+      |   `-- exclude
+      |-- objects
+      |   |-- 03
+      |   |   `-- 607689d6edbda00a3e61d8814d10f68b78e7bd
+      |   |-- 1c
+      |   |   `-- b5d64cdb55e3db2a8d6f00d596572b4cfa9d5c
+      |   |-- 31
+      |   |   `-- 8ff73ef0235d761f6aee1194a6bdaaeb1d0923
+      |   |-- 4b
+      |   |   `-- 825dc642cb6eb9a060e54bf8d69288fbee4904
+      |   |-- 59
+      |   |   `-- 0fafb5e99b66960440a08db4b9a0eaa436a835
+      |   |-- 5f
+      |   |   `-- e573c4b9aa179b802aa76f10d283430770778d
+      |   |-- 66
+      |   |   `-- 40355dfbb8149e6fb196ff6487e6628a932421
+      |   |-- 7a
+      |   |   `-- 8a2405a6715b0a0f432d1bc1e0515ac513984d
+      |   |-- 7f
+      |   |   `-- 7cc7008b8f1464ce892c9739551a129ab1908f
+      |   |-- 89
+      |   |   `-- 52f96884e0ee453406177bebbf4f74a8a8d1be
+      |   |-- 95
+      |   |   `-- 3f19a771cbc2937546fec3b0b155fd2ffe26be
+      |   |-- 99
+      |   |   `-- 5a0f099a9007ba3af44ef52e3506a7cf1c15ab
+      |   |-- af
+      |   |   `-- 3f0aaf5920d2645356c0f0ce1c9cdc2388eee4
+      |   |-- b2
+      |   |   `-- 6a812a71a431e71d30949f25013ca63f8493c3
+      |   |-- c4
+      |   |   `-- 96e3f1973f4b89307bf1dfef60ed5dbba7ee70
+      |   |-- c8
+      |   |   `-- 2fc150c43f13cc56c0e9caeba01b58ec612022
+      |   |-- e3
+      |   |   `-- db0936640a8e40edf51b28357362de839faf38
+      |   |-- f4
+      |   |   `-- cc8cb7efd9eb88d5e3a18dfe7ae64d804273d4
+      |   |-- info
+      |   `-- pack
+      `-- refs
+          |-- heads
+          |-- namespaces
+          `-- tags
+  
+  52 directories, 38 files
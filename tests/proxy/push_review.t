@@ -87,7 +87,7 @@ Make sure all temporary namespace got removed
   ]
   .
   |-- josh
-  |   `-- 22
+  |   `-- 23
   |       `-- sled
   |           |-- blobs
   |           |-- conf
@@ -48,7 +48,7 @@ test for that.
   "real_repo.git" = []
   .
   |-- josh
-  |   `-- 22
+  |   `-- 23
   |       `-- sled
   |           |-- blobs
   |           |-- conf
@@ -118,7 +118,7 @@ Put a double slash in the URL to see that it also works
   ]
   .
   |-- josh
-  |   `-- 22
+  |   `-- 23
   |       `-- sled
   |           |-- blobs
   |           |-- conf
@@ -91,7 +91,7 @@ if [ -n "${JOSH_TEST_SSH+x}" ]; then
 fi
 
 COUNTER=0
-until curl -s http://localhost:8002/
+until curl -s -k "${PROXY_URL:-http://localhost:8002}/"
 do
     sleep 0.1
     COUNTER=$((COUNTER + 1))
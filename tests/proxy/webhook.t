@@ -0,0 +1,182 @@
+  $ export JOSH_WEBHOOK_SECRET=webhook-secret
+  $ cat > ${PWD}/josh.toml <<'EOF'
+  > [[upstream]]
+  > prefix = "/mirror"
+  > remote = ["http://localhost:8001/real"]
+  > EOF
+  $ EXTRA_OPTS="--config=${PWD}/josh.toml" . ${TESTDIR}/setup_test_env.sh
+  $ cd ${TESTTMP}
+
+  $ git clone -q http://localhost:8001/real_repo.git 1> /dev/null
+  warning: You appear to have cloned an empty repository.
+  $ cd real_repo
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+  $ git push -q origin master 1> /dev/null
+
+  $ cd ${TESTTMP}
+  $ git clone -q http://localhost:8002/real_repo.git:/sub1.git sub1
+
+  $ cd ${TESTTMP}/real_repo
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+  $ git push -q origin master 1> /dev/null
+
+  $ upstream_head() {
+  >   git -C ${TESTTMP}/remote/scratch/mirror for-each-ref --format="%(objectname) %(refname)" | grep "real_repo.*/heads/master" | cut -d " " -f 1
+  > }
+  $ upstream_head
+  bb282e9cdc1b972fffd08fd21eead43bc0c83cb8
+
+  $ webhook() {
+  >   curl -s -X POST -H "Content-Type: application/json" "$@" http://localhost:8002/~/webhook
+  > }
+  $ sign() {
+  >   printf "%s" "$1" | openssl dgst -sha256 -hmac "${2:-webhook-secret}" | sed -e "s/.*= *//"
+  > }
+
+  $ PAYLOAD='{"ref":"refs/heads/master","repository":{"full_name":"real_repo"}}'
+  $ webhook -H "X-GitHub-Event: push" -d "${PAYLOAD}"
+  Invalid webhook signature (no-eol)
+  $ webhook -H "X-GitHub-Event: push" -H "X-Hub-Signature-256: sha256=$(sign "${PAYLOAD}" wrong)" -d "${PAYLOAD}"
+  Invalid webhook signature (no-eol)
+  $ webhook -H "X-GitHub-Event: ping" -H "X-Hub-Signature-256: sha256=$(sign "${PAYLOAD}")" -d "${PAYLOAD}"
+  Ignored event (no-eol)
+  $ upstream_head
+  bb282e9cdc1b972fffd08fd21eead43bc0c83cb8
+
+  $ webhook -H "X-GitHub-Event: push" -H "X-Hub-Signature-256: sha256=$(sign "${PAYLOAD}")" -d "${PAYLOAD}"
+  Fetched /real_repo.git, refreshed 1 filters
+  $ test "$(upstream_head)" = "$(git -C ${TESTTMP}/real_repo rev-parse master)"
+
+  $ PAYLOAD='{"ref":"refs/heads/master","project":{"path_with_namespace":"real_repo"}}'
+  $ webhook -H "X-Gitlab-Event: Push Hook" -H "X-Gitlab-Token: wrong" -d "${PAYLOAD}"
+  Invalid webhook signature (no-eol)
+  $ webhook -H "X-Gitlab-Event: Push Hook" -H "X-Gitlab-Token: webhook-secret" -d "${PAYLOAD}"
+  Fetched /real_repo.git, refreshed 1 filters
+
+  $ PAYLOAD='{"repository":"/real_repo.git"}'
+  $ webhook -H "X-Josh-Signature-256: $(sign "${PAYLOAD}")" -d "${PAYLOAD}"
+  Fetched /real_repo.git, refreshed 1 filters
+  $ PAYLOAD='{"ref":"refs/heads/master"}'
+  $ webhook -H "X-Josh-Signature-256: $(sign "${PAYLOAD}")" -d "${PAYLOAD}"
+  Invalid webhook payload: no repository found in webhook payload (no-eol)
+
+Webhooks of an [[upstream]] are sent to the url with its prefix
+
+  $ cd ${TESTTMP}
+  $ git clone -q http://localhost:8001/real/repo2.git 1> /dev/null
+  warning: You appear to have cloned an empty repository.
+  $ cd repo2
+  $ echo contents3 > file3
+  $ git add file3
+  $ git commit -m "add file3" 1> /dev/null
+  $ git push -q origin master 1> /dev/null
+  $ git clone -q http://localhost:8002/mirror/repo2.git ${TESTTMP}/mirrored
+  $ echo contents4 > file4
+  $ git add file4
+  $ git commit -m "add file4" 1> /dev/null
+  $ git push -q origin master 1> /dev/null
+
+  $ PAYLOAD='{"ref":"refs/heads/master","repository":{"full_name":"repo2"}}'
+  $ webhook() {
+  >   curl -s -X POST -H "Content-Type: application/json" -H "X-GitHub-Event: push" -H "X-Hub-Signature-256: sha256=$(sign "${PAYLOAD}")" -d "${PAYLOAD}" http://localhost:8002/~/webhook${1:-}
+  > }
+The payload only names the repo on the forge, so without a prefix the default upstream is used
+
+  $ webhook
+  git process exited with code 128: "fatal: repository 'http://localhost:8001/repo2.git/' not found" (no-eol)
+  $ webhook /unknown
+  No upstream configured for /unknown/repo2.git (no-eol)
+  $ webhook /mirror
+  Fetched /mirror/repo2.git, refreshed 0 filters
+  $ test "$(git -C ${TESTTMP}/remote/scratch/mirror rev-parse "refs/josh/upstream/mirror%2Frepo2.git/refs/heads/master")" = "$(git rev-parse master)"
+
+  $ . ${TESTDIR}/destroy_test_env.sh
+  "mirror/repo2.git" = []
+  "real_repo.git" = [
+      ":/sub1",
+      "::sub1/",
+  ]
+  .
+  |-- josh
+  |   `-- 23
+  |       `-- sled
+  |           |-- blobs
+  |           |-- conf
+  |           `-- db
+  |-- mirror
+  |   |-- FETCH_HEAD
+  |   |-- HEAD
+  |   |-- config
+  |   |-- description
+  |   |-- info
+  |   |   `-- exclude
+  |   |-- objects
+  |   |   |-- 0a
+  |   |   |   `-- fd8cec19653467dd6b315cd306bc4a4df45c2a
+  |   |   |-- 1c
+  |   |   |   `-- b5d64cdb55e3db2a8d6f00d596572b4cfa9d5c
+  |   |   |-- 28
+  |   |   |   `-- 8746e9035732a1fe600ee331de94e70f9639cb
+  |   |   |-- 2a
+  |   |   |   `-- f8fd9cc75470c09c6442895133a815806018fc
+  |   |   |-- 3d
+  |   |   |   `-- 77ff51363c9825cc2a221fc0ba5a883a1a2c72
+  |   |   |-- 63
+  |   |   |   `-- 338b93640034e4886bd818d265179133a716a4
+  |   |   |-- 6b
+  |   |   |   `-- 46faacade805991bcaea19382c9d941828ce80
+  |   |   |-- 7c
+  |   |   |   `-- 30b7adfa79351301a11882adf49f438ec294f8
+  |   |   |-- 81
+  |   |   |   `-- b10fb4984d20142cd275b89c91c346e536876a
+  |   |   |-- a0
+  |   |   |   `-- 24003ee1acc6bf70318a46e7b6df651b9dc246
+  |   |   |-- ba
+  |   |   |   `-- 7e17233d9f79c96cb694959eb065302acd96a6
+  |   |   |-- bb
+  |   |   |   `-- 282e9cdc1b972fffd08fd21eead43bc0c83cb8
+  |   |   |-- c6
+  |   |   |   `-- 27a2e3a6bfbb7307f522ad94fdfc8c20b92967
+  |   |   |-- c8
+  |   |   |   `-- 2fc150c43f13cc56c0e9caeba01b58ec612022
+  |   |   |-- info
+  |   |   `-- pack
+  |   `-- refs
+  |       |-- heads
+  |       |-- josh
+  |       |   `-- upstream
+  |       |       |-- mirror%2Frepo2.git
+  |       |       |   |-- HEAD
+  |       |       |   `-- refs
+  |       |       |       `-- heads
+  |       |       |           `-- master
+  |       |       `-- real_repo.git
+  |       |           |-- HEAD
+  |       |           `-- refs
+  |       |               `-- heads
+  |       |                   `-- master
+  |       `-- tags
+  `-- overlay
+      |-- HEAD
+      |-- config
+      |-- description
+      |-- info
+      |   `-- exclude
+      |-- objects
+      |   |-- 0b
+      |   |   `-- 4cf6c9efbbda1eada39fa9c1d21d2525b027bb
+      |   |-- d8
+      |   |   `-- 388f5880393d255b371f1ed9b801d35620017e
+      |   |-- info
+      |   `-- pack
+      `-- refs
+          |-- heads
+          |-- namespaces
+          `-- tags
+  
+  46 directories, 31 files
@@ -67,7 +67,7 @@ Error: comment in the middle
   remote: 6 | # comment 2        
   remote:   | ^---        
   remote:   |        
-  remote:   = expected EOI, filter_spec, or dst_path        
+  remote:   = expected EOI, filter_spec, let_keyword, or dst_path        
   remote: 
   remote: # comment        
   remote: #        
@@ -106,7 +106,7 @@ Error in filter
   remote: 1 | a/b = :b/sub2        
   remote:   |         ^---        
   remote:   |        
-  remote:   = expected EOI, filter_group, filter_subdir, filter_nop, filter_presub, filter, filter_noarg, filter_message, filter_rev, filter_join, filter_replace, or filter_squash        
+  remote:   = expected EOI, filter_group, filter_named, filter_subdir, filter_nop, filter_presub, filter, filter_noarg, filter_message, filter_rev, filter_join, filter_replace, filter_message_replace, filter_mailmap, or filter_squash        
   remote: 
   remote: a/b = :b/sub2        
   remote: c = :/sub1        